@@ -19,6 +19,14 @@ pub struct Uris {
     pub uris: Vec<String>,
 }
 
+#[derive(Serialize)]
+pub struct UrisAt {
+    pub uris: Vec<String>,
+    // Where to insert them (appended if missing)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<usize>,
+}
+
 #[derive(Serialize)]
 pub struct PlayOffset {
     pub position: u32,
@@ -37,6 +45,12 @@ pub enum PlayRequest {
     },
 }
 
+#[derive(Serialize)]
+pub struct TransferRequest {
+    pub device_ids: Vec<String>,
+    pub play: bool,
+}
+
 #[derive(Serialize)]
 pub struct Ids {
     pub ids: Vec<String>,
@@ -50,6 +64,7 @@ pub struct Name<'a> {
 pub enum SearchType {
     Artist,
     Album,
+    Track,
 }
 
 impl SearchType {
@@ -57,6 +72,7 @@ impl SearchType {
         match self {
             Self::Artist => "artist",
             Self::Album => "album",
+            Self::Track => "track",
         }
     }
 }
@@ -296,7 +312,7 @@ pub struct Device {
     pub id: String,
     pub is_active: bool,
     pub is_restricted: bool,
-    pub volume_percent: u32,
+    pub volume_percent: Option<u32>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -343,18 +359,25 @@ impl From<PlayerState> for ConnectPlayerState {
             "context" => RepeatMode::Playlist,
             _ => RepeatMode::None,
         };
-        let source = context.and_then(|PlayerContext { type_, uri }| match type_.as_str() {
-            "album" => {
-                let id = uri.split(':').last().unwrap_or_default();
-                Some(SongsSource::Album(id.to_string()))
+        let source = context.and_then(|PlayerContext { type_, uri }| {
+            // Playlist URIs may still come in the legacy spotify:user:<user>:playlist:<id> form
+            let id = uri.split(':').last().unwrap_or_default().to_string();
+            match type_.as_str() {
+                "album" => Some(SongsSource::Album(id)),
+                "playlist" => Some(SongsSource::Playlist(id)),
+                "artist" => Some(SongsSource::Artist(id)),
+                "collection" => Some(SongsSource::SavedTracks),
+                _ => None,
             }
-            _ => None,
         });
         let shuffle = shuffle_state;
-        let current_song_id = item.get().map(|i| i.track.id);
+        let track = item.get().map(|i| i.track);
+        let duration_ms = track.as_ref().map(|t| t.duration_ms as u32).unwrap_or(0);
+        let current_song_id = track.map(|t| t.id);
         Self {
             is_playing,
             progress_ms,
+            duration_ms,
             repeat,
             shuffle,
             source,
@@ -372,10 +395,39 @@ pub struct TopTracks {
 pub struct AlbumTrackItem {
     pub id: String,
     pub track_number: Option<usize>,
+    pub disc_number: Option<usize>,
     pub uri: String,
     pub name: String,
     pub duration_ms: i64,
     pub artists: Vec<Artist>,
+    // Only present when a market is specified
+    pub is_playable: Option<bool>,
+    // Set when the track was relinked to another version, playable in that market
+    pub linked_from: Option<LinkedTrack>,
+    pub restrictions: Option<Restrictions>,
+}
+
+// The track that was originally asked for (and that is in the playlist, for instance)
+#[derive(Deserialize, Debug, Clone)]
+pub struct LinkedTrack {
+    pub id: String,
+    pub uri: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Restrictions {
+    pub reason: Option<String>,
+}
+
+impl Restrictions {
+    fn restriction(&self) -> Option<Restriction> {
+        match self.reason.as_deref()? {
+            "market" => Some(Restriction::Market),
+            "product" => Some(Restriction::Product),
+            "explicit" => Some(Restriction::Explicit),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -408,6 +460,7 @@ impl FailibleTrackItem {
 pub struct RawSearchResults {
     pub albums: Option<Page<Album>>,
     pub artists: Option<Page<Artist>>,
+    pub tracks: Option<Page<TrackItem>>,
 }
 
 impl From<Artist> for ArtistSummary {
@@ -481,6 +534,68 @@ impl From<(Page<AlbumTrackItem>, &Album)> for SongBatch {
     }
 }
 
+impl From<TrackItem> for SongDescription {
+    fn from(TrackItem { track, album }: TrackItem) -> Self {
+        let AlbumTrackItem {
+            artists,
+            id,
+            uri,
+            name,
+            duration_ms,
+            track_number,
+            disc_number,
+            is_playable,
+            linked_from,
+            restrictions,
+        } = track;
+        // A relinked track is still known by the original one, which is what playlists contain
+        // (and what librespot relinks by itself)
+        let (id, uri) = match linked_from {
+            Some(LinkedTrack { id, uri }) => (id, uri),
+            None => (id, uri),
+        };
+        let restriction = restrictions.and_then(|r| r.restriction());
+        let to_refs = |artists: Vec<Artist>| {
+            artists
+                .into_iter()
+                .map(|a| ArtistRef {
+                    id: a.id,
+                    name: a.name,
+                })
+                .collect::<Vec<ArtistRef>>()
+        };
+        let artists = to_refs(artists);
+
+        let art = album.best_image_for_width(200).map(|i| &i.url).cloned();
+        let Album {
+            id: album_id,
+            name: album_name,
+            artists: album_artists,
+            ..
+        } = album;
+
+        let album_ref = AlbumRef {
+            id: album_id,
+            name: album_name,
+            artists: to_refs(album_artists),
+        };
+
+        SongDescription {
+            id,
+            track_number: track_number.map(|u| u as u32),
+            disc_number: disc_number.map(|u| u as u32),
+            uri,
+            title: name,
+            artists,
+            album: album_ref,
+            duration: duration_ms as u32,
+            art,
+            is_playable: is_playable.unwrap_or(true),
+            restriction,
+        }
+    }
+}
+
 impl<T> From<Page<T>> for SongBatch
 where
     T: TryInto<TrackItem>,
@@ -493,47 +608,8 @@ where
         };
         let songs = page
             .into_iter()
-            .filter_map(|t| {
-                let TrackItem { track, album } = t.try_into().ok()?;
-                let AlbumTrackItem {
-                    artists,
-                    id,
-                    uri,
-                    name,
-                    duration_ms,
-                    track_number,
-                } = track;
-                let artists = artists
-                    .into_iter()
-                    .map(|a| ArtistRef {
-                        id: a.id,
-                        name: a.name,
-                    })
-                    .collect::<Vec<ArtistRef>>();
-
-                let art = album.best_image_for_width(200).map(|i| &i.url).cloned();
-                let Album {
-                    id: album_id,
-                    name: album_name,
-                    ..
-                } = album;
-
-                let album_ref = AlbumRef {
-                    id: album_id,
-                    name: album_name,
-                };
-
-                Some(SongDescription {
-                    id,
-                    track_number: track_number.map(|u| u as u32),
-                    uri,
-                    title: name,
-                    artists,
-                    album: album_ref,
-                    duration: duration_ms as u32,
-                    art,
-                })
-            })
+            .filter_map(|t| t.try_into().ok())
+            .map(|t: TrackItem| t.into())
             .collect();
         SongBatch { songs, batch }
     }
@@ -640,7 +716,12 @@ impl From<Playlist> for PlaylistDescription {
 impl From<Device> for ConnectDevice {
     fn from(
         Device {
-            id, name, type_, ..
+            id,
+            name,
+            type_,
+            is_active,
+            is_restricted,
+            volume_percent,
         }: Device,
     ) -> Self {
         let kind = match type_.to_lowercase().as_str() {
@@ -653,6 +734,9 @@ impl From<Device> for ConnectDevice {
             id,
             label: name,
             kind,
+            volume: volume_percent.map(|volume| volume.min(100) as u8),
+            is_active,
+            is_restricted,
         }
     }
 }
@@ -678,6 +762,45 @@ mod tests {
         assert!(track_item.is_none());
     }
 
+    #[test]
+    fn test_device() {
+        let device = r#"{"id":"d","name":"Speaker","type":"Speaker","is_active":true,"is_restricted":true,"volume_percent":null}"#;
+        let deserialized: Device = serde_json::from_str(device).unwrap();
+        let device = ConnectDevice::from(deserialized);
+        assert!(matches!(device.kind, ConnectDeviceKind::Speaker));
+        assert!(device.is_active && device.is_restricted);
+        assert_eq!(device.volume, None);
+    }
+
+    fn player_state_with_context(context: &str) -> ConnectPlayerState {
+        let state = format!(
+            r#"{{"progress_ms":0,"is_playing":true,"repeat_state":"off","shuffle_state":false,"item":{{"name":""}},"context":{}}}"#,
+            context
+        );
+        let deserialized: PlayerState = serde_json::from_str(&state).unwrap();
+        deserialized.into()
+    }
+
+    #[test]
+    fn test_player_state_contexts() {
+        let state = player_state_with_context(r#"{"type":"album","uri":"spotify:album:a"}"#);
+        assert_eq!(state.source, Some(SongsSource::Album("a".to_string())));
+
+        let state =
+            player_state_with_context(r#"{"type":"playlist","uri":"spotify:user:u:playlist:p"}"#);
+        assert_eq!(state.source, Some(SongsSource::Playlist("p".to_string())));
+
+        let state = player_state_with_context(r#"{"type":"artist","uri":"spotify:artist:b"}"#);
+        assert_eq!(state.source, Some(SongsSource::Artist("b".to_string())));
+
+        let state =
+            player_state_with_context(r#"{"type":"collection","uri":"spotify:user:u:collection"}"#);
+        assert_eq!(state.source, Some(SongsSource::SavedTracks));
+
+        let state = player_state_with_context("null");
+        assert_eq!(state.source, None);
+    }
+
     #[test]
     fn test_playlist_track_ok() {
         let track = r#"{"is_local":false,"track":{"album":{"artists":[{"external_urls":{"spotify":""},"href":"","id":"","name":"","type":"artist","uri":""}],"id":"","images":[{"height":64,"url":"","width":64}],"name":""},"artists":[{"id":"","name":""}],"duration_ms":1,"id":"","name":"","uri":""}}"#;
@@ -685,4 +808,25 @@ mod tests {
         let track_item: Option<TrackItem> = deserialized.try_into().ok();
         assert!(track_item.is_some());
     }
+
+    #[test]
+    fn test_playlist_track_relinked() {
+        let track = r#"{"is_local":false,"track":{"album":{"artists":[],"id":"","images":[],"name":""},"artists":[],"duration_ms":1,"id":"new","name":"","uri":"spotify:track:new","is_playable":true,"linked_from":{"id":"old","uri":"spotify:track:old"}}}"#;
+        let deserialized: PlaylistTrack = serde_json::from_str(track).unwrap();
+        let track_item: TrackItem = deserialized.try_into().unwrap();
+        let song = SongDescription::from(track_item);
+        assert_eq!(song.id, "old");
+        assert_eq!(song.uri, "spotify:track:old");
+        assert!(song.is_playable);
+    }
+
+    #[test]
+    fn test_playlist_track_restricted() {
+        let track = r#"{"is_local":false,"track":{"album":{"artists":[],"id":"","images":[],"name":""},"artists":[],"duration_ms":1,"id":"a","name":"","uri":"","is_playable":false,"restrictions":{"reason":"market"}}}"#;
+        let deserialized: PlaylistTrack = serde_json::from_str(track).unwrap();
+        let track_item: TrackItem = deserialized.try_into().unwrap();
+        let song = SongDescription::from(track_item);
+        assert!(!song.is_playable);
+        assert_eq!(song.restriction, Some(Restriction::Market));
+    }
 }
@@ -97,6 +97,10 @@ impl CacheManager {
         self.root.join(resource)
     }
 
+    pub fn path_for(&self, resource: &str) -> PathBuf {
+        self.cache_path(resource)
+    }
+
     fn cache_meta_path(&self, resource: &str) -> PathBuf {
         let full = resource.to_string() + EXPIRY_FILE_EXT;
         self.root.join(full)
@@ -12,11 +12,20 @@ use crate::app::models::*;
 
 pub type SpotifyResult<T> = Result<T, SpotifyApiError>;
 
+// The result of a request made with the etag of a previous response
+#[derive(Debug)]
+pub enum Conditional<T> {
+    Changed { value: T, etag: Option<String> },
+    Unchanged,
+}
+
 pub trait SpotifyApiClient {
     fn get_artist(&self, id: &str) -> BoxFuture<SpotifyResult<ArtistDescription>>;
 
     fn get_album(&self, id: &str) -> BoxFuture<SpotifyResult<AlbumFullDescription>>;
 
+    fn get_track(&self, id: &str) -> BoxFuture<SpotifyResult<SongDescription>>;
+
     fn get_album_tracks(
         &self,
         id: &str,
@@ -45,6 +54,8 @@ pub trait SpotifyApiClient {
 
     fn save_tracks(&self, ids: Vec<String>) -> BoxFuture<SpotifyResult<()>>;
 
+    fn is_track_saved(&self, id: &str) -> BoxFuture<SpotifyResult<bool>>;
+
     fn remove_saved_album(&self, id: &str) -> BoxFuture<SpotifyResult<()>>;
 
     fn remove_saved_tracks(&self, ids: Vec<String>) -> BoxFuture<SpotifyResult<()>>;
@@ -55,7 +66,13 @@ pub trait SpotifyApiClient {
         limit: usize,
     ) -> BoxFuture<SpotifyResult<Vec<PlaylistDescription>>>;
 
-    fn add_to_playlist(&self, id: &str, uris: Vec<String>) -> BoxFuture<SpotifyResult<()>>;
+    // Tracks are appended, unless a position is given
+    fn add_to_playlist(
+        &self,
+        id: &str,
+        uris: Vec<String>,
+        position: Option<usize>,
+    ) -> BoxFuture<SpotifyResult<()>>;
 
     fn create_new_playlist(
         &self,
@@ -74,6 +91,13 @@ pub trait SpotifyApiClient {
         limit: usize,
     ) -> BoxFuture<SpotifyResult<SearchResults>>;
 
+    fn search_tracks(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+    ) -> BoxFuture<SpotifyResult<Vec<SongDescription>>>;
+
     fn get_artist_albums(
         &self,
         id: &str,
@@ -104,12 +128,16 @@ pub trait SpotifyApiClient {
 
     fn player_seek(&self, device_id: String, pos: usize) -> BoxFuture<SpotifyResult<()>>;
 
+    fn player_add_to_queue(&self, device_id: String, uri: String) -> BoxFuture<SpotifyResult<()>>;
+
     fn player_repeat(&self, device_id: String, mode: RepeatMode) -> BoxFuture<SpotifyResult<()>>;
 
     fn player_shuffle(&self, device_id: String, shuffle: bool) -> BoxFuture<SpotifyResult<()>>;
 
     fn player_volume(&self, device_id: String, volume: u8) -> BoxFuture<SpotifyResult<()>>;
 
+    fn player_transfer(&self, device_id: String, play: bool) -> BoxFuture<SpotifyResult<()>>;
+
     fn player_play_in_context(
         &self,
         device_id: String,
@@ -124,7 +152,10 @@ pub trait SpotifyApiClient {
         offset: usize,
     ) -> BoxFuture<SpotifyResult<()>>;
 
-    fn player_state(&self) -> BoxFuture<SpotifyResult<ConnectPlayerState>>;
+    fn player_state(
+        &self,
+        etag: Option<String>,
+    ) -> BoxFuture<SpotifyResult<Conditional<ConnectPlayerState>>>;
 }
 
 enum SpotCacheKey<'a> {
@@ -334,7 +365,12 @@ impl SpotifyApiClient for CachedSpotifyClient {
         })
     }
 
-    fn add_to_playlist(&self, id: &str, uris: Vec<String>) -> BoxFuture<SpotifyResult<()>> {
+    fn add_to_playlist(
+        &self,
+        id: &str,
+        uris: Vec<String>,
+        position: Option<usize>,
+    ) -> BoxFuture<SpotifyResult<()>> {
         let id = id.to_owned();
 
         Box::pin(async move {
@@ -344,7 +380,7 @@ impl SpotifyApiClient for CachedSpotifyClient {
                 .unwrap_or(());
 
             self.client
-                .add_to_playlist(&id, uris)
+                .add_to_playlist(&id, uris, position)
                 .send_no_response()
                 .await?;
             Ok(())
@@ -407,6 +443,21 @@ impl SpotifyApiClient for CachedSpotifyClient {
         })
     }
 
+    fn get_track(&self, id: &str) -> BoxFuture<SpotifyResult<SongDescription>> {
+        let id = id.to_owned();
+
+        Box::pin(async move {
+            let track: TrackItem = self
+                .client
+                .get_track(&id)
+                .send()
+                .await?
+                .deserialize()
+                .ok_or(SpotifyApiError::NoContent)?;
+            Ok(SongDescription::from(track))
+        })
+    }
+
     fn get_album(&self, id: &str) -> BoxFuture<SpotifyResult<AlbumFullDescription>> {
         let id = id.to_owned();
 
@@ -452,6 +503,21 @@ impl SpotifyApiClient for CachedSpotifyClient {
         })
     }
 
+    fn is_track_saved(&self, id: &str) -> BoxFuture<SpotifyResult<bool>> {
+        let id = id.to_owned();
+
+        Box::pin(async move {
+            let saved: Vec<bool> = self
+                .client
+                .is_track_saved(&id)
+                .send()
+                .await?
+                .deserialize()
+                .ok_or(SpotifyApiError::NoContent)?;
+            Ok(saved.first().copied().unwrap_or(false))
+        })
+    }
+
     fn remove_saved_album(&self, id: &str) -> BoxFuture<SpotifyResult<()>> {
         let id = id.to_owned();
 
@@ -612,7 +678,12 @@ impl SpotifyApiClient for CachedSpotifyClient {
         Box::pin(async move {
             let results = self
                 .client
-                .search(query, offset, limit)
+                .search(
+                    query,
+                    vec![SearchType::Album, SearchType::Artist],
+                    offset,
+                    limit,
+                )
                 .send()
                 .await?
                 .deserialize()
@@ -636,6 +707,30 @@ impl SpotifyApiClient for CachedSpotifyClient {
         })
     }
 
+    fn search_tracks(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+    ) -> BoxFuture<SpotifyResult<Vec<SongDescription>>> {
+        let query = query.to_owned();
+
+        Box::pin(async move {
+            let results = self
+                .client
+                .search(query, vec![SearchType::Track], offset, limit)
+                .send()
+                .await?
+                .deserialize()
+                .ok_or(SpotifyApiError::NoContent)?;
+
+            Ok(results
+                .tracks
+                .map(|page| SongBatch::from(page).songs)
+                .unwrap_or_default())
+        })
+    }
+
     fn get_user_playlists(
         &self,
         id: &str,
@@ -701,10 +796,7 @@ impl SpotifyApiClient for CachedSpotifyClient {
             Ok(devices
                 .devices
                 .into_iter()
-                .filter(|d| {
-                    debug!("found device: {:?}", d);
-                    !d.is_restricted
-                })
+                .inspect(|d| debug!("found device: {:?}", d))
                 .map(ConnectDevice::from)
                 .collect())
         })
@@ -781,16 +873,28 @@ impl SpotifyApiClient for CachedSpotifyClient {
         Box::pin(self.client.player_seek(&device_id, pos).send_no_response())
     }
 
-    fn player_state(&self) -> BoxFuture<SpotifyResult<ConnectPlayerState>> {
+    fn player_add_to_queue(&self, device_id: String, uri: String) -> BoxFuture<SpotifyResult<()>> {
+        Box::pin(
+            self.client
+                .player_add_to_queue(&device_id, &uri)
+                .send_no_response(),
+        )
+    }
+
+    fn player_state(
+        &self,
+        etag: Option<String>,
+    ) -> BoxFuture<SpotifyResult<Conditional<ConnectPlayerState>>> {
         Box::pin(async move {
-            let result = self
-                .client
-                .player_state()
-                .send()
-                .await?
-                .deserialize()
-                .ok_or(SpotifyApiError::NoContent)?;
-            Ok(result.into())
+            let response = self.client.player_state().etag(etag).send().await?;
+            if let SpotifyResponseKind::NotModified = response.kind {
+                return Ok(Conditional::Unchanged);
+            }
+            let state: PlayerState = response.deserialize().ok_or(SpotifyApiError::NoContent)?;
+            Ok(Conditional::Changed {
+                value: state.into(),
+                etag: response.etag,
+            })
         })
     }
 
@@ -824,6 +928,14 @@ impl SpotifyApiClient for CachedSpotifyClient {
                 .send_no_response(),
         )
     }
+
+    fn player_transfer(&self, device_id: String, play: bool) -> BoxFuture<SpotifyResult<()>> {
+        Box::pin(
+            self.client
+                .player_transfer(&device_id, play)
+                .send_no_response(),
+        )
+    }
 }
 
 #[cfg(test)]
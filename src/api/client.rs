@@ -1,5 +1,4 @@
 use form_urlencoded::Serializer;
-use isahc::config::Configurable;
 use isahc::http::{method::Method, request::Builder, StatusCode, Uri};
 use isahc::{AsyncReadResponseExt, HttpClient, Request};
 use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
@@ -13,6 +12,7 @@ use thiserror::Error;
 
 pub use super::api_models::*;
 use super::cache::CacheError;
+use super::http::make_http_client;
 
 const SPOTIFY_HOST: &str = "api.spotify.com";
 
@@ -155,7 +155,8 @@ pub enum SpotifyApiError {
     #[error("No content from request")]
     NoContent,
     #[error("Request rate exceeded")]
-    TooManyRequests,
+    // How many seconds to wait before trying again, if the server said so
+    TooManyRequests(Option<u64>),
     #[error("Request failed ({0}): {1}")]
     BadStatus(u16, String),
     #[error(transparent)]
@@ -172,22 +173,26 @@ pub enum SpotifyApiError {
 
 pub(crate) struct SpotifyClient {
     token: Mutex<Option<String>>,
-    client: HttpClient,
+    // Created on first use, once the proxy is known (see set_http_proxy)
+    client: Mutex<Option<HttpClient>>,
 }
 
 impl SpotifyClient {
     pub(crate) fn new() -> Self {
-        let mut builder = HttpClient::builder();
-        if cfg!(debug_assertions) {
-            builder = builder.ssl_options(isahc::config::SslOption::DANGER_ACCEPT_INVALID_CERTS);
-        }
-        let client = builder.build().unwrap();
         Self {
             token: Mutex::new(None),
-            client,
+            client: Mutex::new(None),
         }
     }
 
+    fn http_client(&self) -> HttpClient {
+        self.client
+            .lock()
+            .unwrap()
+            .get_or_insert_with(make_http_client)
+            .clone()
+    }
+
     pub(crate) fn request<T>(&self) -> SpotifyRequest<'_, (), T> {
         SpotifyRequest {
             client: self,
@@ -221,6 +226,14 @@ impl SpotifyClient {
             .and_then(|s| u64::from_str(s).ok())
     }
 
+    fn parse_retry_after<T>(response: &isahc::Response<T>) -> Option<u64> {
+        response
+            .headers()
+            .get("retry-after")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|s| u64::from_str(s.trim()).ok())
+    }
+
     async fn send_req<B, T>(
         &self,
         request: Request<B>,
@@ -228,7 +241,7 @@ impl SpotifyClient {
     where
         B: Into<isahc::AsyncBody>,
     {
-        let mut result = self.client.send_async(request).await?;
+        let mut result = self.http_client().send_async(request).await?;
 
         let etag = result
             .headers()
@@ -242,6 +255,8 @@ impl SpotifyClient {
             .and_then(|header| header.to_str().ok())
             .and_then(Self::parse_cache_control);
 
+        let retry_after = Self::parse_retry_after(&result);
+
         match result.status() {
             StatusCode::NO_CONTENT => Err(SpotifyApiError::NoContent),
             s if s.is_success() => Ok(SpotifyResponse {
@@ -253,7 +268,7 @@ impl SpotifyClient {
                 self.clear_token();
                 Err(SpotifyApiError::InvalidToken)
             }
-            StatusCode::TOO_MANY_REQUESTS => Err(SpotifyApiError::TooManyRequests),
+            StatusCode::TOO_MANY_REQUESTS => Err(SpotifyApiError::TooManyRequests(retry_after)),
             StatusCode::NOT_MODIFIED => Ok(SpotifyResponse {
                 kind: SpotifyResponseKind::NotModified,
                 max_age: cache_control.unwrap_or(10),
@@ -273,13 +288,15 @@ impl SpotifyClient {
     where
         B: Into<isahc::AsyncBody>,
     {
-        let mut result = self.client.send_async(request).await?;
+        let mut result = self.http_client().send_async(request).await?;
         match result.status() {
             StatusCode::UNAUTHORIZED => {
                 self.clear_token();
                 Err(SpotifyApiError::InvalidToken)
             }
-            StatusCode::TOO_MANY_REQUESTS => Err(SpotifyApiError::TooManyRequests),
+            StatusCode::TOO_MANY_REQUESTS => Err(SpotifyApiError::TooManyRequests(
+                Self::parse_retry_after(&result),
+            )),
             StatusCode::NOT_MODIFIED => Ok(()),
             s if s.is_success() => Ok(()),
             s => Err(SpotifyApiError::BadStatus(
@@ -328,6 +345,16 @@ impl SpotifyClient {
             .uri(format!("/v1/artists/{id}/top-tracks"), Some(&query))
     }
 
+    pub(crate) fn get_track(&self, id: &str) -> SpotifyRequest<'_, (), TrackItem> {
+        let query = make_query_params()
+            .append_pair("market", "from_token")
+            .finish();
+
+        self.request()
+            .method(Method::GET)
+            .uri(format!("/v1/tracks/{id}"), Some(&query))
+    }
+
     pub(crate) fn is_album_saved(&self, id: &str) -> SpotifyRequest<'_, (), Vec<bool>> {
         let query = make_query_params().append_pair("ids", id).finish();
         self.request()
@@ -335,6 +362,13 @@ impl SpotifyClient {
             .uri("/v1/me/albums/contains".to_string(), Some(&query))
     }
 
+    pub(crate) fn is_track_saved(&self, id: &str) -> SpotifyRequest<'_, (), Vec<bool>> {
+        let query = make_query_params().append_pair("ids", id).finish();
+        self.request()
+            .method(Method::GET)
+            .uri("/v1/me/tracks/contains".to_string(), Some(&query))
+    }
+
     pub(crate) fn save_album(&self, id: &str) -> SpotifyRequest<'_, (), ()> {
         let query = make_query_params().append_pair("ids", id).finish();
         self.request()
@@ -364,9 +398,13 @@ impl SpotifyClient {
     }
 
     pub(crate) fn get_album(&self, id: &str) -> SpotifyRequest<'_, (), FullAlbum> {
+        let query = make_query_params()
+            .append_pair("market", "from_token")
+            .finish();
+
         self.request()
             .method(Method::GET)
-            .uri(format!("/v1/albums/{id}"), None)
+            .uri(format!("/v1/albums/{id}"), Some(&query))
     }
 
     pub(crate) fn get_album_tracks(
@@ -376,6 +414,7 @@ impl SpotifyClient {
         limit: usize,
     ) -> SpotifyRequest<'_, (), Page<AlbumTrackItem>> {
         let query = make_query_params()
+            .append_pair("market", "from_token")
             .append_pair("offset", &offset.to_string()[..])
             .append_pair("limit", &limit.to_string()[..])
             .finish();
@@ -389,8 +428,9 @@ impl SpotifyClient {
         let query = make_query_params()
             .append_pair(
                 "fields",
-                "id,name,images,owner,tracks(total,items(is_local,track(name,id,uri,duration_ms,artists(name,id),album(name,id,images,artists))))",
+                "id,name,images,owner,tracks(total,items(is_local,track(name,id,uri,duration_ms,is_playable,linked_from(id,uri),restrictions,artists(name,id),album(name,id,images,artists))))",
             )
+            .append_pair("market", "from_token")
             .finish();
         self.request()
             .method(Method::GET)
@@ -404,6 +444,7 @@ impl SpotifyClient {
         limit: usize,
     ) -> SpotifyRequest<'_, (), Page<PlaylistTrack>> {
         let query = make_query_params()
+            .append_pair("market", "from_token")
             .append_pair("offset", &offset.to_string()[..])
             .append_pair("limit", &limit.to_string()[..])
             .finish();
@@ -417,11 +458,12 @@ impl SpotifyClient {
         &self,
         playlist: &str,
         uris: Vec<String>,
+        position: Option<usize>,
     ) -> SpotifyRequest<'_, Vec<u8>, ()> {
         self.request()
             .method(Method::POST)
             .uri(format!("/v1/playlists/{playlist}/tracks"), None)
-            .json_body(Uris { uris })
+            .json_body(UrisAt { uris, position })
     }
 
     pub(crate) fn create_new_playlist(
@@ -478,6 +520,7 @@ impl SpotifyClient {
         limit: usize,
     ) -> SpotifyRequest<'_, (), Page<SavedTrack>> {
         let query = make_query_params()
+            .append_pair("market", "from_token")
             .append_pair("offset", &offset.to_string()[..])
             .append_pair("limit", &limit.to_string()[..])
             .finish();
@@ -505,12 +548,13 @@ impl SpotifyClient {
     pub(crate) fn search(
         &self,
         query: String,
+        types: Vec<SearchType>,
         offset: usize,
         limit: usize,
     ) -> SpotifyRequest<'_, (), RawSearchResults> {
         let query = SearchQuery {
             query,
-            types: vec![SearchType::Album, SearchType::Artist],
+            types,
             limit,
             offset,
         };
@@ -603,6 +647,20 @@ impl SpotifyClient {
             .uri("/v1/me/player/next".to_string(), Some(&query))
     }
 
+    pub(crate) fn player_add_to_queue(
+        &self,
+        device_id: &str,
+        uri: &str,
+    ) -> SpotifyRequest<'_, (), ()> {
+        let query = make_query_params()
+            .append_pair("uri", uri)
+            .append_pair("device_id", device_id)
+            .finish();
+        self.request()
+            .method(Method::POST)
+            .uri("/v1/me/player/queue".to_string(), Some(&query))
+    }
+
     pub(crate) fn player_seek(&self, device_id: &str, pos: usize) -> SpotifyRequest<'_, (), ()> {
         let query = make_query_params()
             .append_pair("device_id", device_id)
@@ -640,6 +698,20 @@ impl SpotifyClient {
             .uri("/v1/me/player/shuffle".to_string(), Some(&query))
     }
 
+    pub(crate) fn player_transfer(
+        &self,
+        device_id: &str,
+        play: bool,
+    ) -> SpotifyRequest<'_, Vec<u8>, ()> {
+        self.request()
+            .method(Method::PUT)
+            .uri("/v1/me/player".to_string(), None)
+            .json_body(TransferRequest {
+                device_ids: vec![device_id.to_string()],
+                play,
+            })
+    }
+
     pub(crate) fn player_volume(&self, device_id: &str, volume: u8) -> SpotifyRequest<'_, (), ()> {
         let query = make_query_params()
             .append_pair("device_id", device_id)
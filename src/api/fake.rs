@@ -0,0 +1,325 @@
+use futures::future::BoxFuture;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use super::cached_client::{Conditional, SpotifyApiClient, SpotifyResult};
+use super::client::SpotifyApiError;
+use crate::app::models::*;
+
+// A fake API client for tests: it serves canned data and records what was called
+#[derive(Default)]
+pub struct FakeSpotifyApiClient {
+    // Served by all paged sources of tracks (playlists, albums, saved tracks) and the player queue
+    pub songs: Mutex<Vec<SongDescription>>,
+    // Served in order by player_state (then defaults to Unchanged)
+    pub player_states: Mutex<VecDeque<SpotifyResult<Conditional<ConnectPlayerState>>>>,
+    calls: Mutex<Vec<String>>,
+}
+
+fn ok<T: Send + 'static>(value: T) -> BoxFuture<'static, SpotifyResult<T>> {
+    Box::pin(async move { Ok(value) })
+}
+
+fn not_faked<T: Send + 'static>() -> BoxFuture<'static, SpotifyResult<T>> {
+    Box::pin(async { Err(SpotifyApiError::NoContent) })
+}
+
+impl FakeSpotifyApiClient {
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn push_player_state(&self, state: ConnectPlayerState, etag: Option<&str>) {
+        self.player_states
+            .lock()
+            .unwrap()
+            .push_back(Ok(Conditional::Changed {
+                value: state,
+                etag: etag.map(|e| e.to_string()),
+            }));
+    }
+
+    fn record(&self, call: &str) {
+        self.calls.lock().unwrap().push(call.to_string());
+    }
+
+    fn song_batch(&self, offset: usize, limit: usize) -> SongBatch {
+        let songs = self.songs.lock().unwrap();
+        SongBatch {
+            songs: songs.iter().skip(offset).take(limit).cloned().collect(),
+            batch: Batch {
+                offset,
+                batch_size: limit,
+                total: songs.len(),
+            },
+        }
+    }
+}
+
+impl SpotifyApiClient for FakeSpotifyApiClient {
+    fn get_artist(&self, _id: &str) -> BoxFuture<SpotifyResult<ArtistDescription>> {
+        self.record("get_artist");
+        not_faked()
+    }
+
+    fn get_track(&self, id: &str) -> BoxFuture<SpotifyResult<SongDescription>> {
+        self.record("get_track");
+        let song = self
+            .songs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|song| song.id == id)
+            .cloned();
+        Box::pin(async move { song.ok_or(SpotifyApiError::NoContent) })
+    }
+
+    fn get_album(&self, _id: &str) -> BoxFuture<SpotifyResult<AlbumFullDescription>> {
+        self.record("get_album");
+        not_faked()
+    }
+
+    fn get_album_tracks(
+        &self,
+        _id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> BoxFuture<SpotifyResult<SongBatch>> {
+        self.record("get_album_tracks");
+        ok(self.song_batch(offset, limit))
+    }
+
+    fn get_playlist(&self, _id: &str) -> BoxFuture<SpotifyResult<PlaylistDescription>> {
+        self.record("get_playlist");
+        not_faked()
+    }
+
+    fn get_playlist_tracks(
+        &self,
+        _id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> BoxFuture<SpotifyResult<SongBatch>> {
+        self.record("get_playlist_tracks");
+        ok(self.song_batch(offset, limit))
+    }
+
+    fn get_saved_albums(
+        &self,
+        _offset: usize,
+        _limit: usize,
+    ) -> BoxFuture<SpotifyResult<Vec<AlbumDescription>>> {
+        self.record("get_saved_albums");
+        ok(vec![])
+    }
+
+    fn get_saved_tracks(&self, offset: usize, limit: usize) -> BoxFuture<SpotifyResult<SongBatch>> {
+        self.record("get_saved_tracks");
+        ok(self.song_batch(offset, limit))
+    }
+
+    fn save_album(&self, _id: &str) -> BoxFuture<SpotifyResult<AlbumDescription>> {
+        self.record("save_album");
+        not_faked()
+    }
+
+    fn save_tracks(&self, _ids: Vec<String>) -> BoxFuture<SpotifyResult<()>> {
+        self.record("save_tracks");
+        ok(())
+    }
+
+    fn is_track_saved(&self, _id: &str) -> BoxFuture<SpotifyResult<bool>> {
+        self.record("is_track_saved");
+        ok(false)
+    }
+
+    fn remove_saved_album(&self, _id: &str) -> BoxFuture<SpotifyResult<()>> {
+        self.record("remove_saved_album");
+        ok(())
+    }
+
+    fn remove_saved_tracks(&self, _ids: Vec<String>) -> BoxFuture<SpotifyResult<()>> {
+        self.record("remove_saved_tracks");
+        ok(())
+    }
+
+    fn get_saved_playlists(
+        &self,
+        _offset: usize,
+        _limit: usize,
+    ) -> BoxFuture<SpotifyResult<Vec<PlaylistDescription>>> {
+        self.record("get_saved_playlists");
+        ok(vec![])
+    }
+
+    fn add_to_playlist(
+        &self,
+        _id: &str,
+        _uris: Vec<String>,
+        _position: Option<usize>,
+    ) -> BoxFuture<SpotifyResult<()>> {
+        self.record("add_to_playlist");
+        ok(())
+    }
+
+    fn create_new_playlist(
+        &self,
+        _name: &str,
+        _user_id: &str,
+    ) -> BoxFuture<SpotifyResult<PlaylistDescription>> {
+        self.record("create_new_playlist");
+        not_faked()
+    }
+
+    fn remove_from_playlist(&self, _id: &str, _uris: Vec<String>) -> BoxFuture<SpotifyResult<()>> {
+        self.record("remove_from_playlist");
+        ok(())
+    }
+
+    fn update_playlist_details(&self, _id: &str, _name: String) -> BoxFuture<SpotifyResult<()>> {
+        self.record("update_playlist_details");
+        ok(())
+    }
+
+    fn search(
+        &self,
+        _query: &str,
+        _offset: usize,
+        _limit: usize,
+    ) -> BoxFuture<SpotifyResult<SearchResults>> {
+        self.record("search");
+        not_faked()
+    }
+
+    fn search_tracks(
+        &self,
+        _query: &str,
+        _offset: usize,
+        _limit: usize,
+    ) -> BoxFuture<SpotifyResult<Vec<SongDescription>>> {
+        self.record("search_tracks");
+        not_faked()
+    }
+
+    fn get_artist_albums(
+        &self,
+        _id: &str,
+        _offset: usize,
+        _limit: usize,
+    ) -> BoxFuture<SpotifyResult<Vec<AlbumDescription>>> {
+        self.record("get_artist_albums");
+        ok(vec![])
+    }
+
+    fn get_user(&self, _id: &str) -> BoxFuture<SpotifyResult<UserDescription>> {
+        self.record("get_user");
+        not_faked()
+    }
+
+    fn get_user_playlists(
+        &self,
+        _id: &str,
+        _offset: usize,
+        _limit: usize,
+    ) -> BoxFuture<SpotifyResult<Vec<PlaylistDescription>>> {
+        self.record("get_user_playlists");
+        ok(vec![])
+    }
+
+    fn list_available_devices(&self) -> BoxFuture<SpotifyResult<Vec<ConnectDevice>>> {
+        self.record("list_available_devices");
+        ok(vec![])
+    }
+
+    fn get_player_queue(&self) -> BoxFuture<SpotifyResult<Vec<SongDescription>>> {
+        self.record("get_player_queue");
+        ok(self.songs.lock().unwrap().clone())
+    }
+
+    fn update_token(&self, _token: String) {
+        self.record("update_token");
+    }
+
+    fn player_pause(&self, _device_id: String) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_pause");
+        ok(())
+    }
+
+    fn player_resume(&self, _device_id: String) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_resume");
+        ok(())
+    }
+
+    fn player_next(&self, _device_id: String) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_next");
+        ok(())
+    }
+
+    fn player_seek(&self, _device_id: String, _pos: usize) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_seek");
+        ok(())
+    }
+
+    fn player_add_to_queue(
+        &self,
+        _device_id: String,
+        _uri: String,
+    ) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_add_to_queue");
+        ok(())
+    }
+
+    fn player_repeat(&self, _device_id: String, _mode: RepeatMode) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_repeat");
+        ok(())
+    }
+
+    fn player_shuffle(&self, _device_id: String, _shuffle: bool) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_shuffle");
+        ok(())
+    }
+
+    fn player_volume(&self, _device_id: String, _volume: u8) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_volume");
+        ok(())
+    }
+
+    fn player_transfer(&self, _device_id: String, _play: bool) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_transfer");
+        ok(())
+    }
+
+    fn player_play_in_context(
+        &self,
+        _device_id: String,
+        _context: String,
+        _offset: usize,
+    ) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_play_in_context");
+        ok(())
+    }
+
+    fn player_play_no_context(
+        &self,
+        _device_id: String,
+        _uris: Vec<String>,
+        _offset: usize,
+    ) -> BoxFuture<SpotifyResult<()>> {
+        self.record("player_play_no_context");
+        ok(())
+    }
+
+    fn player_state(
+        &self,
+        _etag: Option<String>,
+    ) -> BoxFuture<SpotifyResult<Conditional<ConnectPlayerState>>> {
+        self.record("player_state");
+        let state = self
+            .player_states
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(Ok(Conditional::Unchanged));
+        Box::pin(async move { state })
+    }
+}
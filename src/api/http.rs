@@ -0,0 +1,53 @@
+use isahc::auth::{Authentication, Credentials};
+use isahc::config::Configurable;
+use isahc::http::Uri;
+use isahc::HttpClient;
+use std::sync::RwLock;
+
+use crate::settings::ResolvedProxy;
+
+struct HttpProxy {
+    uri: Uri,
+    credentials: Option<(String, String)>,
+}
+
+lazy_static! {
+    // Set whenever the proxy is resolved, first before logging in: the Web API client is only
+    // created once, so it keeps using that first proxy until the app is restarted
+    static ref HTTP_PROXY: RwLock<Option<HttpProxy>> = RwLock::new(None);
+}
+
+pub fn set_http_proxy(proxy: &ResolvedProxy) {
+    let http_proxy = proxy.http.as_ref().and_then(|uri| {
+        let uri = uri
+            .parse()
+            .map_err(|e| warn!("Invalid proxy url: {}", e))
+            .ok()?;
+        Some(HttpProxy {
+            uri,
+            credentials: proxy.credentials.clone(),
+        })
+    });
+    if let Ok(mut current) = HTTP_PROXY.write() {
+        *current = http_proxy;
+    }
+}
+
+// Any HTTP client we use should be created here, so that they all go through the configured proxy
+pub fn make_http_client() -> HttpClient {
+    let mut builder = HttpClient::builder();
+    if cfg!(debug_assertions) {
+        builder = builder.ssl_options(isahc::config::SslOption::DANGER_ACCEPT_INVALID_CERTS);
+    }
+    let proxy = HTTP_PROXY.read().unwrap();
+    if let Some(HttpProxy { uri, credentials }) = proxy.as_ref() {
+        debug!("using proxy {}", uri);
+        builder = builder.proxy(uri.clone());
+        if let Some((username, password)) = credentials {
+            builder = builder
+                .proxy_authentication(Authentication::basic())
+                .proxy_credentials(Credentials::new(username.as_str(), password.as_str()));
+        }
+    }
+    builder.build().unwrap()
+}
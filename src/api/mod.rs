@@ -1,11 +1,16 @@
 mod api_models;
 mod cached_client;
 mod client;
+mod http;
+
+#[cfg(test)]
+pub mod fake;
 
 pub mod cache;
 
-pub use cached_client::{CachedSpotifyClient, SpotifyApiClient, SpotifyResult};
+pub use cached_client::{CachedSpotifyClient, Conditional, SpotifyApiClient, SpotifyResult};
 pub use client::SpotifyApiError;
+pub use http::{make_http_client, set_http_proxy};
 
 pub async fn clear_user_cache() -> Option<()> {
     cache::CacheManager::for_dir("spot/net")?
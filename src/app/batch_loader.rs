@@ -1,7 +1,8 @@
 use gettextrs::gettext;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::api::{SpotifyApiClient, SpotifyApiError};
+use crate::api::{SpotifyApiClient, SpotifyApiError, SpotifyResult};
 use crate::app::models::*;
 use crate::app::AppAction;
 
@@ -12,11 +13,13 @@ pub struct BatchLoader {
 }
 
 // The sources mentionned above
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SongsSource {
     Playlist(String),
     Album(String),
     SavedTracks,
+    // The top tracks of an artist
+    Artist(String),
 }
 
 impl PartialEq for SongsSource {
@@ -25,6 +28,7 @@ impl PartialEq for SongsSource {
             (Self::Playlist(l), Self::Playlist(r)) => l == r,
             (Self::Album(l), Self::Album(r)) => l == r,
             (Self::SavedTracks, Self::SavedTracks) => true,
+            (Self::Artist(l), Self::Artist(r)) => l == r,
             _ => false,
         }
     }
@@ -47,7 +51,7 @@ impl SongsSource {
 }
 
 // How to query for a batch: specify a source, and a batch to get (offset + number of elements to get)
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BatchQuery {
     pub source: SongsSource,
     pub batch: Batch,
@@ -69,6 +73,33 @@ impl BatchLoader {
         Self { api }
     }
 
+    // Query a batch of songs
+    pub async fn get_batch(&self, query: &BatchQuery) -> SpotifyResult<SongBatch> {
+        let api = Arc::clone(&self.api);
+
+        let Batch {
+            offset, batch_size, ..
+        } = query.batch;
+        match &query.source {
+            SongsSource::Playlist(id) => api.get_playlist_tracks(id, offset, batch_size).await,
+            SongsSource::SavedTracks => api.get_saved_tracks(offset, batch_size).await,
+            SongsSource::Album(id) => api.get_album_tracks(id, offset, batch_size).await,
+            // Top tracks are few enough to fit in a single batch
+            SongsSource::Artist(id) => {
+                let songs = api.get_artist(id).await?.top_tracks;
+                let total = songs.len();
+                Ok(SongBatch {
+                    songs,
+                    batch: Batch {
+                        offset: 0,
+                        batch_size,
+                        total,
+                    },
+                })
+            }
+        }
+    }
+
     // Query a batch and create an action when it's been retrieved succesfully
     pub async fn query<ActionCreator>(
         &self,
@@ -78,16 +109,7 @@ impl BatchLoader {
     where
         ActionCreator: FnOnce(SongsSource, SongBatch) -> AppAction,
     {
-        let api = Arc::clone(&self.api);
-
-        let Batch {
-            offset, batch_size, ..
-        } = query.batch;
-        let result = match &query.source {
-            SongsSource::Playlist(id) => api.get_playlist_tracks(id, offset, batch_size).await,
-            SongsSource::SavedTracks => api.get_saved_tracks(offset, batch_size).await,
-            SongsSource::Album(id) => api.get_album_tracks(id, offset, batch_size).await,
-        };
+        let result = self.get_batch(&query).await;
 
         match result {
             Ok(batch) => Some(create_action(query.source, batch)),
@@ -117,6 +117,7 @@ impl PlaylistModel for ArtistDetailsModel {
         group.add_action(&song.make_album_action(self.dispatcher.box_clone(), None));
         group.add_action(&song.make_link_action(None));
         group.add_action(&song.make_queue_action(self.dispatcher.box_clone(), None));
+        group.add_action(&song.make_play_next_action(self.dispatcher.box_clone(), None));
 
         Some(group.upcast())
     }
@@ -135,6 +136,7 @@ impl PlaylistModel for ArtistDetailsModel {
         }
 
         menu.append(Some(&*labels::COPY_LINK), Some("song.copy_link"));
+        menu.append(Some(&*labels::PLAY_NEXT), Some("song.play_next"));
         menu.append(Some(&*labels::ADD_TO_QUEUE), Some("song.queue"));
         Some(menu.upcast())
     }
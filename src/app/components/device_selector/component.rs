@@ -1,8 +1,11 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::rc::Rc;
 
 use glib::Cast;
 
+use crate::app::components::utils::{Clock, Debouncer};
 use crate::app::components::{Component, EventListener};
 use crate::app::models::ConnectDevice;
 use crate::app::state::{Device, LoginEvent, PlaybackAction, PlaybackEvent};
@@ -10,6 +13,10 @@ use crate::app::{ActionDispatcher, AppEvent, AppModel};
 
 use super::widget::DeviceSelectorWidget;
 
+// How often the list of devices is refreshed while it's shown
+const REFRESH_INTERVAL_MS: u32 = 5000;
+const VOLUME_DEBOUNCE_MS: u32 = 200;
+
 pub struct DeviceSelectorModel {
     app_model: Rc<AppModel>,
     dispatcher: Box<dyn ActionDispatcher>,
@@ -34,6 +41,15 @@ impl DeviceSelectorModel {
             });
     }
 
+    pub fn set_device_volume(&self, id: String, volume: u8) {
+        let api = self.app_model.get_spotify();
+
+        self.dispatcher
+            .call_spotify_and_dispatch_many(move || async move {
+                api.player_volume(id, volume).await.map(|_| vec![])
+            });
+    }
+
     pub fn get_available_devices(&self) -> impl Deref<Target = Vec<ConnectDevice>> + '_ {
         self.app_model.map_state(|s| s.playback.available_devices())
     }
@@ -70,6 +86,28 @@ impl DeviceSelector {
             model.set_current_device(id);
         }));
 
+        // One per device, so that moving a slider doesn't cancel the change made with another one
+        let debouncers: RefCell<HashMap<String, Debouncer>> = Default::default();
+        widget.connect_volume_changed(clone!(@weak model => move |id, volume| {
+            let mut debouncers = debouncers.borrow_mut();
+            let debouncer = debouncers.entry(id.clone()).or_insert_with(Debouncer::new);
+            debouncer.debounce(VOLUME_DEBOUNCE_MS, clone!(@weak model => move || {
+                model.set_device_volume(id.clone(), volume);
+            }));
+        }));
+
+        let refresh_clock = Clock::new(REFRESH_INTERVAL_MS);
+        widget.connect_popover_visible(clone!(@weak model => move |visible| {
+            if visible {
+                model.refresh_available_devices();
+                refresh_clock.start(clone!(@weak model => move || {
+                    model.refresh_available_devices();
+                }));
+            } else {
+                refresh_clock.stop();
+            }
+        }));
+
         Self { widget, model }
     }
 }
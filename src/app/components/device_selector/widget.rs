@@ -1,15 +1,44 @@
 use crate::app::models::{ConnectDevice, ConnectDeviceKind};
 use crate::app::state::Device;
+use crate::settings;
 use gettextrs::gettext;
 use gio::{Action, SimpleAction, SimpleActionGroup};
 use glib::FromVariant;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::CompositeTemplate;
+use std::cell::RefCell;
 
 const ACTIONS: &str = "devices";
 const CONNECT_ACTION: &str = "connect";
 const REFRESH_ACTION: &str = "refresh";
+const VOLUME_ACTION: &str = "volume";
+
+// The widgets showing a device in the list
+#[derive(Debug)]
+struct DeviceRow {
+    device: ConnectDevice,
+    active_icon: gtk::Image,
+    volume: gtk::Scale,
+}
+
+impl DeviceRow {
+    // Whether the row can be updated to show that device, rather than rebuilt
+    fn shows(&self, device: &ConnectDevice) -> bool {
+        self.device.id == device.id
+            && self.device.label == device.label
+            && self.device.is_restricted == device.is_restricted
+            && self.device.volume.is_some() == device.volume.is_some()
+    }
+
+    fn update(&mut self, device: &ConnectDevice) {
+        self.active_icon.set_visible(device.is_active);
+        if let Some(volume) = device.volume {
+            self.volume.set_value(volume.into());
+        }
+        self.device = device.clone();
+    }
+}
 
 mod imp {
 
@@ -37,6 +66,8 @@ mod imp {
         pub menu: TemplateChild<gio::MenuModel>,
 
         pub action_group: SimpleActionGroup,
+
+        pub(super) rows: RefCell<Vec<DeviceRow>>,
     }
 
     #[glib::object_subclass]
@@ -68,6 +99,8 @@ mod imp {
             this_device.set_action_name(Some(&format!("{}.{}", ACTIONS, CONNECT_ACTION)));
             this_device.set_action_target_value(Some(&Option::<String>::None.to_variant()));
 
+            self.action_group
+                .add_action(&settings::connect_keep_playing_action());
             self.obj()
                 .insert_action_group(ACTIONS, Some(&self.action_group));
             self.obj()
@@ -103,6 +136,34 @@ impl DeviceSelectorWidget {
         });
     }
 
+    pub fn connect_popover_visible<F>(&self, f: F)
+    where
+        F: Fn(bool) + 'static,
+    {
+        self.imp()
+            .popover
+            .connect_visible_notify(move |popover| f(popover.is_visible()));
+    }
+
+    pub fn connect_volume_changed<F>(&self, f: F)
+    where
+        F: Fn(String, u8) + 'static,
+    {
+        self.imp().action_group.add_action(&{
+            let volume = SimpleAction::new(
+                VOLUME_ACTION,
+                Some(<(String, u8)>::static_variant_type().as_ref()),
+            );
+            volume.connect_activate(move |_, parameter| {
+                if let Some((device_id, volume)) = parameter.and_then(<(String, u8)>::from_variant)
+                {
+                    f(device_id, volume);
+                }
+            });
+            volume
+        });
+    }
+
     pub fn connect_switch_device<F>(&self, f: F)
     where
         F: Fn(Option<String>) + 'static,
@@ -148,22 +209,86 @@ impl DeviceSelectorWidget {
         self.imp().button_content.set_icon_name(icon);
     }
 
+    fn make_row(&self, device: &ConnectDevice) -> (gtk::Widget, DeviceRow) {
+        let widget = self.imp();
+
+        let check = gtk::CheckButton::builder()
+            .action_name(&format!("{}.{}", ACTIONS, CONNECT_ACTION))
+            .action_target(&Some(&device.id).to_variant())
+            .group(&*widget.this_device_button)
+            .label(&device.label)
+            .sensitive(!device.is_restricted)
+            .hexpand(true)
+            .build();
+        if device.is_restricted {
+            check.set_tooltip_text(Some(&gettext("This device can't be controlled remotely")));
+        }
+
+        let active_icon = gtk::Image::builder()
+            .icon_name("media-playback-start-symbolic")
+            .tooltip_text(&gettext("Currently active"))
+            .visible(device.is_active)
+            .build();
+
+        let volume = gtk::Scale::with_range(gtk::Orientation::Horizontal, 0.0, 100.0, 1.0);
+        volume.set_margin_start(24);
+        volume.set_value(device.volume.unwrap_or_default().into());
+        volume.set_visible(device.volume.is_some() && !device.is_restricted);
+        // Only emitted when the user moves the slider, so refreshing the list doesn't send anything
+        let device_id = device.id.clone();
+        let action_group = widget.action_group.clone();
+        volume.connect_change_value(move |_, _, value| {
+            let volume = value.clamp(0.0, 100.0) as u8;
+            action_group.activate_action(
+                VOLUME_ACTION,
+                Some(&(device_id.clone(), volume).to_variant()),
+            );
+            gtk::Inhibit(false)
+        });
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        header.append(&check);
+        header.append(&active_icon);
+
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        container.append(&header);
+        container.append(&volume);
+
+        let row = DeviceRow {
+            device: device.clone(),
+            active_icon,
+            volume,
+        };
+        (container.upcast(), row)
+    }
+
     pub fn update_devices_list(&self, devices: &[ConnectDevice]) {
         let widget = self.imp();
         widget.this_device_button.set_sensitive(!devices.is_empty());
 
+        // Same devices as before: update them in place, so as not to pull a slider from under the cursor
+        let mut rows = widget.rows.borrow_mut();
+        let unchanged = rows.len() == devices.len()
+            && rows
+                .iter()
+                .zip(devices)
+                .all(|(row, device)| row.shows(device));
+        if unchanged {
+            for (row, device) in rows.iter_mut().zip(devices) {
+                row.update(device);
+            }
+            return;
+        }
+
         while let Some(child) = widget.devices.upcast_ref::<gtk::Widget>().first_child() {
             widget.devices.remove(&child);
         }
+        rows.clear();
 
         for device in devices {
-            let check = gtk::CheckButton::builder()
-                .action_name(&format!("{}.{}", ACTIONS, CONNECT_ACTION))
-                .action_target(&Some(&device.id).to_variant())
-                .group(&*widget.this_device_button)
-                .label(&device.label)
-                .build();
-            widget.devices.append(&check);
+            let (child, row) = self.make_row(device);
+            widget.devices.append(&child);
+            rows.push(row);
         }
     }
 }
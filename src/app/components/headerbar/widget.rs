@@ -62,6 +62,10 @@ mod imp {
         fn add_child(&self, builder: &gtk::Builder, child: &glib::Object, type_: Option<&str>) {
             if Some("root") == type_ {
                 self.parent_add_child(builder, child, type_);
+            } else if Some("end") == type_ {
+                if let Some(child) = child.downcast_ref::<gtk::Widget>() {
+                    self.main_header.pack_end(child);
+                }
             } else {
                 self.main_header
                     .set_title_widget(child.downcast_ref::<gtk::Widget>());
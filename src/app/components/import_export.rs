@@ -0,0 +1,391 @@
+use futures::stream::{self, StreamExt};
+use gettextrs::*;
+use gio::prelude::*;
+use gtk::prelude::*;
+use libadwaita::prelude::*;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::api::{SpotifyApiClient, SpotifyApiError, SpotifyResult};
+use crate::app::components::{create_playlist_with_tracks, fill_batch, labels};
+use crate::app::models::{SongDescription, SongListModel};
+use crate::app::playlist_file::{PlaylistEntry, PlaylistFile, PlaylistFormat};
+use crate::app::state::LoginAction;
+use crate::app::{ActionDispatcher, AppAction, AppModel, BatchQuery, SongsSource};
+
+// How many searches can be going on at once when importing
+const CONCURRENT_SEARCHES: usize = 4;
+// How many search results to pick from
+const SEARCH_LIMIT: usize = 5;
+// How many more times failed searches are tried, after refreshing the token or waiting for the rate limit
+const SEARCH_RETRIES: usize = 3;
+// How long to wait before trying again, unless told otherwise (with Retry-After)
+const DEFAULT_RETRY_DELAY_SECS: u64 = 2;
+const MAX_RETRY_DELAY_SECS: u64 = 30;
+
+// Sets up a button so that picking a format from its menu exports to that format
+pub fn setup_export_button<F>(button: &gtk::MenuButton, f: F)
+where
+    F: Fn(PlaylistFormat) + 'static,
+{
+    let menu = gio::Menu::new();
+    for format in PlaylistFormat::ALL {
+        let item = gio::MenuItem::new(Some(format.name()), None);
+        item.set_action_and_target_value(Some("export.as"), Some(&format.extension().to_variant()));
+        menu.append_item(&item);
+    }
+    button.set_menu_model(Some(&menu));
+
+    let action = gio::SimpleAction::new("as", Some(glib::VariantTy::STRING));
+    action.connect_activate(move |_, format| {
+        let format = format
+            .and_then(|f| f.str())
+            .and_then(PlaylistFormat::from_extension);
+        if let Some(format) = format {
+            f(format);
+        }
+    });
+    let group = gio::SimpleActionGroup::new();
+    group.add_action(&action);
+    button.insert_action_group("export", Some(&group));
+}
+
+fn parent_window(widget: &impl IsA<gtk::Widget>) -> Option<gtk::Window> {
+    widget.root()?.downcast::<gtk::Window>().ok()
+}
+
+// Asks where to export a list of songs, then loads the missing ones and writes them all there
+pub fn export_songs(
+    parent: &impl IsA<gtk::Widget>,
+    app_model: &AppModel,
+    dispatcher: Box<dyn ActionDispatcher>,
+    format: PlaylistFormat,
+    name: String,
+    source: SongsSource,
+    songs: &SongListModel,
+) {
+    let queries: Vec<(BatchQuery, usize)> = songs
+        .missing_batches_with_starts()
+        .into_iter()
+        .map(|(batch, start)| {
+            let query = BatchQuery {
+                source: source.clone(),
+                batch,
+            };
+            (query, start)
+        })
+        .collect();
+    let songs: Vec<Option<SongDescription>> = (0..songs.len())
+        .map(|i| songs.index(i).map(|s| s.into_description()))
+        .collect();
+    let loader = app_model.get_batch_loader();
+
+    let dialog = gtk::FileDialog::builder()
+        // translators: Title of the dialog to pick where to export a playlist to
+        .title(gettext("Export playlist"))
+        .initial_name(format!("{}.{}", name, format.extension()))
+        .modal(true)
+        .build();
+    let window = parent_window(parent);
+    dialog.save(window.as_ref(), gio::Cancellable::NONE, move |file| {
+        // Cancelled, most likely
+        let path = if let Some(path) = file.ok().and_then(|f| f.path()) {
+            path
+        } else {
+            return;
+        };
+        dispatcher.call_spotify_and_dispatch(move || async move {
+            let mut songs = songs;
+            for (query, start) in queries.iter() {
+                let song_batch = loader.get_batch(query).await?;
+                fill_batch(&mut songs, *start, song_batch, |song| song);
+            }
+            let songs: Vec<SongDescription> = songs.into_iter().flatten().collect();
+            let content = PlaylistFile::new(&name, &songs).write(format);
+            let message = match std::fs::write(&path, content) {
+                // translators: This notification is shown once a playlist has been exported to a file.
+                Ok(_) => gettext("Playlist exported."),
+                Err(e) => {
+                    warn!("Could not export playlist: {}", e);
+                    // translators: This notification is shown when a playlist could not be written to a file.
+                    gettext("The playlist could not be exported.")
+                }
+            };
+            Ok(AppAction::ShowNotification(message))
+        });
+    });
+}
+
+// Asks for a playlist file, finds its tracks on Spotify, then lets the user review the result
+pub fn import_playlist(
+    parent: &impl IsA<gtk::Widget>,
+    app_model: Rc<AppModel>,
+    dispatcher: Box<dyn ActionDispatcher>,
+) {
+    let filter = gtk::FileFilter::new();
+    // translators: Name of the kind of files (M3U, XSPF, CSV or JSON playlists) that can be imported
+    filter.set_name(Some(&gettext("Playlists")));
+    for format in PlaylistFormat::ALL {
+        filter.add_suffix(format.extension());
+    }
+    filter.add_suffix("m3u8");
+
+    let dialog = gtk::FileDialog::builder()
+        // translators: Title of the dialog to pick a playlist file to import
+        .title(gettext("Import playlist"))
+        .default_filter(&filter)
+        .modal(true)
+        .build();
+    let window = parent_window(parent);
+    dialog.open(window.as_ref(), gio::Cancellable::NONE, move |file| {
+        if let Ok(file) = file {
+            glib::MainContext::default()
+                .spawn_local(import_file(window, file, app_model, dispatcher));
+        }
+    });
+}
+
+async fn read_playlist_file(file: &gio::File) -> Option<PlaylistFile> {
+    let format = PlaylistFormat::from_path(&file.basename()?)?;
+    let (bytes, _) = file
+        .load_contents_future()
+        .await
+        .map_err(|e| warn!("Could not read playlist file: {}", e))
+        .ok()?;
+    let content = String::from_utf8_lossy(&bytes);
+    PlaylistFile::parse(format, content.trim_start_matches('\u{feff}'))
+        .map_err(|e| warn!("Could not import playlist: {}", e))
+        .ok()
+}
+
+// The URI of the track an entry refers to, searching for it if needed (None if nothing matches)
+async fn find_track(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    entry: PlaylistEntry,
+) -> SpotifyResult<Option<String>> {
+    if let Some(uri) = entry.track_uri() {
+        return Ok(Some(uri));
+    }
+    let query = entry.search_query();
+    if query.is_empty() {
+        return Ok(None);
+    }
+    let results = api
+        .search_tracks(&query, 0, SEARCH_LIMIT)
+        .await
+        .map_err(|e| {
+            warn!("Could not search for {}: {}", query, e);
+            e
+        })?;
+    Ok(entry.best_match(&results).map(|song| song.uri.clone()))
+}
+
+// Looks for all of them, a few at a time
+async fn find_all_tracks(
+    api: &Arc<dyn SpotifyApiClient + Send + Sync>,
+    entries: Vec<PlaylistEntry>,
+) -> Vec<SpotifyResult<Option<String>>> {
+    stream::iter(entries)
+        .map(|entry| find_track(Arc::clone(api), entry))
+        .buffered(CONCURRENT_SEARCHES)
+        .collect()
+        .await
+}
+
+// Looks for the tracks of all the entries, trying the searches that failed again a few times
+async fn find_tracks(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    dispatcher: &dyn ActionDispatcher,
+    entries: &[PlaylistEntry],
+) -> Vec<SpotifyResult<Option<String>>> {
+    let mut found = find_all_tracks(&api, entries.to_vec()).await;
+    for _ in 0..SEARCH_RETRIES {
+        let failed: Vec<usize> = (0..found.len()).filter(|i| found[*i].is_err()).collect();
+        if failed.is_empty() {
+            break;
+        }
+        if found
+            .iter()
+            .any(|result| matches!(result, Err(SpotifyApiError::InvalidToken)))
+        {
+            dispatcher.dispatch(LoginAction::RefreshToken.into());
+        }
+        let delay = found
+            .iter()
+            .filter_map(|result| match result {
+                Err(SpotifyApiError::TooManyRequests(retry_after)) => *retry_after,
+                _ => None,
+            })
+            .max()
+            .unwrap_or(DEFAULT_RETRY_DELAY_SECS)
+            .min(MAX_RETRY_DELAY_SECS);
+        glib::timeout_future_seconds(delay as u32).await;
+
+        let retried = failed.iter().map(|i| entries[*i].clone()).collect();
+        let retried = find_all_tracks(&api, retried).await;
+        for (i, result) in failed.into_iter().zip(retried) {
+            found[i] = result;
+        }
+    }
+    found
+}
+
+// The entries for which looking for a track gave a given result
+fn entries_where<'a>(
+    entries: &'a [PlaylistEntry],
+    found: &[SpotifyResult<Option<String>>],
+    f: impl Fn(&SpotifyResult<Option<String>>) -> bool,
+) -> Vec<&'a PlaylistEntry> {
+    entries
+        .iter()
+        .zip(found.iter())
+        .filter(|(_, result)| f(result))
+        .map(|(entry, _)| entry)
+        .collect()
+}
+
+async fn import_file(
+    window: Option<gtk::Window>,
+    file: gio::File,
+    app_model: Rc<AppModel>,
+    dispatcher: Box<dyn ActionDispatcher>,
+) {
+    let playlist = if let Some(playlist) = read_playlist_file(&file).await {
+        playlist
+    } else {
+        dispatcher.dispatch(AppAction::ShowNotification(
+            // translators: This notification is shown when a playlist file can't be imported (unknown format, or no tracks in it).
+            gettext("This playlist file could not be read."),
+        ));
+        return;
+    };
+
+    // translators: This notification is shown while the tracks of an imported playlist are being looked for on Spotify.
+    dispatcher.dispatch(AppAction::ShowNotification(gettext("Looking for tracks…")));
+    let found = find_tracks(
+        app_model.get_spotify(),
+        dispatcher.as_ref(),
+        &playlist.tracks,
+    )
+    .await;
+
+    let name = playlist.name.clone().unwrap_or_else(|| {
+        file.basename()
+            .and_then(|b| b.file_stem().map(|s| s.to_string_lossy().to_string()))
+            .unwrap_or_default()
+    });
+    let unmatched = entries_where(&playlist.tracks, &found, |result| {
+        matches!(result, Ok(None))
+    });
+    let failed = entries_where(&playlist.tracks, &found, |result| result.is_err());
+    let uris: Vec<String> = found
+        .iter()
+        .filter_map(|result| result.as_ref().ok().cloned().flatten())
+        .collect();
+
+    let (dialog, name_entry) = review_dialog(
+        window.as_ref(),
+        &name,
+        &unmatched,
+        &failed,
+        uris.len(),
+        found.len(),
+    );
+    dialog.connect_response(None, move |_, response| {
+        if response == "create" {
+            let name = name_entry.text().to_string();
+            create_playlist(&app_model, dispatcher.box_clone(), name, uris.clone());
+        }
+    });
+    dialog.present();
+}
+
+fn create_playlist(
+    app_model: &AppModel,
+    dispatcher: Box<dyn ActionDispatcher>,
+    name: String,
+    uris: Vec<String>,
+) {
+    let user_id = app_model.get_state().logged_user.user.clone();
+    let user_id = if let Some(user_id) = user_id {
+        user_id
+    } else {
+        return;
+    };
+    let api = app_model.get_spotify();
+    dispatcher.call_spotify_and_dispatch_many(move || async move {
+        create_playlist_with_tracks(api, &name, &user_id, uris, None).await
+    });
+}
+
+// A list of entries of the imported file, under some label
+fn entries_list(label: &str, entries: &[&PlaylistEntry]) -> gtk::Box {
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    let label = gtk::Label::new(Some(label));
+    label.set_xalign(0.0);
+    content.append(&label);
+
+    let list = gtk::ListBox::new();
+    list.set_selection_mode(gtk::SelectionMode::None);
+    list.add_css_class("boxed-list");
+    for track in entries {
+        let row = libadwaita::ActionRow::new();
+        row.set_use_markup(false);
+        row.set_title(&track.title);
+        row.set_subtitle(&track.artist);
+        list.append(&row);
+    }
+    let scrolled_window = gtk::ScrolledWindow::new();
+    scrolled_window.set_hscrollbar_policy(gtk::PolicyType::Never);
+    scrolled_window.set_propagate_natural_height(true);
+    scrolled_window.set_max_content_height(240);
+    scrolled_window.set_child(Some(&list));
+    content.append(&scrolled_window);
+    content
+}
+
+// Shows which tracks were not found (or could not be looked for), and asks for the name of the playlist to create
+fn review_dialog(
+    window: Option<&gtk::Window>,
+    name: &str,
+    unmatched: &[&PlaylistEntry],
+    failed: &[&PlaylistEntry],
+    found: usize,
+    total: usize,
+) -> (libadwaita::MessageDialog, gtk::Entry) {
+    let dialog = libadwaita::MessageDialog::new(
+        window,
+        // translators: Title of the dialog shown before creating a playlist out of an imported file
+        Some(&gettext("Import playlist")),
+        Some(&labels::tracks_found_label(found, total)),
+    );
+
+    let content = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    let entry = gtk::Entry::new();
+    entry.set_text(name);
+    // translators: Placeholder of the entry containing the name of the playlist created out of an imported file
+    entry.set_placeholder_text(Some(&gettext("Name")));
+    content.append(&entry);
+
+    if !unmatched.is_empty() {
+        // translators: Shown above the list of tracks of an imported playlist that couldn't be found on Spotify (and won't be added)
+        let label = gettext("Not found:");
+        content.append(&entries_list(&label, unmatched));
+    }
+    if !failed.is_empty() {
+        // translators: Shown above the list of tracks of an imported playlist that couldn't be looked for on Spotify because of an error (and won't be added)
+        let label = gettext("Could not be looked up:");
+        content.append(&entries_list(&label, failed));
+    }
+    dialog.set_extra_child(Some(&content));
+
+    // translators: Button of the dialog shown before creating a playlist out of an imported file
+    dialog.add_response("cancel", &gettext("Cancel"));
+    // translators: Button of the dialog shown before creating a playlist out of an imported file
+    dialog.add_response("create", &gettext("Create playlist"));
+    dialog.set_response_appearance("create", libadwaita::ResponseAppearance::Suggested);
+    dialog.set_response_enabled("create", found > 0);
+    dialog.set_default_response(Some("create"));
+    dialog.set_close_response("cancel");
+    (dialog, entry)
+}
@@ -10,6 +10,9 @@ lazy_static! {
     // translators: This is part of a contextual menu attached to a single track; this entry adds a track at the end of the play queue.
     pub static ref ADD_TO_QUEUE: String = gettext("Add to queue");
 
+    // translators: This is part of a contextual menu attached to a single track; this entry queues a track so that it plays right after the current one.
+    pub static ref PLAY_NEXT: String = gettext("Play next");
+
     // translators: This is part of a contextual menu attached to a single track; this entry removes a track from the play queue.
     pub static ref REMOVE_FROM_QUEUE: String = gettext("Remove from queue");
 }
@@ -53,3 +56,22 @@ pub fn album_by_artist_label(album: &str, artist: &str) -> String {
         glib::markup_escape_text(artist)
     )
 }
+
+pub fn tracks_found_label(found: usize, total: usize) -> String {
+    // this is just to fool xgettext, it doesn't like macros (or rust for that matter) :(
+    if cfg!(debug_assertions) {
+        // translators: This is shown when importing a playlist file, once its tracks were looked for on Spotify.
+        ngettext(
+            "{} of {} track was found.",
+            "{} of {} tracks were found.",
+            total as u32,
+        );
+    }
+    ngettext!(
+        "{} of {} track was found.",
+        "{} of {} tracks were found.",
+        total as u32,
+        found,
+        total
+    )
+}
@@ -2,9 +2,11 @@ use std::time::SystemTime;
 
 use gettextrs::*;
 
+use crate::api::set_http_proxy;
 use crate::app::credentials::Credentials;
-use crate::app::state::{LoginAction, TryLoginAction};
+use crate::app::state::{LoginAction, SettingsAction, TryLoginAction};
 use crate::app::{ActionDispatcher, AppAction, Worker};
+use crate::settings::ProxySettings;
 
 pub struct LoginModel {
     dispatcher: Box<dyn ActionDispatcher>,
@@ -17,26 +19,36 @@ impl LoginModel {
     }
 
     pub fn try_autologin(&self) {
-        self.dispatcher.dispatch_async(Box::pin(async {
-            let action = match Credentials::retrieve().await {
-                Ok(creds) => LoginAction::TryLogin(if creds.token_expired() {
-                    TryLoginAction::Password {
-                        username: creds.username,
-                        password: creds.password,
-                    }
-                } else {
-                    TryLoginAction::Token {
-                        username: creds.username,
-                        token: creds.token,
-                    }
-                }),
-                Err(err) => {
-                    warn!("Could not retrieve credentials: {}", err);
-                    LoginAction::ShowLogin
+        let dispatcher = self.dispatcher.box_clone();
+        // Resolving the proxy needs the main context, hence a local task
+        self.worker.send_local_task(async move {
+            // Nothing goes out before we know which proxy to go through
+            let proxy = ProxySettings::retrieve().await.resolve().await;
+            set_http_proxy(&proxy);
+            dispatcher.dispatch(SettingsAction::SetProxy(proxy).into());
+            dispatcher.dispatch(Self::autologin_action().await);
+        });
+    }
+
+    async fn autologin_action() -> AppAction {
+        let action = match Credentials::retrieve().await {
+            Ok(creds) => LoginAction::TryLogin(if creds.token_expired() {
+                TryLoginAction::Password {
+                    username: creds.username,
+                    password: creds.password,
                 }
-            };
-            Some(action.into())
-        }));
+            } else {
+                TryLoginAction::Token {
+                    username: creds.username,
+                    token: creds.token,
+                }
+            }),
+            Err(err) => {
+                warn!("Could not retrieve credentials: {}", err);
+                LoginAction::ShowLogin
+            }
+        };
+        action.into()
     }
 
     pub fn clear_saved_credentials(&self) {
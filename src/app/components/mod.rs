@@ -31,6 +31,21 @@ pub use settings::*;
 mod player_notifier;
 pub use player_notifier::PlayerNotifier;
 
+mod sleep_timer;
+pub use sleep_timer::SleepTimerNotifier;
+
+mod playback_persistence;
+pub use playback_persistence::{save_playback, PlaybackPersistence};
+
+mod undo;
+pub use undo::UndoHandler;
+
+mod playlist_helpers;
+pub use playlist_helpers::*;
+
+mod import_export;
+pub use import_export::*;
+
 mod library;
 pub use library::*;
 
@@ -1,4 +1,6 @@
 use crate::app::components::EventListener;
+use crate::app::models::Restriction;
+use crate::app::state::{Inverse, PlaybackEvent};
 use crate::app::AppEvent;
 use gettextrs::*;
 use glib::ToVariant;
@@ -34,6 +36,50 @@ impl Notification {
             .build();
         self.toast_overlay.add_toast(toast);
     }
+
+    fn show_undoable(&self, id: u32, inverse: &Inverse) {
+        let message = match inverse {
+            // translators: This is a notification shown after removing tracks from a playlist. It can be undone.
+            Inverse::AddToPlaylist(_, _) => gettext("Tracks removed from playlist."),
+            // translators: This is a notification shown after removing tracks from the saved tracks. It can be undone.
+            Inverse::SaveTracks(_) => gettext("Tracks removed from your library."),
+            // translators: This is a notification shown after removing an album from the library. It can be undone.
+            Inverse::SaveAlbum(_) => gettext("Album removed from your library."),
+            // translators: This is a notification shown after removing tracks from the play queue. It can be undone.
+            Inverse::Requeue(_) => gettext("Tracks removed from the queue."),
+        };
+        // translators: This is a label in the notification shown after removing tracks or albums. If it is clicked, they are put back.
+        let label = gettext("Undo");
+        let toast = libadwaita::Toast::builder()
+            .title(message)
+            .timeout(4)
+            .action_name("app.undo_entry")
+            .button_label(label)
+            .action_target(&id.to_variant())
+            .build();
+        self.toast_overlay.add_toast(toast);
+    }
+}
+
+fn unavailable_message(restriction: Option<Restriction>) -> String {
+    match restriction {
+        Some(Restriction::Market) => {
+            // translators: This notification is shown when a track can't be played in the user's country, and playback moved on to the next one.
+            gettext("This track is not available in your country, skipping it")
+        }
+        Some(Restriction::Product) => {
+            // translators: This notification is shown when a track can't be played with the user's subscription (Free or Premium), and playback moved on to the next one.
+            gettext("This track is not available with your subscription, skipping it")
+        }
+        Some(Restriction::Explicit) => {
+            // translators: This notification is shown when a track is explicit while the user disabled explicit content, and playback moved on to the next one.
+            gettext("This track is explicit, skipping it")
+        }
+        None => {
+            // translators: This notification is shown when a track could not be played (failed to load, for instance) and playback moved on to the next one.
+            gettext("This track is unavailable, skipping it")
+        }
+    }
 }
 
 impl EventListener for Notification {
@@ -42,6 +88,12 @@ impl EventListener for Notification {
             self.show(content)
         } else if let AppEvent::PlaylistCreatedNotificationShown(id) = event {
             self.show_playlist_created(id)
+        } else if let AppEvent::UndoRecorded(id, inverse) = event {
+            self.show_undoable(*id, inverse)
+        } else if let AppEvent::PlaybackEvent(PlaybackEvent::TrackUnavailable(_, restriction)) =
+            event
+        {
+            self.show(&unavailable_message(*restriction))
         }
     }
 }
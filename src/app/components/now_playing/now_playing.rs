@@ -1,9 +1,12 @@
+use futures::channel::mpsc;
+use futures::StreamExt;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::CompositeTemplate;
 use std::rc::Rc;
 
 use super::NowPlayingModel;
+use crate::app::components::sidebar::CreatePlaylistPopover;
 use crate::app::components::{
     Component, DeviceSelector, DeviceSelectorWidget, EventListener, HeaderBarComponent,
     HeaderBarWidget, Playlist,
@@ -21,12 +24,21 @@ mod imp {
         #[template_child]
         pub song_list: TemplateChild<gtk::ListView>,
 
+        #[template_child]
+        pub next_up: TemplateChild<libadwaita::Clamp>,
+
+        #[template_child]
+        pub next_up_list: TemplateChild<gtk::ListView>,
+
         #[template_child]
         pub headerbar: TemplateChild<HeaderBarWidget>,
 
         #[template_child]
         pub device_selector: TemplateChild<DeviceSelectorWidget>,
 
+        #[template_child]
+        pub save_queue: TemplateChild<gtk::MenuButton>,
+
         #[template_child]
         pub scrolled_window: TemplateChild<gtk::ScrolledWindow>,
     }
@@ -82,6 +94,14 @@ impl NowPlayingWidget {
         self.imp().song_list.as_ref()
     }
 
+    fn next_up_list_widget(&self) -> &gtk::ListView {
+        self.imp().next_up_list.as_ref()
+    }
+
+    fn set_next_up_visible(&self, visible: bool) {
+        self.imp().next_up.set_visible(visible);
+    }
+
     fn headerbar_widget(&self) -> &HeaderBarWidget {
         self.imp().headerbar.as_ref()
     }
@@ -89,6 +109,22 @@ impl NowPlayingWidget {
     fn device_selector_widget(&self) -> &DeviceSelectorWidget {
         self.imp().device_selector.as_ref()
     }
+
+    fn set_save_queue_popover(&self, popover: &CreatePlaylistPopover) {
+        popover.set_position(gtk::PositionType::Bottom);
+        self.imp().save_queue.set_popover(Some(popover));
+    }
+
+    // While saving, the button shows how far along it is
+    fn set_save_queue_progress(&self, progress: Option<f64>) {
+        let button = &self.imp().save_queue;
+        button.set_sensitive(progress.is_none());
+        if let Some(progress) = progress {
+            button.set_label(&format!("{:.0}%", progress * 100.0));
+        } else {
+            button.set_icon_name("document-save-symbolic");
+        }
+    }
 }
 
 pub struct NowPlaying {
@@ -105,9 +141,33 @@ impl NowPlaying {
             model.load_more();
         }));
 
+        let popover = CreatePlaylistPopover::new();
+        widget.set_save_queue_popover(&popover);
+        popover.connect_create(
+            clone!(@weak model, @weak widget, @strong worker => move |name| {
+                let (sender, mut receiver) = mpsc::unbounded();
+                widget.set_save_queue_progress(Some(0.0));
+                model.save_queue_as_playlist(name, sender);
+                // Done (or failed) once the sender is dropped
+                worker.send_local_task(async move {
+                    while let Some(progress) = receiver.next().await {
+                        widget.set_save_queue_progress(Some(progress));
+                    }
+                    widget.set_save_queue_progress(None);
+                });
+            }),
+        );
+
         let playlist = Box::new(Playlist::new(
             widget.song_list_widget().clone(),
             model.clone(),
+            worker.clone(),
+        ));
+
+        widget.set_next_up_visible(model.has_next_up());
+        let next_up = Box::new(Playlist::new(
+            widget.next_up_list_widget().clone(),
+            model.next_up_model(),
             worker,
         ));
 
@@ -126,7 +186,7 @@ impl NowPlaying {
         Self {
             widget,
             model,
-            children: vec![playlist, headerbar, device_selector],
+            children: vec![playlist, next_up, headerbar, device_selector],
         }
     }
 }
@@ -143,8 +203,16 @@ impl Component for NowPlaying {
 
 impl EventListener for NowPlaying {
     fn on_event(&mut self, event: &AppEvent) {
-        if let AppEvent::PlaybackEvent(PlaybackEvent::TrackChanged(_)) = event {
-            self.model.load_more();
+        match event {
+            AppEvent::PlaybackEvent(
+                PlaybackEvent::TrackChanged(_) | PlaybackEvent::WaitingForBatch,
+            ) => {
+                self.model.load_more();
+            }
+            AppEvent::PlaybackEvent(PlaybackEvent::NextUpChanged) => {
+                self.widget.set_next_up_visible(self.model.has_next_up());
+            }
+            _ => {}
         }
         self.broadcast_event(event);
     }
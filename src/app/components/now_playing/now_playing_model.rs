@@ -1,11 +1,13 @@
+use futures::channel::mpsc::UnboundedSender;
+use gettextrs::gettext;
 use gio::prelude::*;
 use gio::SimpleActionGroup;
 use std::ops::Deref;
 use std::rc::Rc;
 
 use crate::app::components::{
-    labels, DeviceSelectorModel, HeaderBarModel, PlaylistModel, SimpleHeaderBarModel,
-    SimpleHeaderBarModelWrapper,
+    create_playlist_with_tracks, fill_batch, labels, DeviceSelectorModel, HeaderBarModel,
+    PlaylistModel, SimpleHeaderBarModel, SimpleHeaderBarModelWrapper,
 };
 use crate::app::models::{SongDescription, SongListModel};
 use crate::app::state::Device;
@@ -36,18 +38,78 @@ impl NowPlayingModel {
         let loader = self.app_model.get_batch_loader();
         let query = queue.next_query()?;
         debug!("next_query = {:?}", &query);
+        let is_waiting = queue.is_waiting_for_batch();
 
-        self.dispatcher.dispatch_async(Box::pin(async move {
-            loader
-                .query(query, |source, song_batch| {
-                    PlaybackAction::LoadPagedSongs(source, song_batch).into()
-                })
-                .await
+        self.dispatcher.dispatch_many_async(Box::pin(async move {
+            let source = query.source.clone();
+            match loader.get_batch(&query).await {
+                Ok(song_batch) => vec![PlaybackAction::LoadPagedSongs(source, song_batch).into()],
+                Err(err) => {
+                    error!("Spotify API error: {}", err);
+                    let mut actions = vec![AppAction::ShowNotification(gettext(
+                        // translators: This notification is the default message for unhandled errors. Logs refer to console output.
+                        "An error occured. Check logs for details!",
+                    ))];
+                    // The current track will never show up, don't stay stuck on it
+                    if is_waiting {
+                        actions.push(PlaybackAction::Stop.into());
+                    }
+                    actions
+                }
+            }
         }));
 
         Some(())
     }
 
+    // The uris of the queue, tracks that aren't loaded yet are None
+    fn song_uris(&self) -> Vec<Option<String>> {
+        let songs = self.queue().songs().clone();
+        (0..songs.len())
+            .map(|i| songs.index(i).map(|s| s.into_description().uri))
+            .collect()
+    }
+
+    // What's queued plays right after the current track
+    fn next_up_position(&self) -> usize {
+        let queue = self.queue();
+        queue
+            .current_song_id()
+            .and_then(|id| queue.songs().find_index(&id))
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    }
+
+    // Loads whatever is missing from the queue, then creates a playlist out of it (in the order it plays, minus shuffling)
+    // The fraction of tracks added so far is sent to `progress`
+    pub fn save_queue_as_playlist(&self, name: String, progress: UnboundedSender<f64>) {
+        let user_id = self.app_model.get_state().logged_user.user.clone();
+        let user_id = if let Some(user_id) = user_id {
+            user_id
+        } else {
+            return;
+        };
+        let api = self.app_model.get_spotify();
+        let loader = self.app_model.get_batch_loader();
+        let queries = self.queue().missing_queries();
+        let song_uris = self.song_uris();
+        let position = self.next_up_position().min(song_uris.len());
+        let next_up = self.queue().next_up().map_collect(|s| Some(s.uri));
+
+        self.dispatcher
+            .call_spotify_and_dispatch_many(move || async move {
+                let mut uris = song_uris;
+                for (query, start) in queries.iter() {
+                    let song_batch = loader.get_batch(query).await?;
+                    fill_batch(&mut uris, *start, song_batch, |song| song.uri);
+                }
+                uris.splice(position..position, next_up);
+                let uris: Vec<String> = uris.into_iter().flatten().collect();
+
+                create_playlist_with_tracks(api, &name, &user_id, uris, Some(progress)).await
+            })
+    }
+
     pub fn to_headerbar_model(self: &Rc<Self>) -> Rc<impl HeaderBarModel> {
         Rc::new(SimpleHeaderBarModelWrapper::new(
             self.clone(),
@@ -60,6 +122,17 @@ impl NowPlayingModel {
         DeviceSelectorModel::new(self.app_model.clone(), self.dispatcher.box_clone())
     }
 
+    pub fn next_up_model(&self) -> Rc<NextUpModel> {
+        Rc::new(NextUpModel {
+            app_model: self.app_model.clone(),
+            dispatcher: self.dispatcher.box_clone(),
+        })
+    }
+
+    pub fn has_next_up(&self) -> bool {
+        self.queue().next_up().len() > 0
+    }
+
     fn current_selection_context(&self) -> SelectionContext {
         let state = self.app_model.get_state();
         match state.playback.current_device() {
@@ -153,6 +226,81 @@ impl PlaylistModel for NowPlayingModel {
     }
 }
 
+// What was queued by the user (or on a Connect device), played before the rest of the queue
+pub struct NextUpModel {
+    app_model: Rc<AppModel>,
+    dispatcher: Box<dyn ActionDispatcher>,
+}
+
+impl NextUpModel {
+    fn is_local(&self) -> bool {
+        matches!(
+            self.app_model.get_state().playback.current_device(),
+            Device::Local
+        )
+    }
+}
+
+impl PlaylistModel for NextUpModel {
+    fn song_list_model(&self) -> SongListModel {
+        self.app_model.get_state().playback.next_up().clone()
+    }
+
+    fn is_paused(&self) -> bool {
+        !self.app_model.get_state().playback.is_playing()
+    }
+
+    // Whatever is playing is no longer "next"
+    fn current_song_id(&self) -> Option<String> {
+        None
+    }
+
+    // A device only lets us skip to the next track, not to any track of its queue
+    fn play_song_at(&self, _pos: usize, id: &str) {
+        if self.is_local() {
+            self.dispatcher
+                .dispatch(PlaybackAction::Load(id.to_string()).into());
+        }
+    }
+
+    fn actions_for(&self, id: &str) -> Option<gio::ActionGroup> {
+        let song = self.song_list_model().get(id)?;
+        let song = song.description();
+        let group = SimpleActionGroup::new();
+
+        for view_artist in song.make_artist_actions(self.dispatcher.box_clone(), None) {
+            group.add_action(&view_artist);
+        }
+        group.add_action(&song.make_album_action(self.dispatcher.box_clone(), None));
+        group.add_action(&song.make_link_action(None));
+        group.add_action(&song.make_dequeue_next_up_action(self.dispatcher.box_clone(), None));
+
+        Some(group.upcast())
+    }
+
+    fn menu_for(&self, id: &str) -> Option<gio::MenuModel> {
+        let song = self.song_list_model().get(id)?;
+        let song = song.description();
+
+        let menu = gio::Menu::new();
+        menu.append(Some(&*labels::VIEW_ALBUM), Some("song.view_album"));
+        for artist in song.artists.iter() {
+            menu.append(
+                Some(&labels::more_from_label(&artist.name)),
+                Some(&format!("song.view_artist_{}", artist.id)),
+            );
+        }
+
+        menu.append(Some(&*labels::COPY_LINK), Some("song.copy_link"));
+        // Tracks can't be removed from the queue of a device
+        if self.is_local() {
+            menu.append(Some(&*labels::REMOVE_FROM_QUEUE), Some("song.dequeue"));
+        }
+
+        Some(menu.upcast())
+    }
+}
+
 impl SimpleHeaderBarModel for NowPlayingModel {
     fn title(&self) -> Option<String> {
         None
@@ -3,10 +3,12 @@ use std::rc::Rc;
 
 use crate::app::components::EventListener;
 use crate::app::models::*;
-use crate::app::state::{PlaybackAction, PlaybackEvent, ScreenName, SelectionEvent};
+use crate::app::play;
+use crate::app::state::{PlaybackAction, PlaybackEvent, ScreenName, SelectionEvent, SleepTimer};
 use crate::app::{
     ActionDispatcher, AppAction, AppEvent, AppModel, AppState, BrowserAction, Worker,
 };
+use crate::settings;
 
 use super::playback_widget::PlaybackWidget;
 
@@ -62,6 +64,11 @@ impl PlaybackModel {
             .dispatch(PlaybackAction::ToggleShuffle.into());
     }
 
+    fn set_balanced_shuffle(&self, balanced: bool) {
+        self.dispatcher
+            .dispatch(PlaybackAction::SetBalancedShuffle(balanced).into());
+    }
+
     fn toggle_repeat(&self) {
         self.dispatcher
             .dispatch(PlaybackAction::ToggleRepeat.into());
@@ -71,12 +78,35 @@ impl PlaybackModel {
         self.dispatcher
             .dispatch(PlaybackAction::Seek(position).into());
     }
+
+    fn set_sleep_timer(&self, timer: Option<SleepTimer>) {
+        self.dispatcher
+            .dispatch(PlaybackAction::SetSleepTimer(timer).into());
+    }
+
+    fn save_track(&self, song: SongDescription) {
+        let api = self.app_model.get_spotify();
+        self.dispatcher
+            .call_spotify_and_dispatch(move || async move {
+                api.save_tracks(vec![song.id.clone()]).await?;
+                Ok(BrowserAction::SaveTracks(vec![song]).into())
+            });
+    }
+
+    // Shows the album of a track, and plays the track from there
+    fn open_track(&self, id: String) {
+        let api = self.app_model.get_spotify();
+        self.dispatcher
+            .call_spotify_and_dispatch_many(move || play::open_track(api, id));
+    }
 }
 
 pub struct PlaybackControl {
     model: Rc<PlaybackModel>,
     widget: PlaybackWidget,
     worker: Worker,
+    // Kept around to be notified when balanced shuffle is toggled
+    _balanced_shuffle: gio::Settings,
 }
 
 impl PlaybackControl {
@@ -90,11 +120,17 @@ impl PlaybackControl {
         widget.connect_repeat(clone!(@weak model => move || model.toggle_repeat()));
         widget.connect_seek(clone!(@weak model => move |position| model.seek_to(position)));
         widget.connect_now_playing_clicked(clone!(@weak model => move || model.go_home()));
+        widget
+            .connect_sleep_timer(clone!(@weak model => move |timer| model.set_sleep_timer(timer)));
+        let balanced_shuffle = settings::watch_balanced_shuffle(
+            clone!(@weak model => move |balanced| model.set_balanced_shuffle(balanced)),
+        );
 
         Self {
             model,
             widget,
             worker,
+            _balanced_shuffle: balanced_shuffle,
         }
     }
 
@@ -153,9 +189,18 @@ impl EventListener for PlaybackControl {
             | AppEvent::PlaybackEvent(PlaybackEvent::TrackSeeked(pos)) => {
                 self.sync_seek(*pos);
             }
+            AppEvent::PlaybackEvent(PlaybackEvent::SleepTimerChanged(timer)) => {
+                self.widget.set_sleep_timer(*timer);
+            }
             AppEvent::SelectionEvent(SelectionEvent::SelectionModeChanged(active)) => {
                 self.widget.set_seekbar_visible(!active);
             }
+            AppEvent::TrackOpened(id) => {
+                self.model.open_track(id.clone());
+            }
+            AppEvent::CurrentTrackSaveRequested(song) => {
+                self.model.save_track(song.clone());
+            }
             _ => {}
         }
     }
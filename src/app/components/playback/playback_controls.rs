@@ -1,9 +1,13 @@
 use gettextrs::gettext;
+use gio::SimpleActionGroup;
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::{glib, CompositeTemplate};
 
 use crate::app::models::RepeatMode;
+use crate::settings;
+
+const SHUFFLE_ACTIONS: &str = "shuffle";
 
 mod imp {
 
@@ -26,6 +30,12 @@ mod imp {
 
         #[template_child]
         pub repeat: TemplateChild<gtk::Button>,
+
+        // Shuffle options, shown on long-press
+        #[template_child]
+        pub shuffle_popover: TemplateChild<gtk::PopoverMenu>,
+
+        pub shuffle_actions: SimpleActionGroup,
     }
 
     #[glib::object_subclass]
@@ -43,7 +53,32 @@ mod imp {
         }
     }
 
-    impl ObjectImpl for PlaybackControlsWidget {}
+    impl ObjectImpl for PlaybackControlsWidget {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            let popover = self.shuffle_popover.get();
+            popover.set_parent(&*self.shuffle);
+
+            self.shuffle_actions
+                .add_action(&settings::balanced_shuffle_action());
+            self.obj()
+                .insert_action_group(SHUFFLE_ACTIONS, Some(&self.shuffle_actions));
+
+            // Claiming the sequence keeps the button from toggling when released
+            let long_press = gtk::GestureLongPress::new();
+            long_press.connect_pressed(clone!(@weak popover => move |gesture, _, _| {
+                gesture.set_state(gtk::EventSequenceState::Claimed);
+                popover.popup();
+            }));
+            self.shuffle.add_controller(long_press);
+        }
+
+        // The popover isn't a child of ours from the template's point of view
+        fn dispose(&self) {
+            self.shuffle_popover.unparent();
+        }
+    }
     impl WidgetImpl for PlaybackControlsWidget {}
     impl BoxImpl for PlaybackControlsWidget {}
 }
@@ -1,3 +1,4 @@
+use gio::{SimpleAction, SimpleActionGroup};
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
 use gtk::{glib, CompositeTemplate};
@@ -6,11 +7,33 @@ use crate::app::components::display_add_css_provider;
 use crate::app::components::utils::{format_duration, Clock, Debouncer};
 use crate::app::loader::ImageLoader;
 use crate::app::models::RepeatMode;
+use crate::app::state::SleepTimer;
 use crate::app::Worker;
 
 use super::playback_controls::PlaybackControlsWidget;
 use super::playback_info::PlaybackInfoWidget;
 
+const SLEEP_TIMER_ACTIONS: &str = "sleep";
+const SLEEP_TIMER_ACTION: &str = "timer";
+
+// Sleep timers are represented as strings in the menu: "off", a number of seconds, "track" or "album"
+fn sleep_timer_target(timer: Option<SleepTimer>) -> String {
+    match timer {
+        None => "off".to_string(),
+        Some(SleepTimer::After(seconds)) => seconds.to_string(),
+        Some(SleepTimer::EndOfTrack) => "track".to_string(),
+        Some(SleepTimer::EndOfAlbum) => "album".to_string(),
+    }
+}
+
+fn sleep_timer_from_target(target: &str) -> Option<SleepTimer> {
+    match target {
+        "track" => Some(SleepTimer::EndOfTrack),
+        "album" => Some(SleepTimer::EndOfAlbum),
+        seconds => seconds.parse().ok().map(SleepTimer::After),
+    }
+}
+
 mod imp {
 
     use super::*;
@@ -39,7 +62,12 @@ mod imp {
         #[template_child]
         pub track_duration: TemplateChild<gtk::Label>,
 
+        #[template_child]
+        pub sleep_timer: TemplateChild<gtk::MenuButton>,
+
         pub clock: Clock,
+
+        pub sleep_timer_actions: SimpleActionGroup,
     }
 
     #[glib::object_subclass]
@@ -62,6 +90,8 @@ mod imp {
             self.parent_constructed();
             self.now_playing_mobile.set_info_visible(false);
             self.now_playing.set_info_visible(true);
+            self.obj()
+                .insert_action_group(SLEEP_TIMER_ACTIONS, Some(&self.sleep_timer_actions));
             display_add_css_provider(resource!("/components/playback.css"));
         }
     }
@@ -190,6 +220,18 @@ impl PlaybackWidget {
         widget.controls_mobile.set_shuffled(shuffled);
     }
 
+    pub fn set_sleep_timer(&self, timer: Option<SleepTimer>) {
+        let widget = self.imp();
+        if let Some(action) = widget.sleep_timer_actions.lookup_action(SLEEP_TIMER_ACTION) {
+            action.change_state(&sleep_timer_target(timer).to_variant());
+        }
+        if timer.is_some() {
+            widget.sleep_timer.add_css_class("accent");
+        } else {
+            widget.sleep_timer.remove_css_class("accent");
+        }
+    }
+
     pub fn set_seekbar_visible(&self, visible: bool) {
         let widget = self.imp();
         widget.seek_bar.set_visible(visible);
@@ -239,4 +281,23 @@ impl PlaybackWidget {
         widget.controls.connect_repeat(f.clone());
         widget.controls_mobile.connect_repeat(f);
     }
+
+    pub fn connect_sleep_timer<F>(&self, f: F)
+    where
+        F: Fn(Option<SleepTimer>) + 'static,
+    {
+        self.imp().sleep_timer_actions.add_action(&{
+            let action = SimpleAction::new_stateful(
+                SLEEP_TIMER_ACTION,
+                Some(glib::VariantTy::STRING),
+                sleep_timer_target(None).to_variant(),
+            );
+            action.connect_activate(move |_, target| {
+                if let Some(target) = target.and_then(|t| t.str()) {
+                    f(sleep_timer_from_target(target));
+                }
+            });
+            action
+        });
+    }
 }
@@ -0,0 +1,169 @@
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use futures::stream::{self, StreamExt};
+
+use crate::api::{SpotifyApiClient, SpotifyResult};
+use crate::app::components::utils::Debouncer;
+use crate::app::components::EventListener;
+use crate::app::models::{Batch, SongDescription};
+use crate::app::state::{LoginEvent, PlaybackAction, PlaybackEvent, SavedPlayback};
+use crate::app::{ActionDispatcher, AppAction, AppEvent, AppModel, BatchLoader, BatchQuery};
+
+// Position changes come in bursts (seeking around for instance)
+const SAVE_DEBOUNCE_MS: u32 = 2000;
+const BATCH_SIZE: usize = 50;
+// How many tracks can be fetched at once when there's no source to load them from
+const CONCURRENT_REQUESTS: usize = 4;
+
+fn saved_playback_path() -> PathBuf {
+    glib::user_data_dir().join("spot").join("playback.json")
+}
+
+fn write_playback(saved: &SavedPlayback) -> io::Result<()> {
+    let path = saved_playback_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_vec(saved)?)
+}
+
+fn read_playback() -> Option<SavedPlayback> {
+    let json = std::fs::read(saved_playback_path()).ok()?;
+    serde_json::from_slice(&json)
+        .map_err(|e| warn!("Could not read saved playback: {}", e))
+        .ok()
+}
+
+fn forget_playback() {
+    match std::fs::remove_file(saved_playback_path()) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            warn!("Could not remove saved playback: {}", e)
+        }
+        _ => {}
+    }
+}
+
+// Blocking, since it also has to happen right as the app shuts down
+pub fn save_playback(app_model: &AppModel) {
+    let saved = app_model.get_state().playback.saved();
+    if let Some(saved) = saved {
+        if let Err(e) = write_playback(&saved) {
+            warn!("Could not save playback: {}", e);
+        }
+    }
+}
+
+// The actions to load the saved songs again, then to restore the playback state
+async fn restore_actions(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    saved: SavedPlayback,
+) -> SpotifyResult<Vec<AppAction>> {
+    let mut actions: Vec<AppAction> = vec![];
+    if let Some(source) = saved.source.clone() {
+        let loader = BatchLoader::new(Arc::clone(&api));
+        let first = Batch::first_of_size(BATCH_SIZE);
+        // Older saves only had songs loaded from the start
+        let offsets = if saved.batch_offsets.is_empty() {
+            (0..saved.song_ids.len()).step_by(BATCH_SIZE).collect()
+        } else {
+            saved.batch_offsets.clone()
+        };
+        for offset in offsets {
+            let query = BatchQuery {
+                source: source.clone(),
+                batch: Batch { offset, ..first },
+            };
+            let batch = loader.get_batch(&query).await?;
+            actions.push(PlaybackAction::LoadPagedSongs(query.source, batch).into());
+        }
+    } else {
+        let songs = get_tracks(&api, saved.song_ids.iter()).await;
+        actions.push(PlaybackAction::LoadSongs(songs).into());
+    }
+    let queued_ids = saved.playing_next_up.iter().chain(saved.next_up.iter());
+    let queued = get_tracks(&api, queued_ids).await;
+    actions.push(PlaybackAction::Restore(Box::new(saved), queued).into());
+    Ok(actions)
+}
+
+// Songs that can't be found anymore are left out
+async fn get_tracks(
+    api: &Arc<dyn SpotifyApiClient + Send + Sync>,
+    ids: impl Iterator<Item = &String>,
+) -> Vec<SongDescription> {
+    stream::iter(ids)
+        .map(|id| api.get_track(id))
+        .buffered(CONCURRENT_REQUESTS)
+        .filter_map(|song| async move { song.ok() })
+        .collect()
+        .await
+}
+
+// Saves what's playing to disk, so that it can be picked up again on the next start
+pub struct PlaybackPersistence {
+    app_model: Rc<AppModel>,
+    dispatcher: Box<dyn ActionDispatcher>,
+    debouncer: Debouncer,
+    // Playback is only restored on the first login
+    restored: bool,
+}
+
+impl PlaybackPersistence {
+    pub fn new(app_model: Rc<AppModel>, dispatcher: Box<dyn ActionDispatcher>) -> Self {
+        Self {
+            app_model,
+            dispatcher,
+            debouncer: Debouncer::new(),
+            restored: false,
+        }
+    }
+
+    fn restore(&mut self) {
+        if std::mem::replace(&mut self.restored, true) {
+            return;
+        }
+        // Something might have been started from the command line or D-Bus already
+        let current_song = self.app_model.get_state().playback.current_song_id();
+        if current_song.is_some() {
+            return;
+        }
+        let Some(saved) = read_playback() else {
+            return;
+        };
+        let api = self.app_model.get_spotify();
+        self.dispatcher
+            .call_spotify_and_dispatch_many(move || restore_actions(api, saved));
+    }
+
+    fn schedule_save(&self) {
+        let app_model = Rc::clone(&self.app_model);
+        self.debouncer
+            .debounce(SAVE_DEBOUNCE_MS, move || save_playback(&app_model));
+    }
+}
+
+impl EventListener for PlaybackPersistence {
+    fn on_event(&mut self, event: &AppEvent) {
+        match event {
+            AppEvent::LoginEvent(LoginEvent::LoginCompleted(_)) => self.restore(),
+            AppEvent::PlaybackEvent(PlaybackEvent::PlaybackStopped) => {
+                self.debouncer.cancel();
+                forget_playback();
+            }
+            AppEvent::PlaybackEvent(
+                PlaybackEvent::TrackChanged(_)
+                | PlaybackEvent::PlaylistChanged
+                | PlaybackEvent::NextUpChanged
+                | PlaybackEvent::SourceChanged
+                | PlaybackEvent::ShuffleChanged(_)
+                | PlaybackEvent::RepeatModeChanged(_)
+                | PlaybackEvent::TrackSeeked(_)
+                | PlaybackEvent::PlaybackPaused,
+            ) => self.schedule_save(),
+            _ => {}
+        }
+    }
+}
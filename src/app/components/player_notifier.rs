@@ -6,11 +6,13 @@ use librespot::core::spotify_id::SpotifyId;
 
 use crate::app::components::EventListener;
 use crate::app::state::{
-    Device, LoginAction, LoginEvent, LoginStartedEvent, PlaybackEvent, SettingsEvent,
+    Device, LoginAction, LoginEvent, LoginStartedEvent, PlaybackAction, PlaybackEvent,
+    SettingsEvent,
 };
 use crate::app::{ActionDispatcher, AppAction, AppEvent, AppModel, SongsSource};
 use crate::connect::ConnectCommand;
 use crate::player::Command;
+use crate::settings;
 
 enum CurrentlyPlaying {
     WithSource {
@@ -38,6 +40,8 @@ pub struct PlayerNotifier {
     dispatcher: Box<dyn ActionDispatcher>,
     command_sender: UnboundedSender<Command>,
     connect_command_sender: UnboundedSender<ConnectCommand>,
+    // The device we're playing on, as of the last switch
+    last_device: Device,
 }
 
 impl PlayerNotifier {
@@ -52,6 +56,7 @@ impl PlayerNotifier {
             dispatcher,
             command_sender,
             connect_command_sender,
+            last_device: Device::Local,
         }
     }
 
@@ -59,6 +64,10 @@ impl PlayerNotifier {
         self.app_model.get_state().playback.is_playing()
     }
 
+    fn is_playing_next_up(&self) -> bool {
+        self.app_model.get_state().playback.is_playing_next_up()
+    }
+
     fn currently_playing(&self) -> Option<CurrentlyPlaying> {
         let state = self.app_model.get_state();
         let song = state.playback.current_song_id()?;
@@ -110,6 +119,10 @@ impl PlayerNotifier {
         let event = event.clone();
         let currently_playing = self.currently_playing();
         let command = match event {
+            // Queued tracks aren't part of the context, the device will play them next anyway
+            PlaybackEvent::TrackChanged(song) if self.is_playing_next_up() => {
+                Some(ConnectCommand::PlayerNext { song })
+            }
             PlaybackEvent::TrackChanged(_) | PlaybackEvent::SourceChanged => {
                 match currently_playing {
                     Some(CurrentlyPlaying::WithSource {
@@ -139,6 +152,7 @@ impl PlayerNotifier {
             PlaybackEvent::ShuffleChanged(shuffled) => {
                 Some(ConnectCommand::PlayerShuffle(shuffled))
             }
+            PlaybackEvent::AddedToNextUp(songs) => Some(ConnectCommand::AddToQueue(songs)),
             _ => None,
         };
 
@@ -153,14 +167,19 @@ impl PlayerNotifier {
             PlaybackEvent::PlaybackResumed => Some(Command::PlayerResume),
             PlaybackEvent::PlaybackStopped => Some(Command::PlayerStop),
             PlaybackEvent::VolumeSet(volume) => Some(Command::PlayerSetVolume(*volume)),
-            PlaybackEvent::TrackChanged(id) => {
-                SpotifyId::from_base62(id)
-                    .ok()
-                    .map(|track| Command::PlayerLoad {
-                        track,
-                        resume: true,
-                    })
-            }
+            // Not playing when a saved playback was just restored
+            PlaybackEvent::TrackChanged(id) => match SpotifyId::from_base62(id) {
+                Ok(track) => Some(Command::PlayerLoad {
+                    track,
+                    resume: self.is_playing(),
+                }),
+                // Not something librespot can load (a local file, for instance)
+                Err(_) => {
+                    self.dispatcher
+                        .dispatch(PlaybackAction::MarkUnavailable(id.clone()).into());
+                    None
+                }
+            },
             PlaybackEvent::SourceChanged => {
                 let resume = self.is_playing();
                 self.currently_playing()
@@ -193,13 +212,21 @@ impl PlayerNotifier {
     }
 
     fn switch_device(&mut self, device: &Device) {
-        match device {
-            Device::Connect(device) => {
+        let previous_device = std::mem::replace(&mut self.last_device, device.clone());
+        match (previous_device, device) {
+            // Spotify knows what was playing on the previous device, it can move it over
+            (Device::Connect(_), Device::Connect(device)) => {
+                self.send_command_to_connect_player(ConnectCommand::TransferPlayback {
+                    device_id: device.id.clone(),
+                    keep_playing: settings::connect_keep_playing(),
+                });
+            }
+            (_, Device::Connect(device)) => {
                 self.send_command_to_local_player(Command::PlayerStop);
                 self.send_command_to_connect_player(ConnectCommand::SetDevice(device.id.clone()));
                 self.notify_connect_player(&PlaybackEvent::SourceChanged);
             }
-            Device::Local => {
+            (_, Device::Local) => {
                 self.send_command_to_connect_player(ConnectCommand::PlayerStop);
                 self.notify_local_player(&PlaybackEvent::SourceChanged);
             }
@@ -215,7 +242,8 @@ impl EventListener for PlayerNotifier {
             (_, AppEvent::PlaybackEvent(PlaybackEvent::SwitchedDevice(d))) => self.switch_device(d),
             (Device::Local, AppEvent::PlaybackEvent(event)) => self.notify_local_player(event),
             (Device::Local, AppEvent::SettingsEvent(SettingsEvent::PlayerSettingsChanged)) => {
-                self.send_command_to_local_player(Command::ReloadSettings)
+                let settings = self.app_model.get_state().settings.settings.clone();
+                self.send_command_to_local_player(Command::ReloadSettings(settings.player_settings))
             }
             (Device::Connect(_), AppEvent::PlaybackEvent(event)) => {
                 self.notify_connect_player(event)
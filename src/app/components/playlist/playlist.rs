@@ -227,6 +227,11 @@ where
             AppEvent::PlaybackEvent(PlaybackEvent::TrackChanged(_)) => {
                 self.update_list();
             }
+            AppEvent::PlaybackEvent(PlaybackEvent::TrackUnavailable(id, _)) => {
+                if let Some(song) = self.model.song_list_model().get(id) {
+                    song.set_playable(false);
+                }
+            }
             AppEvent::PlaybackEvent(
                 PlaybackEvent::PlaybackResumed | PlaybackEvent::PlaybackPaused,
             ) => {
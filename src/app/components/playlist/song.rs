@@ -2,6 +2,7 @@ use crate::app::components::display_add_css_provider;
 use crate::app::loader::ImageLoader;
 use crate::app::models::SongModel;
 use crate::app::Worker;
+use gettextrs::gettext;
 use gio::MenuModel;
 use glib::subclass::InitializingObject;
 
@@ -14,6 +15,7 @@ mod imp {
     use super::*;
 
     const SONG_CLASS: &str = "song--playing";
+    const UNPLAYABLE_CLASS: &str = "song--unplayable";
 
     #[derive(Debug, Default, CompositeTemplate)]
     #[template(resource = "/dev/alextren/Spot/components/song.ui")]
@@ -59,9 +61,10 @@ mod imp {
     }
 
     lazy_static! {
-        static ref PROPERTIES: [glib::ParamSpec; 2] = [
+        static ref PROPERTIES: [glib::ParamSpec; 3] = [
             glib::ParamSpecBoolean::builder("playing").build(),
-            glib::ParamSpecBoolean::builder("selected").build()
+            glib::ParamSpecBoolean::builder("selected").build(),
+            glib::ParamSpecBoolean::builder("playable").build()
         ];
     }
 
@@ -88,6 +91,20 @@ mod imp {
                         .expect("type conformity checked by `Object::set_property`");
                     self.song_checkbox.set_active(is_selected);
                 }
+                "playable" => {
+                    let is_playable = value
+                        .get()
+                        .expect("type conformity checked by `Object::set_property`");
+                    let obj = self.obj();
+                    if is_playable {
+                        obj.remove_css_class(UNPLAYABLE_CLASS);
+                        obj.set_tooltip_text(None);
+                    } else {
+                        obj.add_css_class(UNPLAYABLE_CLASS);
+                        // translators: Tooltip shown over a track that can't be played, either because it's not available in the user's country or because it failed to load.
+                        obj.set_tooltip_text(Some(&gettext("This track is unavailable")));
+                    }
+                }
                 _ => unimplemented!(),
             }
         }
@@ -96,6 +113,7 @@ mod imp {
             match pspec.name() {
                 "playing" => self.obj().has_css_class(SONG_CLASS).to_value(),
                 "selected" => self.song_checkbox.is_active().to_value(),
+                "playable" => (!self.obj().has_css_class(UNPLAYABLE_CLASS)).to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -172,6 +190,7 @@ impl SongWidget {
         model.bind_duration(&*widget.song_length, "label");
         model.bind_playing(self, "playing");
         model.bind_selected(self, "selected");
+        model.bind_playable(self, "playable");
 
         self.set_show_cover(show_cover);
         if show_cover {
@@ -19,6 +19,19 @@ impl SongDescription {
         queue
     }
 
+    pub fn make_play_next_action(
+        &self,
+        dispatcher: Box<dyn ActionDispatcher>,
+        name: Option<&str>,
+    ) -> SimpleAction {
+        let play_next = SimpleAction::new(name.unwrap_or("play_next"), None);
+        let song = self.clone();
+        play_next.connect_activate(move |_, _| {
+            dispatcher.dispatch(PlaybackAction::PlayNext(vec![song.clone()]).into());
+        });
+        play_next
+    }
+
     pub fn make_dequeue_action(
         &self,
         dispatcher: Box<dyn ActionDispatcher>,
@@ -32,6 +45,20 @@ impl SongDescription {
         dequeue
     }
 
+    // Only from what was queued by the user, see PlaybackAction::DequeueNextUp
+    pub fn make_dequeue_next_up_action(
+        &self,
+        dispatcher: Box<dyn ActionDispatcher>,
+        name: Option<&str>,
+    ) -> SimpleAction {
+        let dequeue = SimpleAction::new(name.unwrap_or("dequeue"), None);
+        let track_id = self.id.clone();
+        dequeue.connect_activate(move |_, _| {
+            dispatcher.dispatch(PlaybackAction::DequeueNextUp(track_id.clone()).into());
+        });
+        dequeue
+    }
+
     pub fn make_link_action(&self, name: Option<&str>) -> SimpleAction {
         let track_id = self.id.clone();
         let copy_link = SimpleAction::new(name.unwrap_or("copy_link"), None);
@@ -12,6 +12,7 @@ use crate::app::components::{
 };
 use crate::app::dispatch::Worker;
 use crate::app::loader::ImageLoader;
+use crate::app::playlist_file::PlaylistFormat;
 use crate::app::state::{PlaybackEvent, SelectionEvent};
 use crate::app::{AppEvent, BrowserEvent};
 use libadwaita::subclass::prelude::BinImpl;
@@ -157,6 +158,13 @@ impl PlaylistDetailsWidget {
         self.imp().headerbar.connect_edit(f);
     }
 
+    pub fn connect_export<F>(&self, f: F)
+    where
+        F: Fn(PlaylistFormat) + 'static,
+    {
+        self.imp().headerbar.connect_export(f);
+    }
+
     pub fn connect_cancel<F>(&self, f: F)
     where
         F: Fn() + 'static,
@@ -232,6 +240,10 @@ impl PlaylistDetails {
             model.enable_selection();
         }));
 
+        widget.connect_export(clone!(@weak model, @weak widget => move |format| {
+            model.export(widget.upcast_ref(), format);
+        }));
+
         widget.connect_cancel(clone!(@weak model => move || model.disable_selection()));
         widget.connect_done(clone!(@weak model => move |n| {
             model.disable_selection();
@@ -5,8 +5,9 @@ use std::ops::Deref;
 use std::rc::Rc;
 
 use crate::api::SpotifyApiError;
-use crate::app::components::{labels, PlaylistModel};
+use crate::app::components::{export_songs, labels, PlaylistModel};
 use crate::app::models::*;
+use crate::app::playlist_file::PlaylistFormat;
 use crate::app::state::SelectionContext;
 use crate::app::state::{BrowserAction, PlaybackAction, SelectionAction, SelectionState};
 use crate::app::AppState;
@@ -147,6 +148,23 @@ impl PlaylistDetailsModel {
         self.dispatcher
             .dispatch(BrowserAction::NavigationPop.into());
     }
+
+    pub fn export(&self, parent: &gtk::Widget, format: PlaylistFormat) {
+        let name = if let Some(info) = self.get_playlist_info() {
+            info.title.clone()
+        } else {
+            return;
+        };
+        export_songs(
+            parent,
+            &self.app_model,
+            self.dispatcher.box_clone(),
+            format,
+            name,
+            SongsSource::Playlist(self.id.clone()),
+            &self.song_list_model(),
+        );
+    }
 }
 
 impl PlaylistModel for PlaylistDetailsModel {
@@ -190,6 +208,7 @@ impl PlaylistModel for PlaylistDetailsModel {
         group.add_action(&song.make_album_action(self.dispatcher.box_clone(), None));
         group.add_action(&song.make_link_action(None));
         group.add_action(&song.make_queue_action(self.dispatcher.box_clone(), None));
+        group.add_action(&song.make_play_next_action(self.dispatcher.box_clone(), None));
 
         Some(group.upcast())
     }
@@ -208,6 +227,7 @@ impl PlaylistModel for PlaylistDetailsModel {
         }
 
         menu.append(Some(&*labels::COPY_LINK), Some("song.copy_link"));
+        menu.append(Some(&*labels::PLAY_NEXT), Some("song.play_next"));
         menu.append(Some(&*labels::ADD_TO_QUEUE), Some("song.queue"));
 
         Some(menu.upcast())
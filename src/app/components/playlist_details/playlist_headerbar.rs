@@ -3,6 +3,9 @@ use gtk::subclass::prelude::*;
 use gtk::CompositeTemplate;
 use libadwaita::subclass::prelude::BinImpl;
 
+use crate::app::components::setup_export_button;
+use crate::app::playlist_file::PlaylistFormat;
+
 mod imp {
 
     use super::*;
@@ -25,6 +28,9 @@ mod imp {
         #[template_child]
         pub edit: TemplateChild<gtk::Button>,
 
+        #[template_child]
+        pub export: TemplateChild<gtk::MenuButton>,
+
         #[template_child]
         pub ok: TemplateChild<gtk::Button>,
 
@@ -85,6 +91,13 @@ impl PlaylistHeaderBarWidget {
         self.imp().edit.connect_clicked(move |_| f());
     }
 
+    pub fn connect_export<F>(&self, f: F)
+    where
+        F: Fn(PlaylistFormat) + 'static,
+    {
+        setup_export_button(&self.imp().export, f);
+    }
+
     pub fn connect_ok<F>(&self, f: F)
     where
         F: Fn() + 'static,
@@ -0,0 +1,58 @@
+use futures::channel::mpsc::UnboundedSender;
+use gettextrs::*;
+use std::sync::Arc;
+
+use crate::api::{SpotifyApiClient, SpotifyResult};
+use crate::app::models::{SongBatch, SongDescription};
+use crate::app::AppAction;
+
+// Tracks can only be added to a playlist 100 at a time
+const ADD_TO_PLAYLIST_CHUNK_SIZE: usize = 100;
+
+// Fill in the songs of a list that weren't loaded yet, from where their batch starts in that list
+// (which isn't the offset of the batch if songs were removed or inserted before it)
+pub fn fill_batch<T>(
+    items: &mut [Option<T>],
+    start: usize,
+    song_batch: SongBatch,
+    map: impl Fn(SongDescription) -> T,
+) {
+    for (i, song) in song_batch.songs.into_iter().enumerate() {
+        if let Some(item) = items.get_mut(start + i) {
+            *item = Some(map(song));
+        }
+    }
+}
+
+// Creates a playlist with these tracks, sending the fraction added so far to `progress`
+// Once created, the playlist is dispatched even if some tracks could not be added, so the error is reported after it
+pub async fn create_playlist_with_tracks(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    name: &str,
+    user_id: &str,
+    uris: Vec<String>,
+    progress: Option<UnboundedSender<f64>>,
+) -> SpotifyResult<Vec<AppAction>> {
+    let playlist = api.create_new_playlist(name, user_id).await?;
+    let total = uris.len().max(1) as f64;
+    for (i, chunk) in uris.chunks(ADD_TO_PLAYLIST_CHUNK_SIZE).enumerate() {
+        let added = api
+            .add_to_playlist(&playlist.id, chunk.to_vec(), None)
+            .await;
+        if let Err(err) = added {
+            error!("Could not add tracks to playlist: {}", err);
+            return Ok(vec![
+                AppAction::CreatePlaylist(playlist),
+                AppAction::ShowNotification(gettext(
+                    // translators: Shown when a playlist was created, but adding tracks to it failed halfway
+                    "Some tracks could not be added to the playlist",
+                )),
+            ]);
+        }
+        if let Some(progress) = progress.as_ref() {
+            let added = i * ADD_TO_PLAYLIST_CHUNK_SIZE + chunk.len();
+            let _ = progress.unbounded_send(added as f64 / total);
+        }
+    }
+    Ok(vec![AppAction::CreatePlaylist(playlist)])
+}
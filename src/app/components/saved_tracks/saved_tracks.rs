@@ -4,7 +4,8 @@ use gtk::CompositeTemplate;
 use std::rc::Rc;
 
 use super::SavedTracksModel;
-use crate::app::components::{Component, EventListener, Playlist};
+use crate::app::components::{setup_export_button, Component, EventListener, Playlist};
+use crate::app::playlist_file::PlaylistFormat;
 use crate::app::state::LoginEvent;
 use crate::app::{AppEvent, Worker};
 use libadwaita::subclass::prelude::BinImpl;
@@ -21,6 +22,9 @@ mod imp {
 
         #[template_child]
         pub scrolled_window: TemplateChild<gtk::ScrolledWindow>,
+
+        #[template_child]
+        pub export: TemplateChild<gtk::MenuButton>,
     }
 
     #[glib::object_subclass]
@@ -65,6 +69,13 @@ impl SavedTracksWidget {
             });
     }
 
+    fn connect_export<F>(&self, f: F)
+    where
+        F: Fn(PlaylistFormat) + 'static,
+    {
+        setup_export_button(&self.imp().export, f);
+    }
+
     fn song_list_widget(&self) -> &gtk::ListView {
         self.imp().song_list.as_ref()
     }
@@ -84,6 +95,10 @@ impl SavedTracks {
             model.load_more();
         }));
 
+        widget.connect_export(clone!(@weak model, @weak widget => move |format| {
+            model.export(widget.upcast_ref(), format);
+        }));
+
         let playlist = Playlist::new(widget.song_list_widget().clone(), model.clone(), worker);
 
         Self {
@@ -1,10 +1,12 @@
+use gettextrs::gettext;
 use gio::prelude::*;
 use gio::SimpleActionGroup;
 use std::ops::Deref;
 use std::rc::Rc;
 
-use crate::app::components::{labels, PlaylistModel};
+use crate::app::components::{export_songs, labels, PlaylistModel};
 use crate::app::models::*;
+use crate::app::playlist_file::PlaylistFormat;
 use crate::app::state::SelectionContext;
 use crate::app::state::{PlaybackAction, SelectionAction, SelectionState};
 use crate::app::{ActionDispatcher, AppAction, AppModel, BatchQuery, BrowserAction, SongsSource};
@@ -53,6 +55,18 @@ impl SavedTracksModel {
         }));
         Some(())
     }
+
+    pub fn export(&self, parent: &gtk::Widget, format: PlaylistFormat) {
+        export_songs(
+            parent,
+            &self.app_model,
+            self.dispatcher.box_clone(),
+            format,
+            gettext("Saved tracks"),
+            SongsSource::SavedTracks,
+            &self.song_list_model(),
+        );
+    }
 }
 
 impl PlaylistModel for SavedTracksModel {
@@ -37,6 +37,10 @@ impl SelectionToolbarModel {
         self.dispatcher.dispatch(AppAction::QueueSelection);
     }
 
+    pub fn play_next_selection(&self) {
+        self.dispatcher.dispatch(AppAction::PlayNextSelection);
+    }
+
     fn dequeue_selection(&self) {
         self.dispatcher.dispatch(AppAction::DequeueSelection);
     }
@@ -106,7 +110,7 @@ impl SelectionToolbarModel {
             .collect();
         self.dispatcher
             .call_spotify_and_dispatch(move || async move {
-                api.add_to_playlist(&id, uris).await?;
+                api.add_to_playlist(&id, uris, None).await?;
                 Ok(SelectionAction::Clear.into())
             })
     }
@@ -142,6 +146,7 @@ impl SelectionToolbar {
         widget.connect_move_up(clone!(@weak model => move || model.move_up_selection()));
         widget.connect_move_down(clone!(@weak model => move || model.move_down_selection()));
         widget.connect_queue(clone!(@weak model => move || model.queue_selection()));
+        widget.connect_play_next(clone!(@weak model => move || model.play_next_selection()));
         widget.connect_remove(clone!(@weak model => move || model.remove_selection()));
         widget.connect_save(clone!(@weak model => move || model.save_selection()));
         Self { model, widget }
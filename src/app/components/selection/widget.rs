@@ -32,6 +32,9 @@ mod imp {
         #[template_child]
         pub queue: TemplateChild<gtk::Button>,
 
+        #[template_child]
+        pub play_next: TemplateChild<gtk::Button>,
+
         #[template_child]
         pub save: TemplateChild<gtk::Button>,
     }
@@ -104,6 +107,13 @@ impl SelectionToolbarWidget {
         self.imp().queue.connect_clicked(move |_| f());
     }
 
+    pub fn connect_play_next<F>(&self, f: F)
+    where
+        F: Fn() + 'static,
+    {
+        self.imp().play_next.connect_clicked(move |_| f());
+    }
+
     pub fn connect_save<F>(&self, f: F)
     where
         F: Fn() + 'static,
@@ -128,6 +138,8 @@ impl SelectionToolbarWidget {
     pub fn set_queue(&self, state: SelectionToolState) {
         self.imp().queue.set_sensitive(state.sensitive());
         self.imp().queue.set_visible(state.visible());
+        self.imp().play_next.set_sensitive(state.sensitive());
+        self.imp().play_next.set_visible(state.visible());
     }
 
     pub fn set_add(&self, state: SelectionToolState) {
@@ -1,6 +1,6 @@
 use crate::app::components::EventListener;
 use crate::app::AppEvent;
-use crate::settings::SpotSettings;
+use crate::settings::ProxySettings;
 
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
@@ -28,17 +28,44 @@ mod imp {
         #[template_child]
         pub alsa_device_row: TemplateChild<libadwaita::ActionRow>,
 
+        #[template_child]
+        pub pipe_path: TemplateChild<gtk::Entry>,
+
+        #[template_child]
+        pub pipe_path_row: TemplateChild<libadwaita::ActionRow>,
+
+        #[template_child]
+        pub subprocess_command: TemplateChild<gtk::Entry>,
+
+        #[template_child]
+        pub subprocess_command_row: TemplateChild<libadwaita::ActionRow>,
+
         #[template_child]
         pub audio_backend: TemplateChild<libadwaita::ComboRow>,
 
         #[template_child]
         pub gapless_playback: TemplateChild<libadwaita::ActionRow>,
 
+        #[template_child]
+        pub balanced_shuffle: TemplateChild<libadwaita::ActionRow>,
+
         #[template_child]
         pub ap_port: TemplateChild<gtk::Entry>,
 
+        #[template_child]
+        pub proxy_url: TemplateChild<gtk::Entry>,
+
+        #[template_child]
+        pub proxy_username: TemplateChild<gtk::Entry>,
+
+        #[template_child]
+        pub proxy_password: TemplateChild<gtk::PasswordEntry>,
+
         #[template_child]
         pub theme: TemplateChild<libadwaita::ComboRow>,
+
+        // Whether the proxy password needs to be saved to the keyring
+        pub proxy_password_changed: std::cell::Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -73,6 +100,7 @@ impl SettingsWindow {
 
         window.bind_backend_and_device();
         window.bind_settings();
+        window.bind_proxy_password();
         window.connect_theme_select();
         window
     }
@@ -89,13 +117,32 @@ impl SettingsWindow {
             .downcast_ref::<libadwaita::ActionRow>()
             .unwrap();
 
+        let pipe_path_row = widget
+            .pipe_path_row
+            .downcast_ref::<libadwaita::ActionRow>()
+            .unwrap();
+        let subprocess_command_row = widget
+            .subprocess_command_row
+            .downcast_ref::<libadwaita::ActionRow>()
+            .unwrap();
+
         audio_backend
             .bind_property("selected", alsa_device_row, "visible")
             .transform_to(|_, value: u32| Some(value == 1))
             .build();
+        audio_backend
+            .bind_property("selected", pipe_path_row, "visible")
+            .transform_to(|_, value: u32| Some(value == 3))
+            .build();
+        audio_backend
+            .bind_property("selected", subprocess_command_row, "visible")
+            .transform_to(|_, value: u32| Some(value == 4))
+            .build();
 
         if audio_backend.selected() == 0 {
             alsa_device_row.set_visible(false);
+            pipe_path_row.set_visible(false);
+            subprocess_command_row.set_visible(false);
         }
     }
 
@@ -136,6 +183,17 @@ impl SettingsWindow {
         let alsa_device = widget.alsa_device.downcast_ref::<gtk::Entry>().unwrap();
         settings.bind("alsa-device", alsa_device, "text").build();
 
+        let pipe_path = widget.pipe_path.downcast_ref::<gtk::Entry>().unwrap();
+        settings.bind("pipe-path", pipe_path, "text").build();
+
+        let subprocess_command = widget
+            .subprocess_command
+            .downcast_ref::<gtk::Entry>()
+            .unwrap();
+        settings
+            .bind("subprocess-command", subprocess_command, "text")
+            .build();
+
         let audio_backend = widget
             .audio_backend
             .downcast_ref::<libadwaita::ComboRow>()
@@ -148,6 +206,8 @@ impl SettingsWindow {
                         "pulseaudio" => 0,
                         "alsa" => 1,
                         "gstreamer" => 2,
+                        "pipe" => 3,
+                        "subprocess" => 4,
                         _ => unreachable!(),
                     }
                     .to_value()
@@ -159,6 +219,8 @@ impl SettingsWindow {
                         0 => "pulseaudio",
                         1 => "alsa",
                         2 => "gstreamer",
+                        3 => "pipe",
+                        4 => "subprocess",
                         _ => unreachable!(),
                     }
                     .to_variant()
@@ -178,6 +240,14 @@ impl SettingsWindow {
             )
             .build();
 
+        settings
+            .bind(
+                "balanced-shuffle",
+                &widget.balanced_shuffle.activatable_widget().unwrap(),
+                "active",
+            )
+            .build();
+
         let ap_port = widget.ap_port.downcast_ref::<gtk::Entry>().unwrap();
         settings
             .bind("ap-port", ap_port, "text")
@@ -185,6 +255,14 @@ impl SettingsWindow {
             .set_mapping(|value, _| value.get::<u32>().ok().map(|u| u.to_variant()))
             .build();
 
+        let proxy_url = widget.proxy_url.downcast_ref::<gtk::Entry>().unwrap();
+        settings.bind("proxy-url", proxy_url, "text").build();
+
+        let proxy_username = widget.proxy_username.downcast_ref::<gtk::Entry>().unwrap();
+        settings
+            .bind("proxy-username", proxy_username, "text")
+            .build();
+
         let theme = widget.theme.downcast_ref::<libadwaita::ComboRow>().unwrap();
         settings
             .bind("theme-preference", theme, "selected")
@@ -213,6 +291,37 @@ impl SettingsWindow {
             .build();
     }
 
+    // The proxy password is kept in the keyring rather than in GSettings
+    fn bind_proxy_password(&self) {
+        let widget = self.imp();
+        let proxy_password = widget
+            .proxy_password
+            .downcast_ref::<gtk::PasswordEntry>()
+            .unwrap();
+        proxy_password.connect_changed(clone!(@weak self as _self => move |_| {
+            _self.imp().proxy_password_changed.set(true);
+        }));
+        // Filled in once the keyring answers, unless the user was faster
+        glib::MainContext::default().spawn_local(
+            clone!(@weak proxy_password, @weak self as _self => async move {
+                let password = ProxySettings::retrieve().await.password;
+                if !_self.imp().proxy_password_changed.get() {
+                    proxy_password.set_text(&password);
+                    _self.imp().proxy_password_changed.set(false);
+                }
+            }),
+        );
+    }
+
+    // The new proxy password, if it was changed
+    fn take_proxy_password(&self) -> Option<String> {
+        let widget = self.imp();
+        if !widget.proxy_password_changed.replace(false) {
+            return None;
+        }
+        Some(widget.proxy_password.text().to_string())
+    }
+
     fn connect_theme_select(&self) {
         let widget = self.imp();
         let theme = widget.theme.downcast_ref::<libadwaita::ComboRow>().unwrap();
@@ -232,13 +341,13 @@ impl SettingsWindow {
 
     fn connect_close<F>(&self, on_close: F)
     where
-        F: Fn() + 'static,
+        F: Fn(Option<String>) + 'static,
     {
         let window = self.upcast_ref::<libadwaita::Window>();
 
         window.connect_close_request(
             clone!(@weak self as _self => @default-return gtk::Inhibit(false), move |_| {
-                on_close();
+                on_close(_self.take_proxy_password());
                 gtk::Inhibit(false)
             }),
         );
@@ -254,12 +363,12 @@ impl Settings {
     pub fn new(parent: gtk::Window, model: SettingsModel) -> Self {
         let settings_window = SettingsWindow::new();
 
-        settings_window.connect_close(move || {
-            let new_settings = SpotSettings::new_from_gsettings().unwrap_or_default();
-            if model.settings().player_settings != new_settings.player_settings {
+        settings_window.connect_close(move |proxy_password| {
+            if model.settings().player_settings != model.new_settings().player_settings {
                 model.stop_player();
             }
             model.set_settings();
+            model.update_proxy(proxy_password);
         });
 
         Self {
@@ -1,6 +1,8 @@
+use crate::api::set_http_proxy;
+use crate::app::credentials::ProxyPassword;
 use crate::app::state::{PlaybackAction, SettingsAction};
 use crate::app::{ActionDispatcher, AppModel};
-use crate::settings::SpotSettings;
+use crate::settings::{ProxySettings, SpotSettings};
 use std::rc::Rc;
 
 pub struct SettingsModel {
@@ -29,4 +31,33 @@ impl SettingsModel {
         let state = self.app_model.get_state();
         state.settings.settings.clone()
     }
+
+    // What the settings are now in GSettings, along with the proxy we know of
+    pub fn new_settings(&self) -> SpotSettings {
+        let state = self.app_model.get_state();
+        SpotSettings::new_from_gsettings()
+            .unwrap_or_default()
+            .with_proxy(&state.settings.proxy)
+    }
+
+    // Saves the new proxy password (if it was changed) before resolving the proxy again, without blocking
+    pub fn update_proxy(&self, new_password: Option<String>) {
+        let dispatcher = self.dispatcher.box_clone();
+        let current_proxy = self.app_model.get_state().settings.proxy.clone();
+        glib::MainContext::default().spawn_local(async move {
+            if let Some(password) = new_password {
+                if let Err(e) = ProxyPassword::save(&password).await {
+                    warn!("Could not save proxy password: {}", e);
+                }
+            }
+            let proxy = ProxySettings::retrieve().await.resolve().await;
+            if proxy != current_proxy {
+                set_http_proxy(&proxy);
+                dispatcher.dispatch_many(vec![
+                    PlaybackAction::Stop.into(),
+                    SettingsAction::SetProxy(proxy).into(),
+                ]);
+            }
+        });
+    }
 }
@@ -0,0 +1,186 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::channel::mpsc::UnboundedSender;
+
+use crate::app::components::utils::{Clock, Debouncer};
+use crate::app::components::EventListener;
+use crate::app::state::{Device, PlaybackAction, PlaybackEvent, SleepTimer};
+use crate::app::{ActionDispatcher, AppEvent, AppModel};
+use crate::player::Command;
+
+// The volume is lowered in FADE_STEPS steps, FADE_STEP_MS apart (so the fade lasts 10s)
+const FADE_STEPS: u32 = 20;
+const FADE_STEP_MS: u32 = 500;
+
+struct SleepTimerModel {
+    app_model: Rc<AppModel>,
+    dispatcher: Box<dyn ActionDispatcher>,
+    command_sender: UnboundedSender<Command>,
+    fade_clock: Clock,
+    // Some(step) while fading out
+    fade_step: Cell<Option<u32>>,
+}
+
+impl SleepTimerModel {
+    fn is_local(&self) -> bool {
+        matches!(
+            *self.app_model.map_state(|s| s.playback.current_device()),
+            Device::Local
+        )
+    }
+
+    fn is_fading(&self) -> bool {
+        self.fade_step.get().is_some()
+    }
+
+    fn set_player_volume(&self, volume: f64) {
+        // If the player is gone, the player notifier will already have reported it
+        let _ = self
+            .command_sender
+            .unbounded_send(Command::PlayerSetVolume(volume));
+    }
+
+    fn fade_out(self: &Rc<Self>) {
+        let is_playing = self.app_model.get_state().playback.is_playing();
+        // Connect devices are simply paused
+        if !is_playing || !self.is_local() {
+            self.fall_asleep();
+            return;
+        }
+        self.fade_step.set(Some(0));
+        let model = Rc::downgrade(self);
+        self.fade_clock.start(move || {
+            if let Some(model) = model.upgrade() {
+                model.fade_tick();
+            }
+        });
+    }
+
+    fn fade_tick(&self) {
+        let step = self
+            .fade_step
+            .get()
+            .map(|step| step + 1)
+            .unwrap_or(FADE_STEPS);
+        self.fade_step.set(Some(step));
+        if step >= FADE_STEPS {
+            self.fade_clock.stop();
+            self.fall_asleep();
+        } else {
+            let volume = self.app_model.get_state().playback.volume();
+            let factor = 1.0 - f64::from(step) / f64::from(FADE_STEPS);
+            self.set_player_volume(volume * factor);
+        }
+    }
+
+    fn fall_asleep(&self) {
+        self.dispatcher.dispatch(PlaybackAction::Pause.into());
+        self.dispatcher
+            .dispatch(PlaybackAction::SetSleepTimer(None).into());
+    }
+
+    // Stops fading out, and puts the volume back where it was
+    fn cancel_fade(&self) {
+        if self.fade_step.take().is_some() {
+            self.fade_clock.stop();
+            let volume = self.app_model.get_state().playback.volume();
+            self.set_player_volume(volume);
+        }
+    }
+}
+
+// Pauses playback (after a short fade out) once the sleep timer runs out
+pub struct SleepTimerNotifier {
+    model: Rc<SleepTimerModel>,
+    debouncer: Debouncer,
+}
+
+impl SleepTimerNotifier {
+    pub fn new(
+        app_model: Rc<AppModel>,
+        dispatcher: Box<dyn ActionDispatcher>,
+        command_sender: UnboundedSender<Command>,
+    ) -> Self {
+        Self {
+            model: Rc::new(SleepTimerModel {
+                app_model,
+                dispatcher,
+                command_sender,
+                fade_clock: Clock::new(FADE_STEP_MS),
+                fade_step: Cell::new(None),
+            }),
+            debouncer: Debouncer::new(),
+        }
+    }
+
+    fn is_track_based(&self) -> bool {
+        matches!(
+            self.model.app_model.get_state().playback.sleep_timer(),
+            Some(SleepTimer::EndOfTrack) | Some(SleepTimer::EndOfAlbum)
+        )
+    }
+
+    fn cancel(&self) {
+        self.debouncer.cancel();
+        self.model.cancel_fade();
+    }
+
+    // (Re)computes when to start fading out
+    fn schedule(&self) {
+        self.cancel();
+        let remaining = {
+            let state = self.model.app_model.get_state();
+            // A fixed duration keeps running while paused, the end of a track obviously doesn't
+            let is_running = state.playback.is_playing()
+                || matches!(state.playback.sleep_timer(), Some(SleepTimer::After(_)));
+            state
+                .playback
+                .sleep_timer_remaining()
+                .filter(|_| is_running)
+        };
+        if let Some(remaining) = remaining {
+            let fade_duration = Duration::from_millis((FADE_STEPS * FADE_STEP_MS).into());
+            let delay = remaining.saturating_sub(fade_duration).as_millis() as u32;
+            let model = Rc::downgrade(&self.model);
+            self.debouncer.debounce(delay, move || {
+                if let Some(model) = model.upgrade() {
+                    model.fade_out();
+                }
+            });
+        }
+    }
+}
+
+impl EventListener for SleepTimerNotifier {
+    fn on_event(&mut self, event: &AppEvent) {
+        match event {
+            AppEvent::PlaybackEvent(PlaybackEvent::SleepTimerChanged(Some(_))) => self.schedule(),
+            AppEvent::PlaybackEvent(PlaybackEvent::SleepTimerChanged(None)) => self.cancel(),
+            AppEvent::PlaybackEvent(PlaybackEvent::PlaybackPaused)
+            | AppEvent::PlaybackEvent(PlaybackEvent::PlaybackStopped)
+                if self.model.is_fading() || self.is_track_based() =>
+            {
+                self.cancel()
+            }
+            // The track we were fading out just ended
+            AppEvent::PlaybackEvent(PlaybackEvent::TrackChanged(_))
+                if self.model.is_fading() && self.is_track_based() =>
+            {
+                self.model.fade_clock.stop();
+                self.model.fall_asleep();
+            }
+            AppEvent::PlaybackEvent(PlaybackEvent::TrackChanged(_))
+            | AppEvent::PlaybackEvent(PlaybackEvent::TrackSeeked(_))
+            | AppEvent::PlaybackEvent(PlaybackEvent::SeekSynced(_))
+            | AppEvent::PlaybackEvent(PlaybackEvent::PlaybackResumed)
+            | AppEvent::PlaybackEvent(PlaybackEvent::PlaylistChanged)
+                if self.is_track_based() && !self.model.is_fading() =>
+            {
+                self.schedule()
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use gettextrs::*;
+use std::rc::Rc;
+
+use crate::api::SpotifyApiError;
+use crate::app::components::EventListener;
+use crate::app::models::SongDescription;
+use crate::app::state::{BrowserAction, Inverse, LoginAction};
+use crate::app::{ActionDispatcher, AppAction, AppEvent, AppModel};
+
+// Group tracks with consecutive positions, so that each run can be put back in one call
+fn runs(tracks: Vec<(usize, SongDescription)>) -> Vec<(usize, Vec<String>)> {
+    let mut runs: Vec<(usize, Vec<String>)> = vec![];
+    for (position, song) in tracks {
+        match runs.last_mut() {
+            Some((start, uris)) if *start + uris.len() == position => uris.push(song.uri),
+            _ => runs.push((position, vec![song.uri])),
+        }
+    }
+    runs
+}
+
+// Performs the API calls needed to undo something, then updates the state accordingly
+pub struct UndoHandler {
+    app_model: Rc<AppModel>,
+    dispatcher: Box<dyn ActionDispatcher>,
+}
+
+impl UndoHandler {
+    pub fn new(app_model: Rc<AppModel>, dispatcher: Box<dyn ActionDispatcher>) -> Self {
+        Self {
+            app_model,
+            dispatcher,
+        }
+    }
+
+    fn undo(&self, inverse: Inverse) {
+        let api = self.app_model.get_spotify();
+        match inverse {
+            Inverse::AddToPlaylist(id, tracks) => {
+                self.dispatcher
+                    .call_spotify_and_dispatch_many(move || async move {
+                        // Positions are those before removal: going in order keeps them right
+                        let mut done = 0;
+                        let mut result = Ok(());
+                        for (position, uris) in runs(tracks.clone()) {
+                            let count = uris.len();
+                            result = api.add_to_playlist(&id, uris, Some(position)).await;
+                            if result.is_err() {
+                                break;
+                            }
+                            done += count;
+                        }
+                        let mut tracks = tracks;
+                        let remaining = tracks.split_off(done);
+                        let mut actions = vec![];
+                        if !tracks.is_empty() {
+                            actions.push(
+                                BrowserAction::InsertPlaylistTracks(id.clone(), tracks).into(),
+                            );
+                        }
+                        if let Err(err) = result {
+                            actions.append(&mut failed(err, Inverse::AddToPlaylist(id, remaining)));
+                        }
+                        Ok(actions)
+                    })
+            }
+            Inverse::SaveTracks(tracks) => {
+                self.dispatcher
+                    .call_spotify_and_dispatch_many(move || async move {
+                        let ids = tracks.iter().map(|s| s.id.clone()).collect();
+                        Ok(match api.save_tracks(ids).await {
+                            Ok(_) => vec![BrowserAction::SaveTracks(tracks).into()],
+                            Err(err) => failed(err, Inverse::SaveTracks(tracks)),
+                        })
+                    })
+            }
+            Inverse::SaveAlbum(id) => {
+                self.dispatcher
+                    .call_spotify_and_dispatch_many(move || async move {
+                        Ok(match api.save_album(&id).await {
+                            Ok(album) => vec![BrowserAction::SaveAlbum(Box::new(album)).into()],
+                            Err(err) => failed(err, Inverse::SaveAlbum(id)),
+                        })
+                    })
+            }
+            // Put back by the state directly, no API involved
+            Inverse::Requeue(_) => {}
+        }
+    }
+}
+
+// Keep what could not be undone, so that it can be tried again
+fn failed(err: SpotifyApiError, inverse: Inverse) -> Vec<AppAction> {
+    let mut actions = vec![];
+    if let SpotifyApiError::InvalidToken = err {
+        actions.push(LoginAction::RefreshToken.into());
+    } else {
+        error!("Spotify API error: {}", err);
+    }
+    // translators: This notification is shown when putting back removed tracks or albums failed. The user can try again.
+    actions.push(AppAction::ShowNotification(gettext(
+        "Could not undo, try again later.",
+    )));
+    actions.push(AppAction::RecordUndo(inverse));
+    actions
+}
+
+impl EventListener for UndoHandler {
+    fn on_event(&mut self, event: &AppEvent) {
+        if let AppEvent::UndoRequested(inverse) = event {
+            self.undo(inverse.clone());
+        }
+    }
+}
@@ -38,6 +38,14 @@ impl UserMenu {
             logout
         });
 
+        action_group.add_action(&{
+            let import_action = SimpleAction::new("import_playlist", None);
+            import_action.connect_activate(clone!(@weak model, @weak user_button => move |_, _| {
+                model.import_playlist(user_button.upcast_ref());
+            }));
+            import_action
+        });
+
         action_group.add_action(&{
             let settings_action = SimpleAction::new("settings", None);
             settings_action.connect_activate(clone!(@weak model => move |_, _| {
@@ -71,6 +79,11 @@ impl UserMenu {
         if let Some(username) = self.model.username() {
             let user_menu = gio::Menu::new();
             // translators: This is a menu entry.
+            user_menu.append(
+                Some(&gettext("Import playlist…")),
+                Some("menu.import_playlist"),
+            );
+            // translators: This is a menu entry.
             user_menu.append(Some(&gettext("Log out")), Some("menu.logout"));
             menu.insert_section(0, Some(&username), &user_menu);
         }
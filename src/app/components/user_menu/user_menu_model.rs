@@ -1,4 +1,5 @@
 use crate::api::clear_user_cache;
+use crate::app::components::import_playlist;
 use crate::app::credentials::Credentials;
 use crate::app::state::{LoginAction, PlaybackAction};
 use crate::app::{ActionDispatcher, AppModel};
@@ -32,6 +33,10 @@ impl UserMenuModel {
         }));
     }
 
+    pub fn import_playlist(&self, parent: &gtk::Widget) {
+        import_playlist(parent, self.app_model.clone(), self.dispatcher.box_clone());
+    }
+
     pub fn fetch_user_playlists(&self) {
         let api = self.app_model.get_spotify();
         if let Some(current_user) = self.username() {
@@ -74,6 +74,12 @@ impl Debouncer {
             previous_source.remove();
         }
     }
+
+    pub fn cancel(&self) {
+        if let Some(source) = self.0.take() {
+            source.remove();
+        }
+    }
 }
 
 pub struct Animator<EasingFn> {
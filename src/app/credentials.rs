@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, time::SystemTime};
 
 static SPOT_ATTR: &str = "spot_credentials";
+static PROXY_ATTR: &str = "spot_proxy_password";
 
 // I'm not sure this is the right way to make credentials identifiable, but hey, it works
 fn make_attributes() -> HashMap<&'static str, &'static str> {
@@ -11,6 +12,12 @@ fn make_attributes() -> HashMap<&'static str, &'static str> {
     attributes
 }
 
+fn make_proxy_attributes() -> HashMap<&'static str, &'static str> {
+    let mut attributes = HashMap::new();
+    attributes.insert(PROXY_ATTR, "yes");
+    attributes
+}
+
 // A (statically accessed) wrapper around the DBUS Secret Service
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct Credentials {
@@ -74,3 +81,37 @@ impl Credentials {
         Ok(())
     }
 }
+
+// The password of the proxy set in the preferences, kept in the keyring as well
+pub struct ProxyPassword;
+
+impl ProxyPassword {
+    pub async fn retrieve() -> Result<String, Error> {
+        let service = SecretService::connect(EncryptionType::Dh).await?;
+        let collection = service.get_default_collection().await?;
+        if collection.is_locked().await? {
+            collection.unlock().await?;
+        }
+        let items = collection.search_items(make_proxy_attributes()).await?;
+        let item = items.get(0).ok_or(Error::NoResult)?.get_secret().await?;
+        String::from_utf8(item).map_err(|_| Error::Unavailable)
+    }
+
+    pub async fn save(password: &str) -> Result<(), Error> {
+        let service = SecretService::connect(EncryptionType::Dh).await?;
+        let collection = service.get_default_collection().await?;
+        if collection.is_locked().await? {
+            collection.unlock().await?;
+        }
+        collection
+            .create_item(
+                "Spot Proxy Password",
+                make_proxy_attributes(),
+                password.as_bytes(),
+                true,
+                "text/plain",
+            )
+            .await?;
+        Ok(())
+    }
+}
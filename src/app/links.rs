@@ -0,0 +1,137 @@
+use url::Url;
+
+// What a Spotify URI (spotify:album:id) or link (https://open.spotify.com/album/id) points to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpotifyLink {
+    Track(String),
+    Album(String),
+    Playlist(String),
+    Artist(String),
+    User(String),
+}
+
+impl SpotifyLink {
+    pub fn parse(uri: &str) -> Option<Self> {
+        if let Some(path) = uri.strip_prefix("spotify:") {
+            // Might start with /// because of https://gitlab.gnome.org/GNOME/glib/-/issues/1886/
+            let path = path.strip_prefix("///").unwrap_or(path);
+            Self::from_segments(&path.split(':').collect::<Vec<_>>())
+        } else {
+            let url = Url::parse(uri).ok()?;
+            if url.scheme() != "https" || url.host_str() != Some("open.spotify.com") {
+                return None;
+            }
+            // Localized links look like https://open.spotify.com/intl-fr/album/id
+            let segments: Vec<&str> = url
+                .path_segments()?
+                .skip_while(|s| s.starts_with("intl-"))
+                .collect();
+            Self::from_segments(&segments)
+        }
+    }
+
+    fn from_segments(segments: &[&str]) -> Option<Self> {
+        match segments {
+            // Legacy playlist links, from back when playlists belonged to users
+            ["user", _, "playlist", id, ..] => Self::from_parts("playlist", id),
+            [kind, id, ..] => Self::from_parts(kind, id),
+            _ => None,
+        }
+    }
+
+    fn from_parts(kind: &str, id: &str) -> Option<Self> {
+        if id.is_empty() {
+            return None;
+        }
+        let id = id.to_string();
+        match kind {
+            "track" => Some(Self::Track(id)),
+            "album" => Some(Self::Album(id)),
+            "playlist" => Some(Self::Playlist(id)),
+            "artist" => Some(Self::Artist(id)),
+            "user" => Some(Self::User(id)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uri() {
+        assert_eq!(
+            SpotifyLink::parse("spotify:track:6rqhFgbbKwnb9MLmUQDhG6"),
+            Some(SpotifyLink::Track("6rqhFgbbKwnb9MLmUQDhG6".to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse("spotify:album:1"),
+            Some(SpotifyLink::Album("1".to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse("spotify:user:1"),
+            Some(SpotifyLink::User("1".to_string()))
+        );
+        assert_eq!(SpotifyLink::parse("spotify:album:"), None);
+        assert_eq!(SpotifyLink::parse("spotify:album"), None);
+        assert_eq!(SpotifyLink::parse("spotify:episode:1"), None);
+        assert_eq!(SpotifyLink::parse("file:///album/1"), None);
+    }
+
+    #[test]
+    fn test_parse_uri_from_glib() {
+        assert_eq!(
+            SpotifyLink::parse("spotify:///artist:1"),
+            Some(SpotifyLink::Artist("1".to_string()))
+        );
+        assert_eq!(SpotifyLink::parse("spotify:///"), None);
+    }
+
+    #[test]
+    fn test_parse_legacy_playlist_uri() {
+        assert_eq!(
+            SpotifyLink::parse("spotify:user:spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+            Some(SpotifyLink::Playlist("37i9dQZF1DXcBWIGoYBM5M".to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse("https://open.spotify.com/user/spotify/playlist/1"),
+            Some(SpotifyLink::Playlist("1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            SpotifyLink::parse("https://open.spotify.com/playlist/37i9dQZF1DXcBWIGoYBM5M"),
+            Some(SpotifyLink::Playlist("37i9dQZF1DXcBWIGoYBM5M".to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse("https://open.spotify.com/artist/1?si=abc"),
+            Some(SpotifyLink::Artist("1".to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse("https://open.spotify.com/track/1/"),
+            Some(SpotifyLink::Track("1".to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse("https://open.spotify.com/user/1"),
+            Some(SpotifyLink::User("1".to_string()))
+        );
+        assert_eq!(SpotifyLink::parse("https://open.spotify.com/"), None);
+        assert_eq!(SpotifyLink::parse("http://open.spotify.com/album/1"), None);
+        assert_eq!(SpotifyLink::parse("https://example.com/artist/1"), None);
+    }
+
+    #[test]
+    fn test_parse_localized_url() {
+        assert_eq!(
+            SpotifyLink::parse("https://open.spotify.com/intl-fr/track/1?si=abc"),
+            Some(SpotifyLink::Track("1".to_string()))
+        );
+        assert_eq!(
+            SpotifyLink::parse("https://open.spotify.com/intl-pt-BR/album/1"),
+            Some(SpotifyLink::Album("1".to_string()))
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use crate::api::cache::*;
+use crate::api::make_http_client;
 use gdk_pixbuf::traits::PixbufLoaderExt;
 use gdk_pixbuf::{Pixbuf, PixbufLoader};
-use isahc::config::Configurable;
-use isahc::{AsyncBody, AsyncReadResponseExt, HttpClient, Response};
+use isahc::{AsyncBody, AsyncReadResponseExt, Response};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hasher;
 use std::io::{Error, ErrorKind, Write};
+use std::path::PathBuf;
 
 // A wrapper to be able to implement the Write trait on a PixbufLoader
 struct LocalPixbufLoader<'a>(&'a PixbufLoader);
@@ -47,14 +48,32 @@ impl ImageLoader {
     }
 
     async fn get_image(url: &str) -> Option<Response<AsyncBody>> {
-        let mut builder = HttpClient::builder();
-        if cfg!(debug_assertions) {
-            builder = builder.ssl_options(isahc::config::SslOption::DANGER_ACCEPT_INVALID_CERTS);
-        }
-        let client = builder.build().unwrap();
+        let client = make_http_client();
         client.get_async(url).await.ok()
     }
 
+    // Read an image from cache, or get it over HTTP (and save it to cache)
+    async fn read_or_fetch(&self, url: &str, resource: &str) -> Option<Vec<u8>> {
+        // Try to read from cache first, ignoring possible expiry
+        match self
+            .cache
+            .read_cache_file(resource, CachePolicy::IgnoreExpiry)
+            .await
+        {
+            Ok(CacheFile::Fresh(buffer, _)) => Some(buffer),
+            _ => {
+                let mut resp = Self::get_image(url).await?;
+                let mut buffer = vec![];
+                resp.copy_to(&mut buffer).await.ok()?;
+                self.cache
+                    .write_cache_file(resource, &buffer[..], CacheExpiry::Never)
+                    .await
+                    .ok()?;
+                Some(buffer)
+            }
+        }
+    }
+
     pub async fn load_remote(
         &self,
         url: &str,
@@ -67,34 +86,18 @@ impl ImageLoader {
         pixbuf_loader.set_size(width, height);
         let mut loader = LocalPixbufLoader(&pixbuf_loader);
 
-        // Try to read from cache first, ignoring possible expiry
-        match self
-            .cache
-            .read_cache_file(&resource[..], CachePolicy::IgnoreExpiry)
-            .await
-        {
-            // Write content of cache file to the pixbuf loader if the cache contained something
-            Ok(CacheFile::Fresh(buffer, _)) => {
-                loader.write_all(&buffer[..]).ok()?;
-            }
-            // Otherwise, get image over HTTP
-            _ => {
-                if let Some(mut resp) = Self::get_image(url).await {
-                    let mut buffer = vec![];
-                    // Copy the image to a buffer...
-                    resp.copy_to(&mut buffer).await.ok()?;
-                    // ... copy the buffer to the loader...
-                    loader.write_all(&buffer[..]).ok()?;
-                    // ... but also save that buffer to cache
-                    self.cache
-                        .write_cache_file(&resource[..], &buffer[..], CacheExpiry::Never)
-                        .await
-                        .ok()?;
-                }
-            }
-        };
+        if let Some(buffer) = self.read_or_fetch(url, &resource).await {
+            loader.write_all(&buffer[..]).ok()?;
+        }
 
         pixbuf_loader.close().ok()?;
         pixbuf_loader.pixbuf()
     }
+
+    // Make sure an image is cached, and get the path to the cached file (for consumers outside the app)
+    pub async fn cache_remote(&self, url: &str, ext: &str) -> Option<PathBuf> {
+        let resource = Self::resource_for(url, ext);
+        self.read_or_fetch(url, &resource).await?;
+        Some(self.cache.path_for(&resource))
+    }
 }
@@ -24,9 +24,17 @@ pub use batch_loader::*;
 pub mod credentials;
 pub mod loader;
 
+pub mod links;
+pub use links::SpotifyLink;
+
 pub mod rng;
 pub use rng::LazyRandomIndex;
 
+pub mod playlist_file;
+
+// Playing links, from within the app or from outside of it
+pub mod play;
+
 // Where all the app logic happens
 pub struct App {
     settings: SpotSettings,
@@ -48,18 +56,36 @@ impl App {
         sender: UnboundedSender<AppAction>,
         worker: Worker,
     ) -> Self {
-        let state = AppState::new();
+        let mut state = AppState::new();
+        state.settings.settings = settings.clone();
         let spotify_client = Arc::new(CachedSpotifyClient::new());
         let model = Rc::new(AppModel::new(state, spotify_client));
 
+        let command_sender =
+            crate::player::start_player_service(settings.player_settings.clone(), sender.clone());
+
         // Non widget components
         let components: Vec<Box<dyn EventListener>> = vec![
             App::make_player_notifier(
                 Rc::clone(&model),
-                &settings,
                 Box::new(ActionDispatcherImpl::new(sender.clone(), worker.clone())),
+                command_sender.clone(),
                 sender.clone(),
             ),
+            // Must come after the player notifier, so that the volume is only restored once paused
+            App::make_sleep_timer(
+                Rc::clone(&model),
+                Box::new(ActionDispatcherImpl::new(sender.clone(), worker.clone())),
+                command_sender,
+            ),
+            App::make_playback_persistence(
+                Rc::clone(&model),
+                Box::new(ActionDispatcherImpl::new(sender.clone(), worker.clone())),
+            ),
+            App::make_undo_handler(
+                Rc::clone(&model),
+                Box::new(ActionDispatcherImpl::new(sender.clone(), worker.clone())),
+            ),
             App::make_dbus(Rc::clone(&model), sender.clone()),
         ];
 
@@ -114,8 +140,8 @@ impl App {
     // A component that listens to what's happening in the app, and translates it for the actual player
     fn make_player_notifier(
         app_model: Rc<AppModel>,
-        settings: &SpotSettings,
         dispatcher: Box<dyn ActionDispatcher>,
+        command_sender: UnboundedSender<crate::player::Command>,
         sender: UnboundedSender<AppAction>,
     ) -> Box<impl EventListener> {
         let api = app_model.get_spotify();
@@ -123,12 +149,41 @@ impl App {
             app_model,
             dispatcher,
             // Either communications with the librespot player
-            crate::player::start_player_service(settings.player_settings.clone(), sender.clone()),
+            command_sender,
             // or with a Spotify Connect device
             crate::connect::start_connect_server(api, sender),
         ))
     }
 
+    // Pauses playback when the sleep timer runs out
+    fn make_sleep_timer(
+        app_model: Rc<AppModel>,
+        dispatcher: Box<dyn ActionDispatcher>,
+        command_sender: UnboundedSender<crate::player::Command>,
+    ) -> Box<impl EventListener> {
+        Box::new(SleepTimerNotifier::new(
+            app_model,
+            dispatcher,
+            command_sender,
+        ))
+    }
+
+    // A component to save what's playing, and pick it up again on the next start
+    fn make_playback_persistence(
+        app_model: Rc<AppModel>,
+        dispatcher: Box<dyn ActionDispatcher>,
+    ) -> Box<impl EventListener> {
+        Box::new(PlaybackPersistence::new(app_model, dispatcher))
+    }
+
+    // Undoes what can't be undone by the state alone (removals from the library and playlists)
+    fn make_undo_handler(
+        app_model: Rc<AppModel>,
+        dispatcher: Box<dyn ActionDispatcher>,
+    ) -> Box<impl EventListener> {
+        Box::new(UndoHandler::new(app_model, dispatcher))
+    }
+
     // A component to handle anything DBUS related
     fn make_dbus(
         app_model: Rc<AppModel>,
@@ -237,6 +292,11 @@ impl App {
         Box::new(Notification::new(toast_overlay))
     }
 
+    // The command line is handled outside of the app, but needs to peek at its state
+    pub fn model(&self) -> Rc<AppModel> {
+        Rc::clone(&self.model)
+    }
+
     // Main handler called in a loop
     fn handle(&mut self, action: AppAction) {
         let starting = matches!(&action, &AppAction::Start);
@@ -3,6 +3,8 @@ use std::{
     str::FromStr,
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::app::SongsSource;
 
 // A batch of whatever
@@ -59,6 +61,7 @@ pub struct ArtistRef {
 pub struct AlbumRef {
     pub id: String,
     pub name: String,
+    pub artists: Vec<ArtistRef>,
 }
 
 #[derive(Clone, Debug)]
@@ -130,6 +133,11 @@ pub struct ConnectDevice {
     pub id: String,
     pub label: String,
     pub kind: ConnectDeviceKind,
+    // In percent, if the volume of the device can be changed
+    pub volume: Option<u8>,
+    pub is_active: bool,
+    // Restricted devices don't accept commands from the Web API
+    pub is_restricted: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -142,12 +150,27 @@ pub struct PlaylistSummary {
 pub struct SongDescription {
     pub id: String,
     pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
     pub uri: String,
     pub title: String,
     pub artists: Vec<ArtistRef>,
     pub album: AlbumRef,
     pub duration: u32,
     pub art: Option<String>,
+    // False if the track can't be played in the user's market, or failed to load
+    pub is_playable: bool,
+    // Why it can't be played, when Spotify tells us
+    pub restriction: Option<Restriction>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Restriction {
+    // Not available in the user's country
+    Market,
+    // Not available with the user's subscription
+    Product,
+    // Explicit content was disabled by the user
+    Explicit,
 }
 
 impl SongDescription {
@@ -246,19 +269,20 @@ pub struct UserDescription {
     pub playlists: Vec<PlaylistDescription>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RepeatMode {
     Song,
     Playlist,
     None,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ConnectPlayerState {
     pub is_playing: bool,
     pub source: Option<SongsSource>,
     pub current_song_id: Option<String>,
     pub progress_ms: u32,
+    pub duration_ms: u32,
     pub repeat: RepeatMode,
     pub shuffle: bool,
 }
@@ -270,6 +294,7 @@ impl Default for ConnectPlayerState {
             source: None,
             current_song_id: None,
             progress_ms: 0,
+            duration_ms: 0,
             repeat: RepeatMode::None,
             shuffle: false,
         }
@@ -290,10 +315,14 @@ mod tests {
             album: AlbumRef {
                 id: "".to_string(),
                 name: "".to_string(),
+                artists: vec![],
             },
             duration: 1000,
             art: None,
             track_number: None,
+            disc_number: None,
+            is_playable: true,
+            restriction: None,
         }
     }
 
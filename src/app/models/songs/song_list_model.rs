@@ -138,6 +138,18 @@ impl SongListModel {
         self.inner().needed_batch_for(i)
     }
 
+    pub fn missing_batches(&self) -> Vec<Batch> {
+        self.inner().missing_batches()
+    }
+
+    pub fn missing_batches_with_starts(&self) -> Vec<(Batch, usize)> {
+        self.inner().missing_batches_with_starts()
+    }
+
+    pub fn loaded_batches(&self) -> Vec<Batch> {
+        self.inner().loaded_batches()
+    }
+
     pub fn partial_len(&self) -> usize {
         self.inner().partial_len()
     }
@@ -156,6 +168,11 @@ impl SongListModel {
         SongListModelPending::new(Some(range), self)
     }
 
+    pub fn insert(&mut self, position: usize, songs: Vec<SongDescription>) -> SongListModelPending {
+        let range = self.inner_mut().insert(position, songs);
+        SongListModelPending::new(Some(range), self)
+    }
+
     pub fn find_index(&self, song_id: &str) -> Option<usize> {
         self.inner().find_index(song_id)
     }
@@ -27,6 +27,10 @@ impl SongModel {
         self.set_property("selected", is_selected);
     }
 
+    pub fn set_playable(&self, is_playable: bool) {
+        self.set_property("playable", is_playable);
+    }
+
     pub fn get_playing(&self) -> bool {
         self.property("playing")
     }
@@ -35,6 +39,10 @@ impl SongModel {
         self.property("selected")
     }
 
+    pub fn get_playable(&self) -> bool {
+        self.property("playable")
+    }
+
     pub fn get_id(&self) -> String {
         self.property("id")
     }
@@ -87,6 +95,14 @@ impl SongModel {
         );
     }
 
+    pub fn bind_playable(&self, o: &impl ObjectType, property: &str) {
+        self.imp().push_binding(
+            self.bind_property("playable", o, property)
+                .flags(glib::BindingFlags::DEFAULT | glib::BindingFlags::SYNC_CREATE)
+                .build(),
+        );
+    }
+
     pub fn unbind_all(&self) {
         self.imp().unbind_all(self);
     }
@@ -150,7 +166,7 @@ mod imp {
     }
 
     lazy_static! {
-        static ref PROPERTIES: [glib::ParamSpec; 8] = [
+        static ref PROPERTIES: [glib::ParamSpec; 9] = [
             glib::ParamSpecString::builder("id").read_only().build(),
             glib::ParamSpecUInt::builder("index").read_only().build(),
             glib::ParamSpecString::builder("title").read_only().build(),
@@ -167,6 +183,10 @@ mod imp {
             glib::ParamSpecBoolean::builder("selected")
                 .readwrite()
                 .build(),
+            // Backed by the song description, but can be unset if the song fails to load
+            glib::ParamSpecBoolean::builder("playable")
+                .readwrite()
+                .build(),
         ];
     }
 
@@ -197,6 +217,14 @@ mod imp {
                         is_selected,
                     });
                 }
+                "playable" => {
+                    let is_playable = value
+                        .get()
+                        .expect("type conformity checked by `Object::set_property`");
+                    if let Some(song) = self.song.borrow_mut().as_mut() {
+                        song.is_playable = is_playable;
+                    }
+                }
                 _ => unimplemented!(),
             }
         }
@@ -248,6 +276,13 @@ mod imp {
                     .to_value(),
                 "playing" => self.state.get().is_playing.to_value(),
                 "selected" => self.state.get().is_selected.to_value(),
+                "playable" => self
+                    .song
+                    .borrow()
+                    .as_ref()
+                    .expect("song set at constructor")
+                    .is_playable
+                    .to_value(),
                 _ => unimplemented!(),
             }
         }
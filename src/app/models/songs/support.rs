@@ -138,6 +138,11 @@ pub struct SongList {
     // Here a batch has an index (key) and a list of associated song ids
     // Why not a Vec? We could have batch 1, 2, NOT 3, then 4
     batches: HashMap<usize, Vec<String>>,
+    // With non contiguous batches, songs removed or inserted before a batch move it from where it was loaded
+    // This is how far each batch moved, so that we can keep the batch keys
+    offsets: HashMap<usize, isize>,
+    // How many songs were inserted (or removed, if negative) since batches were loaded, which their total doesn't know about
+    edits: isize,
     indexed_songs: HashMap<String, SongModel>,
 }
 
@@ -149,6 +154,8 @@ impl SongList {
             batch_size,
             last_batch_key: 0,
             batches: Default::default(),
+            offsets: Default::default(),
+            edits: 0,
             indexed_songs: Default::default(),
         }
     }
@@ -171,11 +178,69 @@ impl SongList {
     // How many songs are loaded, up to a given batch index
     fn estimated_len(&self, up_to_batch_index: usize) -> usize {
         let batches = &self.batches;
-        let batch_size = self.batch_size;
-        let batch_count = (0..up_to_batch_index)
-            .filter(move |i| batches.contains_key(i))
-            .count();
-        batch_size * batch_count
+        (0..up_to_batch_index)
+            .filter_map(move |i| batches.get(&i))
+            .map(Vec::len)
+            .sum()
+    }
+
+    // Whether the whole list is loaded, in which case batches can be recreated from scratch
+    // (a list with some batches still to load must keep its batch keys, they're where these batches come from)
+    fn is_fully_loaded(&self) -> bool {
+        self.total_loaded >= self.total
+    }
+
+    // Position of the first song of a batch
+    fn batch_start(&self, key: usize) -> usize {
+        let offset = self.offsets.get(&key).copied().unwrap_or(0);
+        ((key * self.batch_size) as isize + offset).max(0) as usize
+    }
+
+    // How far a batch has moved (or will have moved once loaded) because of songs removed or inserted before it
+    fn offset_for(&self, key: usize) -> isize {
+        if let Some(offset) = self.offsets.get(&key) {
+            return *offset;
+        }
+        // Like the next loaded batch did...
+        (key + 1..=self.last_batch_key)
+            .find(|key| self.batches.contains_key(key))
+            .map(|key| self.offsets.get(&key).copied().unwrap_or(0))
+            .or_else(|| {
+                // ...or like the previous one did, plus whatever was removed from or inserted in it
+                let (key, batch) = (0..key)
+                    .rev()
+                    .find_map(|key| Some((key, self.batches.get(&key)?)))?;
+                let offset = self.offsets.get(&key).copied().unwrap_or(0);
+                Some(offset + batch.len() as isize - self.batch_size as isize)
+            })
+            .unwrap_or(0)
+    }
+
+    // Where a batch starts, or will start once loaded
+    fn expected_batch_start(&self, key: usize) -> usize {
+        ((key * self.batch_size) as isize + self.offset_for(key)).max(0) as usize
+    }
+
+    // The batches that aren't loaded yet, with where they will start
+    fn unloaded_batches(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..)
+            .map(move |key| (key, self.expected_batch_start(key)))
+            .take_while(move |(_, start)| *start < self.total)
+            .filter(move |(key, _)| !self.batches.contains_key(key))
+    }
+
+    // The batch that contains the song at i, and where it is in that batch
+    fn locate(&self, i: usize) -> Option<(usize, usize)> {
+        (0..=self.last_batch_key)
+            .filter_map(|key| Some((key, self.batches.get(&key)?)))
+            .find_map(|(key, batch)| {
+                let start = self.batch_start(key);
+                if (start..start + batch.len()).contains(&i) {
+                    Some((key, i - start))
+                } else {
+                    None
+                }
+            })
     }
 
     // The theoretical len of the playlist, if we had all songs
@@ -184,10 +249,8 @@ impl SongList {
     }
 
     fn iter_ids_from(&self, i: usize) -> impl Iterator<Item = (usize, &'_ String)> {
-        let batch_size = self.batch_size;
-        let index = i / batch_size;
-        self.iter_range(index, self.last_batch_key)
-            .skip(i % batch_size)
+        self.iter_range(0, self.last_batch_key)
+            .skip_while(move |(pos, _)| *pos < i)
     }
 
     // Find the position of a song in the list
@@ -199,14 +262,12 @@ impl SongList {
 
     // Iterate over batches (in a given batch range), returning a tuple with the index of a song and its id
     fn iter_range(&self, a: usize, b: usize) -> impl Iterator<Item = (usize, &'_ String)> {
-        let batch_size = self.batch_size;
         let batches = &self.batches;
         (a..=b)
             .filter_map(move |i| batches.get_key_value(&i))
             .flat_map(move |(k, b)| {
-                b.iter()
-                    .enumerate()
-                    .map(move |(i, id)| (i + *k * batch_size, id))
+                let start = self.batch_start(*k);
+                b.iter().enumerate().map(move |(i, id)| (i + start, id))
             })
     }
 
@@ -232,6 +293,9 @@ impl SongList {
     }
 
     pub fn remove(&mut self, ids: &[String]) -> ListRangeUpdate {
+        if !self.is_fully_loaded() {
+            return self.remove_in_place(ids);
+        }
         let len = self.total_loaded;
         let mut batches = HashMap::<usize, Vec<String>>::default();
         self.iter_ids_from(0)
@@ -241,14 +305,38 @@ impl SongList {
                 Self::batches_add(&mut batches, self.batch_size, next);
             });
         self.last_batch_key = batches.len().saturating_sub(1);
+        // Some of these ids might not be in the list at all
+        let removed = len - batches.values().map(Vec::len).sum::<usize>();
         self.batches = batches;
-        let removed = ids.len();
+        self.offsets.clear();
+        self.edits -= removed as isize;
         self.total = self.total.saturating_sub(removed);
         self.total_loaded = self.total_loaded.saturating_sub(removed);
         // Lazy computation of the affected range, basically assume everything has changed
         ListRangeUpdate(0, len as i32, self.total_loaded as i32)
     }
 
+    // Removing songs when some batches are missing: we can't recreate all batches, but every song
+    // after a removed one still has to move down by one, including those in batches further down
+    fn remove_in_place(&mut self, ids: &[String]) -> ListRangeUpdate {
+        let len = self.total_loaded;
+        let mut removed = 0;
+        for key in 0..=self.last_batch_key {
+            if let Some(batch) = self.batches.get_mut(&key) {
+                if removed > 0 {
+                    *self.offsets.entry(key).or_default() -= removed as isize;
+                }
+                let batch_len = batch.len();
+                batch.retain(|id| !ids.contains(id));
+                removed += batch_len - batch.len();
+            }
+        }
+        self.edits -= removed as isize;
+        self.total = self.total.saturating_sub(removed);
+        self.total_loaded = self.total_loaded.saturating_sub(removed);
+        ListRangeUpdate(0, len as i32, self.total_loaded as i32)
+    }
+
     pub fn append(&mut self, songs: Vec<SongDescription>) -> ListRangeUpdate {
         let songs_len = songs.len();
         // How many loaded/visible songs so far
@@ -283,11 +371,80 @@ impl SongList {
         self.total_loaded = self.total_loaded.saturating_add(songs_len);
         self.last_batch_key = batches.len().saturating_sub(1);
         self.batches = batches;
+        self.offsets.clear();
 
         // But it's a bit easier to computer the visibly affected range :)
         ListRangeUpdate::inserted(insertion_start, songs_len)
     }
 
+    // Insert songs before whatever is at some position (or at the end)
+    pub fn insert(&mut self, position: usize, songs: Vec<SongDescription>) -> ListRangeUpdate {
+        let len = self.total_loaded;
+        let songs_len = songs.len();
+        let ids: Vec<String> = songs.iter().map(|s| s.id.clone()).collect();
+        for song in songs {
+            self.indexed_songs
+                .insert(song.id.clone(), SongModel::new(song));
+        }
+
+        if !self.is_fully_loaded() {
+            return self.insert_in_place(position, ids);
+        }
+
+        // Same as removing, all batches are recreated
+        let mut batches = HashMap::<usize, Vec<String>>::default();
+        let mut inserted = false;
+        self.iter_ids_from(0).for_each(|(i, next)| {
+            if !inserted && i >= position {
+                ids.iter()
+                    .for_each(|id| Self::batches_add(&mut batches, self.batch_size, id));
+                inserted = true;
+            }
+            Self::batches_add(&mut batches, self.batch_size, next);
+        });
+        if !inserted {
+            ids.iter()
+                .for_each(|id| Self::batches_add(&mut batches, self.batch_size, id));
+        }
+
+        self.edits += songs_len as isize;
+        self.total = self.total.saturating_add(songs_len);
+        self.total_loaded = self.total_loaded.saturating_add(songs_len);
+        self.last_batch_key = batches.len().saturating_sub(1);
+        self.batches = batches;
+        self.offsets.clear();
+        ListRangeUpdate(0, len as i32, self.total_loaded as i32)
+    }
+
+    // Same as `remove_in_place`: songs go in the loaded batch found at that position, batches after it move up
+    // If the position isn't loaded, songs go right after the last loaded song before it
+    fn insert_in_place(&mut self, position: usize, ids: Vec<String>) -> ListRangeUpdate {
+        let len = self.total_loaded;
+        let songs_len = ids.len();
+        let target = (0..=self.last_batch_key)
+            .filter(|key| self.batches.contains_key(key))
+            .take_while(|key| self.batch_start(*key) <= position)
+            .last()
+            .or_else(|| self.batches.keys().min().copied());
+        let Some(target) = target else {
+            return ListRangeUpdate(0, 0, 0);
+        };
+        let index = position.saturating_sub(self.batch_start(target));
+        if let Some(batch) = self.batches.get_mut(&target) {
+            let index = index.min(batch.len());
+            batch.splice(index..index, ids);
+        }
+        for key in target + 1..=self.last_batch_key {
+            if self.batches.contains_key(&key) {
+                *self.offsets.entry(key).or_default() += songs_len as isize;
+            }
+        }
+        self.edits += songs_len as isize;
+        self.total = self.total.saturating_add(songs_len);
+        self.total_loaded = self.total_loaded.saturating_add(songs_len);
+        ListRangeUpdate(0, len as i32, self.total_loaded as i32)
+    }
+
     // Adding a batch is easy, might only require a resize
     pub fn add(&mut self, song_batch: SongBatch) -> Option<ListRangeUpdate> {
         if song_batch.batch.batch_size != self.batch_size {
@@ -331,8 +488,12 @@ impl SongList {
             })
             .collect();
 
+        let offset = self.offset_for(index);
+        if offset != 0 {
+            self.offsets.insert(index, offset);
+        }
         self.batches.insert(index, ids);
-        self.total = batch.total;
+        self.total = (batch.total as isize + self.edits).max(0) as usize;
         self.total_loaded += len;
         self.last_batch_key = usize::max(self.last_batch_key, index);
 
@@ -340,11 +501,8 @@ impl SongList {
     }
 
     fn index_mut(&mut self, i: usize) -> Option<&mut String> {
-        let batch_size = self.batch_size;
-        let i_batch = i / batch_size;
-        self.batches
-            .get_mut(&i_batch)
-            .and_then(|s| s.get_mut(i % batch_size))
+        let (key, i) = self.locate(i)?;
+        self.batches.get_mut(&key).and_then(|s| s.get_mut(i))
     }
 
     pub fn swap(&mut self, a: usize, b: usize) -> Option<ListRangeUpdate> {
@@ -366,49 +524,73 @@ impl SongList {
 
     // Get the song at i (if the index is valid AND has been loaded)
     pub fn index(&self, i: usize) -> Option<&SongModel> {
-        let batch_size = self.batch_size;
-        let batch_id = i / batch_size;
-        let indexed_songs = &self.indexed_songs;
+        let (key, i) = self.locate(i)?;
         self.batches
-            .get(&batch_id)
-            .and_then(|batch| batch.get(i % batch_size))
-            .and_then(move |id| indexed_songs.get(id))
+            .get(&key)
+            .and_then(|batch| batch.get(i))
+            .and_then(|id| self.indexed_songs.get(id))
     }
 
     // Get the i-th loaded song. VERY different!
     pub fn index_continuous(&self, i: usize) -> Option<&SongModel> {
-        let batch_size = self.batch_size;
-        let bi = i / batch_size;
-        let batch = (0..=self.last_batch_key)
-            // Skip missing/not loaded batches
-            .filter_map(move |i| self.batches.get(&i))
-            .nth(bi)?;
-        batch
-            .get(i % batch_size)
-            .and_then(move |id| self.indexed_songs.get(id))
+        let mut i = i;
+        // Skip missing/not loaded batches
+        for batch in (0..=self.last_batch_key).filter_map(|key| self.batches.get(&key)) {
+            if i < batch.len() {
+                return self.indexed_songs.get(&batch[i]);
+            }
+            i -= batch.len();
+        }
+        None
     }
 
     // Return the batch needed to access the song at index i (if it's not loaded yet)
+    // Songs removed or inserted before an unloaded batch move it too, so it's not always the batch at i / batch_size
     pub fn needed_batch_for(&self, i: usize) -> Option<Batch> {
-        let total = self.total;
+        if self.locate(i).is_some() {
+            return None;
+        }
         let batch_size = self.batch_size;
-        let batch_id = i / batch_size;
-        if self.batches.contains_key(&batch_id) {
-            None
-        } else {
-            Some(Batch {
+        self.unloaded_batches()
+            .take_while(|(_, start)| *start <= i)
+            .find(|(_, start)| i < start + batch_size)
+            .map(|(key, _)| Batch {
                 batch_size,
-                total,
-                offset: batch_id * batch_size,
+                total: self.total,
+                offset: key * batch_size,
             })
-        }
+    }
+
+    // All the batches that still have to be loaded to get the whole list
+    pub fn missing_batches(&self) -> Vec<Batch> {
+        self.unloaded_batches()
+            .map(|(key, _)| Batch {
+                batch_size: self.batch_size,
+                total: self.total,
+                offset: key * self.batch_size,
+            })
+            .collect()
+    }
+
+    // Same as above, along with where each batch will start once loaded
+    pub fn missing_batches_with_starts(&self) -> Vec<(Batch, usize)> {
+        self.unloaded_batches()
+            .map(|(key, start)| {
+                let batch = Batch {
+                    batch_size: self.batch_size,
+                    total: self.total,
+                    offset: key * self.batch_size,
+                };
+                (batch, start)
+            })
+            .collect()
     }
 
     // Get the full song batch that contains i
     pub fn song_batch_for(&self, i: usize) -> Option<SongBatch> {
         let total = self.total;
         let batch_size = self.batch_size;
-        let batch_id = i / batch_size;
+        let (batch_id, _) = self.locate(i)?;
         let indexed_songs = &self.indexed_songs;
         self.batches.get(&batch_id).map(|songs| SongBatch {
             songs: songs
@@ -423,6 +605,19 @@ impl SongList {
         })
     }
 
+    // The batches loaded so far
+    pub fn loaded_batches(&self) -> Vec<Batch> {
+        let mut keys: Vec<usize> = self.batches.keys().copied().collect();
+        keys.sort_unstable();
+        keys.into_iter()
+            .map(|key| Batch {
+                batch_size: self.batch_size,
+                total: self.total,
+                offset: key * self.batch_size,
+            })
+            .collect()
+    }
+
     // The last loaded batch
     pub fn last_batch(&self) -> Option<Batch> {
         if self.total_loaded == 0 {
@@ -465,10 +660,14 @@ mod tests {
             album: AlbumRef {
                 id: "".to_string(),
                 name: "".to_string(),
+                artists: vec![],
             },
             duration: 1000,
             art: None,
             track_number: None,
+            disc_number: None,
+            is_playable: true,
+            restriction: None,
         }
     }
 
@@ -636,6 +835,147 @@ mod tests {
         assert!(list_iter.next().is_none());
     }
 
+    #[test]
+    fn test_remove_non_contiguous() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+        list.add(batch(3));
+
+        list.remove(&["song0".to_string()]);
+
+        assert_eq!(list.len(), 9);
+        assert_eq!(list.partial_len(), 3);
+        assert_eq!(list.index(0).unwrap().description().id, "song1");
+        // Songs further down moved by one as well...
+        assert_eq!(list.index(5).unwrap().description().id, "song6");
+        assert_eq!(list.find_index("song7"), Some(6));
+        assert!(list.index(7).is_none());
+        // ...but their batch is still the one we loaded
+        let offsets: Vec<usize> = list.missing_batches().iter().map(|b| b.offset).collect();
+        assert_eq!(offsets, vec![2, 4, 8]);
+
+        // A batch loaded afterwards goes where it belongs
+        list.add(batch(1));
+        let ids: Vec<(usize, String)> = list
+            .iter_ids_from(0)
+            .map(|(i, id)| (i, id.clone()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                (0, "song1".to_string()),
+                (1, "song2".to_string()),
+                (2, "song3".to_string()),
+                (5, "song6".to_string()),
+                (6, "song7".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_needed_batch_after_remove() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+
+        list.remove(&["song0".to_string()]);
+
+        // song2 moved down to 1, its batch is the one to load
+        assert!(list.index(1).is_none());
+        assert_eq!(list.needed_batch_for(1).map(|b| b.offset), Some(2));
+        assert_eq!(list.needed_batch_for(2).map(|b| b.offset), Some(2));
+        assert_eq!(list.needed_batch_for(3).map(|b| b.offset), Some(4));
+        assert!(list.needed_batch_for(0).is_none());
+
+        list.add(batch(1));
+        assert_eq!(list.index(1).unwrap().description().id, "song2");
+        assert!(list.needed_batch_for(1).is_none());
+    }
+
+    #[test]
+    fn test_remove_from_loaded_prefix() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+
+        list.remove(&["song1".to_string()]);
+        list.add(batch(4));
+        list.add(batch(1));
+
+        // The removed song is accounted for, so the last one is still within the list
+        assert_eq!(list.len(), 9);
+        assert_eq!(list.find_index("song2"), Some(1));
+        assert_eq!(list.find_index("song9"), Some(8));
+        let offsets: Vec<usize> = list.missing_batches().iter().map(|b| b.offset).collect();
+        assert_eq!(offsets, vec![4, 6]);
+    }
+
+    #[test]
+    fn test_missing_batches_with_starts() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+        list.add(batch(2));
+
+        list.remove(&["song1".to_string(), "song4".to_string()]);
+
+        let missing: Vec<(usize, usize)> = list
+            .missing_batches_with_starts()
+            .iter()
+            .map(|(batch, start)| (batch.offset, *start))
+            .collect();
+        assert_eq!(missing, vec![(2, 1), (6, 4), (8, 6)]);
+    }
+
+    #[test]
+    fn test_insert_non_contiguous() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+        list.add(batch(3));
+
+        list.remove(&["song0".to_string()]);
+        list.insert(0, vec![song("song0")]);
+
+        assert_eq!(list.len(), 10);
+        assert_eq!(list.index(0).unwrap().description().id, "song0");
+        assert_eq!(list.index(6).unwrap().description().id, "song6");
+        assert_eq!(list.find_index("song7"), Some(7));
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+        list.add(batch(1));
+
+        list.insert(1, vec![song("a"), song("b")]);
+        list.insert(10, vec![song("c")]);
+
+        assert_eq!(list.partial_len(), 7);
+        let ids: Vec<String> = list.iter().map(|s| s.description().id.clone()).collect();
+        assert_eq!(ids, vec!["song0", "a", "b", "song1", "song2", "song3", "c"]);
+    }
+
+    #[test]
+    fn test_loaded_batches() {
+        let mut list = SongList::new_from_initial_batch(batch(3));
+        list.add(batch(0));
+
+        let offsets: Vec<usize> = list.loaded_batches().iter().map(|b| b.offset).collect();
+        assert_eq!(offsets, vec![0, 6]);
+    }
+
+    #[test]
+    fn test_missing_batches() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+        list.add(batch(2));
+
+        let offsets: Vec<usize> = list.missing_batches().iter().map(|b| b.offset).collect();
+        assert_eq!(offsets, vec![2, 6, 8]);
+    }
+
+    #[test]
+    fn test_remove_missing() {
+        let mut list = SongList::new_from_initial_batch(batch(0));
+        let total = list.len();
+
+        list.remove(&["song0".to_string(), "not_there".to_string()]);
+
+        assert_eq!(list.partial_len(), 1);
+        assert_eq!(list.len(), total - 1);
+    }
+
     #[test]
     fn test_batch_for() {
         let mut list = SongList::new_from_initial_batch(batch(0));
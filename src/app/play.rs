@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use crate::api::{SpotifyApiClient, SpotifyApiError, SpotifyResult};
+use crate::app::models::{Batch, SongDescription};
+use crate::app::state::PlaybackAction;
+use crate::app::{AppAction, BatchLoader, BatchQuery, SongsSource, SpotifyLink};
+
+// Same as the saved tracks page
+const BATCH_SIZE: usize = 50;
+
+// The actions to play a source from its first track
+pub async fn play_source(
+    loader: &BatchLoader,
+    source: SongsSource,
+) -> SpotifyResult<Vec<AppAction>> {
+    let query = BatchQuery {
+        source,
+        batch: Batch::first_of_size(BATCH_SIZE),
+    };
+    let batch = loader.get_batch(&query).await?;
+    let first_song = batch
+        .songs
+        .first()
+        .map(|song| song.id.clone())
+        .ok_or(SpotifyApiError::NoContent)?;
+    Ok(vec![
+        PlaybackAction::LoadPagedSongs(query.source, batch).into(),
+        PlaybackAction::Load(first_song).into(),
+    ])
+}
+
+// The album of a track, and the actions to play the track from there
+async fn play_in_album(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    id: String,
+) -> SpotifyResult<(String, Vec<AppAction>)> {
+    let song = api.get_track(&id).await?;
+    let album_id = song.album.id.clone();
+    let query = BatchQuery {
+        source: SongsSource::Album(album_id.clone()),
+        batch: Batch::first_of_size(BATCH_SIZE),
+    };
+    let batch = BatchLoader::new(api).get_batch(&query).await?;
+    // Very long albums might not have the track in their first batch
+    let load: AppAction = if batch.songs.iter().any(|s| s.id == id) {
+        PlaybackAction::LoadPagedSongs(query.source, batch).into()
+    } else {
+        PlaybackAction::LoadSongs(vec![song]).into()
+    };
+    Ok((album_id, vec![load, PlaybackAction::Load(id).into()]))
+}
+
+// The actions to show the album of a track, and play the track from there
+pub async fn open_track(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    id: String,
+) -> SpotifyResult<Vec<AppAction>> {
+    let (album_id, mut actions) = play_in_album(api, id).await?;
+    actions.insert(0, AppAction::ViewAlbum(album_id));
+    Ok(actions)
+}
+
+// Every track a link points to, to queue them for instance (users have none)
+pub async fn tracks_of(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    link: SpotifyLink,
+) -> SpotifyResult<Vec<SongDescription>> {
+    let source = match link {
+        SpotifyLink::Track(id) => return Ok(vec![api.get_track(&id).await?]),
+        SpotifyLink::User(_) => return Err(SpotifyApiError::NoContent),
+        SpotifyLink::Album(id) => SongsSource::Album(id),
+        SpotifyLink::Playlist(id) => SongsSource::Playlist(id),
+        SpotifyLink::Artist(id) => SongsSource::Artist(id),
+    };
+    let loader = BatchLoader::new(api);
+    let mut songs = vec![];
+    let mut query = Some(BatchQuery {
+        source,
+        batch: Batch::first_of_size(BATCH_SIZE),
+    });
+    while let Some(current) = query {
+        let batch = loader.get_batch(&current).await?;
+        query = BatchQuery {
+            source: current.source,
+            batch: batch.batch,
+        }
+        .next();
+        songs.extend(batch.songs);
+    }
+    Ok(songs)
+}
+
+// The actions to play whatever a link points to (users can only be shown)
+pub async fn play_link(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    link: SpotifyLink,
+) -> SpotifyResult<Vec<AppAction>> {
+    let source = match link {
+        SpotifyLink::Track(id) => return Ok(play_in_album(api, id).await?.1),
+        SpotifyLink::User(id) => return Ok(vec![AppAction::Raise, AppAction::ViewUser(id)]),
+        SpotifyLink::Album(id) => SongsSource::Album(id),
+        SpotifyLink::Playlist(id) => SongsSource::Playlist(id),
+        SpotifyLink::Artist(id) => SongsSource::Artist(id),
+    };
+    play_source(&BatchLoader::new(api), source).await
+}
@@ -0,0 +1,679 @@
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use url::Url;
+
+use crate::app::models::SongDescription;
+use crate::app::SpotifyLink;
+
+// Below that score, a search result isn't considered to be the track we're looking for
+const MATCH_THRESHOLD: f64 = 0.6;
+// Durations further apart than that (in ms) probably mean another version of a track
+const DURATION_TOLERANCE: i64 = 10_000;
+
+lazy_static! {
+    static ref BRACKETED: Regex = Regex::new(r"\([^)]*\)|\[[^\]]*\]").unwrap();
+    static ref XML_ENTITY: Regex = Regex::new(r"&(#x[0-9a-fA-F]+|#[0-9]+|[a-z]+);").unwrap();
+}
+
+// The formats playlists can be exported to and imported from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    M3u,
+    Xspf,
+    Csv,
+    Json,
+}
+
+impl PlaylistFormat {
+    pub const ALL: [Self; 4] = [Self::M3u, Self::Xspf, Self::Csv, Self::Json];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::M3u => "m3u",
+            Self::Xspf => "xspf",
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+
+    // Not translated, it's the name of the format
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::M3u => "M3U",
+            Self::Xspf => "XSPF",
+            Self::Csv => "CSV",
+            Self::Json => "JSON",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "xspf" => Some(Self::Xspf),
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+}
+
+// A track as found in a playlist file: with a Spotify URI, or only with its title and artist (from another player)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default)]
+    pub album: String,
+    // In ms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+}
+
+impl From<&SongDescription> for PlaylistEntry {
+    fn from(song: &SongDescription) -> Self {
+        Self {
+            uri: Some(song.uri.clone()),
+            title: song.title.clone(),
+            artist: song.artists_name(),
+            album: song.album.name.clone(),
+            duration: Some(song.duration),
+        }
+    }
+}
+
+impl PlaylistEntry {
+    // The URI of the track, if the entry has a valid one (links to open.spotify.com work too)
+    pub fn track_uri(&self) -> Option<String> {
+        match SpotifyLink::parse(self.uri.as_ref()?.trim())? {
+            SpotifyLink::Track(id) => Some(format!("spotify:track:{}", id)),
+            _ => None,
+        }
+    }
+
+    // What to search for when there's no URI
+    pub fn search_query(&self) -> String {
+        format!("{} {}", self.title, self.artist).trim().to_string()
+    }
+
+    // How well a track matches this entry, from 0 to 1
+    pub fn match_score(&self, song: &SongDescription) -> f64 {
+        let title = similarity(&self.title, &song.title);
+        let score = if self.artist.trim().is_empty() {
+            title
+        } else {
+            let artist = song
+                .artists
+                .iter()
+                .map(|a| similarity(&self.artist, &a.name))
+                .fold(similarity(&self.artist, &song.artists_name()), f64::max);
+            0.6 * title + 0.4 * artist
+        };
+        match self.duration {
+            Some(duration)
+                if (i64::from(duration) - i64::from(song.duration)).abs() > DURATION_TOLERANCE =>
+            {
+                score * 0.8
+            }
+            _ => score,
+        }
+    }
+
+    // The track (among search results) that is the most likely to be this entry, if any is close enough
+    pub fn best_match<'a>(&self, songs: &'a [SongDescription]) -> Option<&'a SongDescription> {
+        songs
+            .iter()
+            .map(|song| (self.match_score(song), song))
+            .filter(|(score, _)| *score >= MATCH_THRESHOLD)
+            .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+            .map(|(_, song)| song)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PlaylistFileError {
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("No tracks found")]
+    Empty,
+}
+
+// The contents of a playlist file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlaylistFile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tracks: Vec<PlaylistEntry>,
+}
+
+impl PlaylistFile {
+    pub fn new(name: &str, songs: &[SongDescription]) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            tracks: songs.iter().map(PlaylistEntry::from).collect(),
+        }
+    }
+
+    pub fn write(&self, format: PlaylistFormat) -> String {
+        match format {
+            PlaylistFormat::M3u => self.write_m3u(),
+            PlaylistFormat::Xspf => self.write_xspf(),
+            PlaylistFormat::Csv => self.write_csv(),
+            PlaylistFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+        }
+    }
+
+    pub fn parse(format: PlaylistFormat, content: &str) -> Result<Self, PlaylistFileError> {
+        let file = match format {
+            PlaylistFormat::M3u => Self::parse_m3u(content),
+            PlaylistFormat::Xspf => Self::parse_xspf(content),
+            PlaylistFormat::Csv => Self::parse_csv(content),
+            PlaylistFormat::Json => serde_json::from_str(content)?,
+        };
+        if file.tracks.is_empty() {
+            Err(PlaylistFileError::Empty)
+        } else {
+            Ok(file)
+        }
+    }
+
+    // Extended M3U, the location of each track being its URI
+    fn write_m3u(&self) -> String {
+        let mut m3u = String::from("#EXTM3U\n");
+        if let Some(name) = self.name.as_ref() {
+            m3u.push_str(&format!("#PLAYLIST:{}\n", name));
+        }
+        for track in self.tracks.iter() {
+            let uri = if let Some(uri) = track.uri.as_ref() {
+                uri
+            } else {
+                continue;
+            };
+            let seconds = track
+                .duration
+                .map(|d| ((d + 500) / 1000) as i64)
+                .unwrap_or(-1);
+            m3u.push_str(&format!(
+                "#EXTINF:{},{} - {}\n",
+                seconds, track.artist, track.title
+            ));
+            if !track.album.is_empty() {
+                m3u.push_str(&format!("#EXTALB:{}\n", track.album));
+            }
+            m3u.push_str(uri);
+            m3u.push('\n');
+        }
+        m3u
+    }
+
+    fn parse_m3u(content: &str) -> Self {
+        let mut file = Self::default();
+        let mut entry = PlaylistEntry::default();
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(name) = line.strip_prefix("#PLAYLIST:") {
+                file.name = Some(name.trim().to_string());
+            } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+                let (duration, title) = info.split_once(',').unwrap_or((info, ""));
+                // The duration may be followed by attributes
+                entry.duration = duration
+                    .split_whitespace()
+                    .next()
+                    .and_then(|d| d.parse::<f64>().ok())
+                    .filter(|d| *d >= 0.0)
+                    .map(|d| (d * 1000.0) as u32);
+                if let Some((artist, title)) = title.split_once(" - ") {
+                    entry.artist = artist.trim().to_string();
+                    entry.title = title.trim().to_string();
+                } else {
+                    entry.title = title.trim().to_string();
+                }
+            } else if let Some(album) = line.strip_prefix("#EXTALB:") {
+                entry.album = album.trim().to_string();
+            } else if !line.starts_with('#') {
+                entry.uri = Some(line.to_string());
+                // A local file, with nothing but its name to go by
+                if entry.title.is_empty() && entry.track_uri().is_none() {
+                    entry.title = file_stem(line);
+                }
+                file.tracks.push(std::mem::take(&mut entry));
+            }
+        }
+        file
+    }
+
+    fn write_xspf(&self) -> String {
+        let mut xspf = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+        );
+        if let Some(name) = self.name.as_ref() {
+            xspf.push_str(&format!("  <title>{}</title>\n", xml_escape(name)));
+        }
+        xspf.push_str("  <trackList>\n");
+        for track in self.tracks.iter() {
+            xspf.push_str("    <track>\n");
+            if let Some(uri) = track.uri.as_ref() {
+                xspf.push_str(&format!("      <location>{}</location>\n", xml_escape(uri)));
+            }
+            xspf.push_str(&format!(
+                "      <title>{}</title>\n",
+                xml_escape(&track.title)
+            ));
+            xspf.push_str(&format!(
+                "      <creator>{}</creator>\n",
+                xml_escape(&track.artist)
+            ));
+            xspf.push_str(&format!(
+                "      <album>{}</album>\n",
+                xml_escape(&track.album)
+            ));
+            if let Some(duration) = track.duration {
+                xspf.push_str(&format!("      <duration>{}</duration>\n", duration));
+            }
+            xspf.push_str("    </track>\n");
+        }
+        xspf.push_str("  </trackList>\n</playlist>\n");
+        xspf
+    }
+
+    // Not a full XML parser, but XSPF is simple enough
+    fn parse_xspf(content: &str) -> Self {
+        let head = content.split("<trackList").next().unwrap_or_default();
+        let tracks = content
+            .split("<track>")
+            .skip(1)
+            .map(|track| {
+                let track = track.split("</track>").next().unwrap_or_default();
+                let locations = [xml_field(track, "location"), xml_field(track, "identifier")];
+                let mut entry = PlaylistEntry {
+                    title: xml_field(track, "title").unwrap_or_default(),
+                    artist: xml_field(track, "creator").unwrap_or_default(),
+                    album: xml_field(track, "album").unwrap_or_default(),
+                    duration: xml_field(track, "duration").and_then(|d| d.parse().ok()),
+                    ..Default::default()
+                };
+                // Prefer whichever is a Spotify URI
+                entry.uri = locations
+                    .iter()
+                    .flatten()
+                    .find(|uri| matches!(SpotifyLink::parse(uri), Some(SpotifyLink::Track(_))))
+                    .or(locations[0].as_ref())
+                    .cloned();
+                if entry.title.is_empty() {
+                    entry.title = entry.uri.as_deref().map(file_stem).unwrap_or_default();
+                }
+                entry
+            })
+            .collect();
+        Self {
+            name: xml_field(head, "title"),
+            tracks,
+        }
+    }
+
+    fn write_csv(&self) -> String {
+        let mut csv = String::from("title,artist,album,duration,uri\n");
+        for track in self.tracks.iter() {
+            let duration = track.duration.map(|d| d.to_string()).unwrap_or_default();
+            let fields = [
+                &track.title[..],
+                &track.artist,
+                &track.album,
+                &duration,
+                track.uri.as_deref().unwrap_or_default(),
+            ];
+            let line: Vec<String> = fields.iter().map(|f| csv_escape(f)).collect();
+            csv.push_str(&line.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    // Columns are found by their names, so that files exported by other tools can be read too
+    fn parse_csv(content: &str) -> Self {
+        let mut records = csv_records(content).into_iter();
+        let header: Vec<String> = records
+            .next()
+            .unwrap_or_default()
+            .iter()
+            .map(|h| h.trim().to_lowercase())
+            .collect();
+        let find = |f: &dyn Fn(&str) -> bool| header.iter().position(|h| f(h));
+        let uri = find(&|h| h.contains("uri") && !h.contains("artist") && !h.contains("album"));
+        let title = find(&|h| h == "title")
+            .or_else(|| find(&|h| h.contains("track name")))
+            .or_else(|| find(&|h| h == "name"));
+        let artist = find(&|h| h.contains("artist") && !h.contains("uri") && !h.contains("album"));
+        let album = find(&|h| h.contains("album") && !h.contains("uri") && !h.contains("artist"));
+        let duration = find(&|h| h.contains("duration"));
+
+        let tracks = records
+            .map(|record| {
+                let field = |i: Option<usize>| {
+                    i.and_then(|i| record.get(i))
+                        .map(|f| f.trim().to_string())
+                        .unwrap_or_default()
+                };
+                PlaylistEntry {
+                    uri: Some(field(uri)).filter(|u| !u.is_empty()),
+                    title: field(title),
+                    artist: field(artist),
+                    album: field(album),
+                    duration: field(duration).parse().ok(),
+                }
+            })
+            .filter(|entry| entry.uri.is_some() || !entry.title.is_empty())
+            .collect();
+        Self { name: None, tracks }
+    }
+}
+
+// Lowercase, without punctuation or what's in brackets (Remastered, feat...)
+fn normalize(s: &str) -> String {
+    let s = BRACKETED.replace_all(s, " ").to_lowercase();
+    let s: String = s
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// How alike two strings are, from 0 to 1 (Dice coefficient over pairs of characters)
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return if a.is_empty() { 0.0 } else { 1.0 };
+    }
+    let bigrams = |s: &str| -> Vec<(char, char)> {
+        let chars: Vec<char> = s.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let a = bigrams(&a);
+    let mut b = bigrams(&b);
+    let total = a.len() + b.len();
+    if total == 0 {
+        return 0.0;
+    }
+    let mut common = 0;
+    for pair in a {
+        if let Some(i) = b.iter().position(|other| *other == pair) {
+            b.swap_remove(i);
+            common += 1;
+        }
+    }
+    2.0 * common as f64 / total as f64
+}
+
+// The name of a file, from its path or its (percent-encoded) file:// URL
+fn file_stem(location: &str) -> String {
+    let path = Url::parse(location)
+        .ok()
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok())
+        .unwrap_or_else(|| PathBuf::from(location));
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(location)
+        .to_string()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_unescape(s: &str) -> String {
+    if let Some(data) = s
+        .strip_prefix("<![CDATA[")
+        .and_then(|s| s.strip_suffix("]]>"))
+    {
+        return data.to_string();
+    }
+    XML_ENTITY
+        .replace_all(s, |caps: &Captures| {
+            let entity = &caps[1];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                    .and_then(Result::ok)
+                    .and_then(char::from_u32),
+            };
+            c.map(String::from).unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+// The text of the first <tag> element
+fn xml_field(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(xml_unescape(xml[start..end].trim()))
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Records of a CSV file, with quoted fields possibly containing commas, quotes ("") or line breaks
+fn csv_records(content: &str) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+        .into_iter()
+        .filter(|r| r.iter().any(|f| !f.trim().is_empty()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::models::{AlbumRef, ArtistRef};
+
+    fn song(id: &str, title: &str, artist: &str) -> SongDescription {
+        SongDescription {
+            id: id.to_string(),
+            uri: format!("spotify:track:{}", id),
+            title: title.to_string(),
+            artists: vec![ArtistRef {
+                id: artist.to_string(),
+                name: artist.to_string(),
+            }],
+            album: AlbumRef {
+                id: "".to_string(),
+                name: "Album, \"the\" <best>".to_string(),
+                artists: vec![],
+            },
+            duration: 201_500,
+            art: None,
+            track_number: None,
+            disc_number: None,
+            is_playable: true,
+            restriction: None,
+        }
+    }
+
+    fn playlist() -> PlaylistFile {
+        PlaylistFile::new(
+            "Road & trip",
+            &[
+                song("1", "Hey, You", "Pink Floyd"),
+                song("2", "Comfortably Numb", "Pink Floyd"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let file = playlist();
+        for format in PlaylistFormat::ALL {
+            let parsed = PlaylistFile::parse(format, &file.write(format)).unwrap();
+            let mut expected = file.tracks.clone();
+            if format == PlaylistFormat::M3u {
+                // Durations are in seconds
+                expected.iter_mut().for_each(|t| t.duration = Some(202_000));
+            }
+            assert_eq!(parsed.tracks, expected, "{}", format.name());
+            if format != PlaylistFormat::Csv {
+                assert_eq!(parsed.name, file.name, "{}", format.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_m3u() {
+        let m3u = "#EXTM3U\n\
+            #EXTINF:123 tvg-id=\"x\",Artist - Some Title\n\
+            /home/me/Music/some title.mp3\n\
+            \n\
+            # a comment\n\
+            ../Music/Other Song.flac\n\
+            https://open.spotify.com/track/42?si=abc\n";
+        let file = PlaylistFile::parse(PlaylistFormat::M3u, m3u).unwrap();
+        assert_eq!(file.tracks.len(), 3);
+        assert_eq!(file.tracks[0].artist, "Artist");
+        assert_eq!(file.tracks[0].title, "Some Title");
+        assert_eq!(file.tracks[0].duration, Some(123_000));
+        assert_eq!(file.tracks[0].track_uri(), None);
+        assert_eq!(file.tracks[1].title, "Other Song");
+        assert_eq!(file.tracks[1].duration, None);
+        assert_eq!(
+            file.tracks[2].track_uri(),
+            Some("spotify:track:42".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_xspf() {
+        let xspf = r#"<?xml version="1.0"?>
+            <playlist version="1" xmlns="http://xspf.org/ns/0/">
+              <title>Mine &amp; &#233;</title>
+              <trackList>
+                <track>
+                  <location>file:///music/a.ogg</location>
+                  <identifier>spotify:track:1</identifier>
+                  <title><![CDATA[A & B]]></title>
+                  <creator>Someone</creator>
+                </track>
+                <track><location>file:///music/Nothing%20else.ogg</location></track>
+              </trackList>
+            </playlist>"#;
+        let file = PlaylistFile::parse(PlaylistFormat::Xspf, xspf).unwrap();
+        assert_eq!(file.name, Some("Mine & é".to_string()));
+        assert_eq!(file.tracks.len(), 2);
+        assert_eq!(file.tracks[0].uri, Some("spotify:track:1".to_string()));
+        assert_eq!(file.tracks[0].title, "A & B");
+        assert_eq!(file.tracks[0].artist, "Someone");
+        assert_eq!(file.tracks[1].title, "Nothing else");
+    }
+
+    #[test]
+    fn test_parse_csv_other_tool() {
+        let csv = "\"Track URI\",\"Track Name\",\"Artist URI(s)\",\"Artist Name(s)\",\"Album Name\",\"Album Artist Name(s)\",\"Duration (ms)\"\r\n\
+            \"spotify:track:1\",\"Say \"\"Hi\"\"\",\"spotify:artist:a\",\"A, B\",\"Multi\nline\",\"C\",\"1000\"\r\n\
+            ,Untitled,,,,,\r\n\
+            ,,,,,,\r\n";
+        let file = PlaylistFile::parse(PlaylistFormat::Csv, csv).unwrap();
+        assert_eq!(
+            file.tracks,
+            vec![
+                PlaylistEntry {
+                    uri: Some("spotify:track:1".to_string()),
+                    title: "Say \"Hi\"".to_string(),
+                    artist: "A, B".to_string(),
+                    album: "Multi\nline".to_string(),
+                    duration: Some(1000),
+                },
+                PlaylistEntry {
+                    title: "Untitled".to_string(),
+                    ..Default::default()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        assert!(matches!(
+            PlaylistFile::parse(PlaylistFormat::Csv, "title,artist\n"),
+            Err(PlaylistFileError::Empty)
+        ));
+        assert!(matches!(
+            PlaylistFile::parse(PlaylistFormat::Json, "{"),
+            Err(PlaylistFileError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_best_match() {
+        let entry = PlaylistEntry {
+            title: "Comfortably Numb (2011 Remastered)".to_string(),
+            artist: "pink floyd".to_string(),
+            duration: Some(200_000),
+            ..Default::default()
+        };
+        let results = vec![
+            song("1", "Comfortably Numb - Live", "Some Cover Band"),
+            song("2", "Comfortably Numb", "Pink Floyd"),
+            song("3", "Hey You", "Pink Floyd"),
+        ];
+        assert_eq!(entry.best_match(&results).map(|s| &s.id[..]), Some("2"));
+        assert!(entry.best_match(&results[2..]).is_none());
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            PlaylistFormat::from_path(Path::new("/tmp/a.M3U8")),
+            Some(PlaylistFormat::M3u)
+        );
+        assert_eq!(PlaylistFormat::from_path(Path::new("/tmp/a")), None);
+    }
+}
@@ -1,5 +1,10 @@
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
 
+// For a balanced shuffle: how many of the last picked tracks a new pick should not be similar to...
+const BALANCED_WINDOW: usize = 3;
+// ...and how many random picks we try before settling for a similar track anyway
+const BALANCED_ATTEMPTS: usize = 8;
+
 // A random, resizable mapping (i-th element to play => j-th track) used to handle shuffled playlists
 // It's lazy: initially we don't compute what index i maps to
 // It's resizable: if our playlist grows or shrinks, we have to keep the generated mappings stable
@@ -53,9 +58,74 @@ impl LazyRandomIndex {
         self.indices.extend(current_size..size);
     }
 
+    // Drop every index beyond the new size, wherever it was mapped
+    // The mappings left keep their order
     pub fn shrink(&mut self, size: usize) {
-        self.generated = usize::min(size, self.generated);
-        self.indices.truncate(size);
+        let generated = self.indices[..self.generated]
+            .iter()
+            .filter(|&&i| i < size)
+            .count();
+        self.indices.retain(|&i| i < size);
+        self.generated = generated;
+    }
+
+    // Removes a single index (for instance, a track removed from a playlist)
+    // Indices after it move down by one, just like the tracks after the removed one
+    pub fn remove(&mut self, index: usize) {
+        let Some(position) = self.indices.iter().position(|&i| i == index) else {
+            return;
+        };
+        self.indices.remove(position);
+        if position < self.generated {
+            self.generated -= 1;
+        }
+        for i in self.indices.iter_mut().filter(|i| **i > index) {
+            *i -= 1;
+        }
+    }
+
+    // Makes room for a new index (for instance, a track inserted in a playlist), not mapped yet
+    // Indices from that one move up by one
+    pub fn insert(&mut self, index: usize) {
+        for i in self.indices.iter_mut().filter(|i| **i >= index) {
+            *i += 1;
+        }
+        self.indices.push(index);
+    }
+
+    // Where some index was mapped, if it was
+    pub fn position_of(&self, index: usize) -> Option<usize> {
+        self.generated().iter().position(|&i| i == index)
+    }
+
+    // The mappings generated so far, in order
+    pub fn generated(&self) -> &[usize] {
+        &self.indices[..self.generated]
+    }
+
+    // Every index: the mapped ones first (in order), then the others (in no particular order yet)
+    pub fn order(&self) -> &[usize] {
+        &self.indices
+    }
+
+    // Restores mappings obtained from `generated` for a list of the given size
+    // If they don't fit that list, we start over with no mappings instead
+    pub fn restore(&mut self, generated: &[usize], size: usize) {
+        let mut remaining = vec![true; size];
+        let valid = generated
+            .iter()
+            .all(|&i| i < size && std::mem::replace(&mut remaining[i], false));
+        if valid {
+            self.indices = generated
+                .iter()
+                .copied()
+                .chain((0..size).filter(|&i| remaining[i]))
+                .collect();
+            self.generated = generated.len();
+        } else {
+            self.indices = (0..size).collect();
+            self.generated = 0;
+        }
     }
 
     // Get the index (for instance in a playlist) of the i-th next element to play
@@ -69,6 +139,14 @@ impl LazyRandomIndex {
 
     // Generate all mappings until the mapping for i has been generated
     pub fn next_until(&mut self, i: usize) -> Option<usize> {
+        self.next_until_balanced(i, |_, _| false)
+    }
+
+    // Same, but tries to keep apart the indices that are `similar` (tracks by the same artist for instance)
+    pub fn next_until_balanced<F>(&mut self, i: usize, similar: F) -> Option<usize>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
         if i >= self.indices.len() {
             return None;
         }
@@ -77,13 +155,22 @@ impl LazyRandomIndex {
             if self.generated > i {
                 break Some(self.indices[i]);
             }
-            self.next();
+            self.next_balanced(&similar);
         }
     }
 
     // Generate the next mapping
     pub fn next(&mut self) -> Option<usize> {
-        if self.indices.len() < self.generated {
+        self.next_balanced(&|_, _| false)
+    }
+
+    // Generate the next mapping, avoiding indices similar to the last few mapped ones (if we can)
+    // Since it only changes which index gets picked, mappings stay as stable as with `next`
+    fn next_balanced<F>(&mut self, similar: &F) -> Option<usize>
+    where
+        F: Fn(usize, usize) -> bool,
+    {
+        if self.indices.len() <= self.generated {
             return None;
         }
 
@@ -104,7 +191,19 @@ impl LazyRandomIndex {
         // [1, 0, 2], generated = 1, we swapped 0 and 1
         // [1, 0, 2], generated = 2, we swapped 1 and 1 (no-op)
         // [1, 0, 2], generated = 3, no-op again (no choice, only one element left to place)
-        let next = (self.rng.next_u64() as usize) % (self.indices.len() - last) + last;
+        let size = self.indices.len();
+        let rng = &mut self.rng;
+        let mut random_next = || (rng.next_u64() as usize) % (size - last) + last;
+
+        let recent = &self.indices[last.saturating_sub(BALANCED_WINDOW)..last];
+        let mut next = random_next();
+        for _ in 1..BALANCED_ATTEMPTS {
+            let candidate = self.indices[next];
+            if !recent.iter().any(|&i| similar(i, candidate)) {
+                break;
+            }
+            next = random_next();
+        }
         Some(self.pick_next(next))
     }
 
@@ -163,6 +262,19 @@ mod tests {
         assert_eq!(sorted, &[0, 1, 2, 3, 4]);
     }
 
+    #[test]
+    fn test_next_when_all_generated() {
+        let mut index = LazyRandomIndex::from(rng_for_test());
+        index.grow(3);
+        for _ in 0..3 {
+            assert!(index.next().is_some());
+        }
+
+        // Nothing left to map, and nothing to panic about
+        assert_eq!(index.next(), None);
+        assert_eq!(index.generated().len(), 3);
+    }
+
     #[test]
     fn test_after_grow() {
         let mut index = LazyRandomIndex::from(rng_for_test());
@@ -195,4 +307,212 @@ mod tests {
         index.reset_picking_first(2);
         assert_eq!(index.get(0), Some(2));
     }
+
+    // Whatever happened, the index should map to each track exactly once
+    fn assert_permutation(index: &LazyRandomIndex, size: usize) {
+        let mut all = index.indices.clone();
+        all.sort();
+        assert_eq!(all, (0..size).collect::<Vec<usize>>());
+        assert!(index.generated <= size);
+    }
+
+    #[test]
+    fn test_shrink() {
+        let mut index = LazyRandomIndex::from(rng_for_test());
+        index.grow(10);
+        index.next_until(9);
+        let generated = index.generated().to_vec();
+
+        index.shrink(4);
+        assert_permutation(&index, 4);
+        let kept: Vec<usize> = generated.into_iter().filter(|&i| i < 4).collect();
+        assert_eq!(index.generated(), &kept[..]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut index = LazyRandomIndex::from(rng_for_test());
+        index.grow(5);
+        index.next_until(2);
+        let generated = index.generated().to_vec();
+
+        index.remove(generated[1]);
+        assert_permutation(&index, 4);
+        let shifted = |i: usize| if i > generated[1] { i - 1 } else { i };
+        assert_eq!(
+            index.generated(),
+            &[shifted(generated[0]), shifted(generated[2])]
+        );
+        assert_eq!(index.position_of(shifted(generated[2])), Some(1));
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut index = LazyRandomIndex::from(rng_for_test());
+        index.grow(5);
+        index.next_until(4);
+        let generated = index.generated().to_vec();
+
+        // Removing then inserting an index back gets us the same mappings, minus that one
+        index.remove(generated[0]);
+        index.insert(generated[0]);
+        assert_permutation(&index, 5);
+        assert_eq!(index.generated(), &generated[1..]);
+        index.next_until(4);
+        assert_eq!(index.generated()[4], generated[0]);
+    }
+
+    // Property test: random sequences of operations, as tracks get loaded, removed and played
+    #[test]
+    fn test_resize_while_shuffling() {
+        for seed in 0..200 {
+            let mut ops = SmallRng::seed_from_u64(seed);
+            let mut index = LazyRandomIndex::from(SmallRng::seed_from_u64(seed));
+            let mut size = 0;
+            for _ in 0..50 {
+                let before = index.generated().to_vec();
+                match ops.next_u32() % 4 {
+                    0 => {
+                        size += (ops.next_u32() % 100) as usize;
+                        index.grow(size);
+                        // Growing never changes what was already generated
+                        assert_eq!(index.generated(), &before[..]);
+                    }
+                    1 if size > 0 => {
+                        size = (ops.next_u32() as usize) % size;
+                        index.shrink(size);
+                        let kept: Vec<usize> = before.into_iter().filter(|&i| i < size).collect();
+                        assert_eq!(index.generated(), &kept[..]);
+                    }
+                    2 if size > 0 => {
+                        let removed = (ops.next_u32() as usize) % size;
+                        size -= 1;
+                        index.remove(removed);
+                        let kept: Vec<usize> = before
+                            .into_iter()
+                            .filter(|&i| i != removed)
+                            .map(|i| if i > removed { i - 1 } else { i })
+                            .collect();
+                        assert_eq!(index.generated(), &kept[..]);
+                    }
+                    _ if size > 0 => {
+                        let i = (ops.next_u32() as usize) % size;
+                        assert!(index.next_until(i).unwrap() < size);
+                        assert_eq!(index.generated()[..before.len()], before[..]);
+                    }
+                    _ => {}
+                }
+                assert_permutation(&index, size);
+            }
+        }
+    }
+
+    // Property test: any track is as likely to come up first, including those far down a big list
+    #[test]
+    fn test_uniform() {
+        let size = 5000;
+        let runs = 20000;
+        let mut first_half = 0;
+        let mut last_tenth = 0;
+        let mut rng = rng_for_test();
+        for _ in 0..runs {
+            let mut index = LazyRandomIndex::from(SmallRng::seed_from_u64(rng.next_u64()));
+            index.grow(50);
+            index.grow(size);
+            let first = index.next().unwrap();
+            if first < size / 2 {
+                first_half += 1;
+            }
+            if first >= size - size / 10 {
+                last_tenth += 1;
+            }
+        }
+        // Loose bounds, about 5 standard deviations
+        assert!((runs / 2 - 400..runs / 2 + 400).contains(&first_half));
+        assert!((runs / 10 - 250..runs / 10 + 250).contains(&last_tenth));
+    }
+
+    // Tracks by 10 different artists, 10 of each
+    fn same_artist(a: usize, b: usize) -> bool {
+        a % 10 == b % 10
+    }
+
+    // How many times the same artist plays twice in a row
+    fn repeats(index: &LazyRandomIndex) -> usize {
+        index
+            .generated()
+            .windows(2)
+            .filter(|w| same_artist(w[0], w[1]))
+            .count()
+    }
+
+    #[test]
+    fn test_balanced_spread() {
+        let mut plain_repeats = 0;
+        let mut balanced_repeats = 0;
+        for seed in 0..100 {
+            let mut plain = LazyRandomIndex::from(SmallRng::seed_from_u64(seed));
+            plain.grow(100);
+            plain.next_until(99);
+            plain_repeats += repeats(&plain);
+
+            let mut balanced = LazyRandomIndex::from(SmallRng::seed_from_u64(seed));
+            balanced.grow(100);
+            balanced.next_until_balanced(99, same_artist);
+            assert_permutation(&balanced, 100);
+            balanced_repeats += repeats(&balanced);
+        }
+        // About 9 repeats per shuffle for the plain one, the balanced one only gets some at the very end
+        assert!(plain_repeats > 700);
+        assert!(balanced_repeats * 10 < plain_repeats);
+    }
+
+    #[test]
+    fn test_balanced_stable() {
+        let mut index = LazyRandomIndex::from(rng_for_test());
+        index.grow(20);
+        index.next_until_balanced(5, same_artist);
+        let generated = index.generated().to_vec();
+
+        index.grow(100);
+        index.next_until_balanced(30, same_artist);
+        assert_eq!(index.generated()[..6], generated[..]);
+        assert_permutation(&index, 100);
+
+        index.reset_picking_first(42);
+        assert_eq!(index.next_until_balanced(0, same_artist), Some(42));
+        assert!(!same_artist(
+            42,
+            index.next_until_balanced(1, same_artist).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut index = LazyRandomIndex::from(rng_for_test());
+        index.grow(10);
+        index.next_until(4);
+        let generated = index.generated().to_vec();
+        assert_eq!(generated.len(), 5);
+
+        let mut restored = LazyRandomIndex::from(rng_for_test());
+        restored.restore(&generated, 10);
+        assert_eq!(restored.generated(), &generated[..]);
+
+        // The remaining mappings can still be generated
+        restored.next_until(9);
+        let mut all = restored.generated().to_vec();
+        all.sort();
+        assert_eq!(all, (0..10).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_restore_invalid() {
+        let mut index = LazyRandomIndex::from(rng_for_test());
+        index.restore(&[1, 1], 3);
+        assert_eq!(index.generated(), &[] as &[usize]);
+        index.restore(&[5], 3);
+        assert_eq!(index.get(0), None);
+        assert!(index.next_until(2).is_some());
+    }
 }
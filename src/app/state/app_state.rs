@@ -1,14 +1,16 @@
 use std::borrow::Cow;
 
-use crate::app::models::{PlaylistDescription, PlaylistSummary};
+use crate::app::models::{PlaylistDescription, PlaylistSummary, SongDescription};
 use crate::app::state::{
     browser_state::{BrowserAction, BrowserEvent, BrowserState},
     login_state::{LoginAction, LoginEvent, LoginState},
-    playback_state::{PlaybackAction, PlaybackEvent, PlaybackState},
+    playback_state::{Dequeued, PlaybackAction, PlaybackEvent, PlaybackState},
     selection_state::{SelectionAction, SelectionContext, SelectionEvent, SelectionState},
     settings_state::{SettingsAction, SettingsEvent, SettingsState},
+    undo_stack::{Inverse, UndoStack},
     ScreenName, UpdatableState,
 };
+use crate::app::SpotifyLink;
 
 // It's a big one...
 // All possible actions!
@@ -26,8 +28,13 @@ pub enum AppAction {
     Raise,
     ShowNotification(String),
     ViewNowPlaying,
+    // Opens the album of a track, and plays that track
+    OpenTrack(String),
+    // Adds the track currently playing to the saved tracks
+    SaveCurrentTrack,
     // Cross-state actions
     QueueSelection,
+    PlayNextSelection,
     DequeueSelection,
     MoveUpSelection,
     MoveDownSelection,
@@ -37,32 +44,26 @@ pub enum AppAction {
     CancelSelection,
     CreatePlaylist(PlaylistDescription),
     UpdatePlaylistName(PlaylistSummary),
+    // Undo the last destructive action (removing tracks from a playlist, unsaving...)
+    Undo,
+    // Undo a specific action, by the id it was recorded with
+    UndoEntry(u32),
+    // Put back an action whose undoing failed
+    RecordUndo(Inverse),
 }
 
 // Not actual actions, just neat wrappers
 impl AppAction {
-    // An action to open a Spotify URI
+    // An action to open a Spotify URI or link
     #[allow(non_snake_case)]
     pub fn OpenURI(uri: String) -> Option<Self> {
         debug!("parsing {}", &uri);
-        let mut parts = uri.split(':');
-        if parts.next()? != "spotify" {
-            return None;
-        }
-
-        // Might start with /// because of https://gitlab.gnome.org/GNOME/glib/-/issues/1886/
-        let action = parts
-            .next()?
-            .strip_prefix("///")
-            .filter(|p| !p.is_empty())?;
-        let data = parts.next()?;
-
-        match action {
-            "album" => Some(Self::ViewAlbum(data.to_string())),
-            "artist" => Some(Self::ViewArtist(data.to_string())),
-            "playlist" => Some(Self::ViewPlaylist(data.to_string())),
-            "user" => Some(Self::ViewUser(data.to_string())),
-            _ => None,
+        match SpotifyLink::parse(&uri)? {
+            SpotifyLink::Track(id) => Some(Self::OpenTrack(id)),
+            SpotifyLink::Album(id) => Some(Self::ViewAlbum(id)),
+            SpotifyLink::Artist(id) => Some(Self::ViewArtist(id)),
+            SpotifyLink::Playlist(id) => Some(Self::ViewPlaylist(id)),
+            SpotifyLink::User(id) => Some(Self::ViewUser(id)),
         }
     }
 
@@ -105,7 +106,14 @@ pub enum AppEvent {
     NotificationShown(String),
     PlaylistCreatedNotificationShown(String),
     NowPlayingShown,
+    TrackOpened(String),
+    // Saving requires calling the API first
+    CurrentTrackSaveRequested(SongDescription),
     SettingsEvent(SettingsEvent),
+    // Something that can be undone happened
+    UndoRecorded(u32, Inverse),
+    // Undoing requires calling the API first
+    UndoRequested(Inverse),
 }
 
 // The actual state, split five-ways
@@ -116,6 +124,7 @@ pub struct AppState {
     pub selection: SelectionState,
     pub logged_user: LoginState,
     pub settings: SettingsState,
+    undo: UndoStack,
 }
 
 impl AppState {
@@ -127,7 +136,50 @@ impl AppState {
             selection: Default::default(),
             logged_user: Default::default(),
             settings: Default::default(),
+            undo: Default::default(),
+        }
+    }
+
+    fn record(&mut self, inverse: Inverse) -> AppEvent {
+        let id = self.undo.push(inverse.clone());
+        AppEvent::UndoRecorded(id, inverse)
+    }
+
+    fn undo(&mut self, inverse: Option<Inverse>) -> Vec<AppEvent> {
+        match inverse {
+            Some(Inverse::Requeue(dequeued)) => {
+                forward_action(PlaybackAction::Requeue(dequeued), &mut self.playback)
+            }
+            Some(inverse) => vec![AppEvent::UndoRequested(inverse)],
+            None => vec![],
+        }
+    }
+
+    fn dequeue(&mut self, ids: &[String]) -> Vec<AppEvent> {
+        let dequeued = self.playback.dequeue(ids);
+        self.record_dequeued(dequeued)
+    }
+
+    fn record_dequeued(&mut self, dequeued: Dequeued) -> Vec<AppEvent> {
+        let mut events: Vec<AppEvent> = dequeued.events().into_iter().map(|e| e.into()).collect();
+        if !dequeued.is_empty() {
+            events.push(self.record(Inverse::Requeue(dequeued)));
         }
+        events
+    }
+
+    // The tracks of a playlist that have one of these uris, and where they are
+    fn playlist_tracks(&self, id: &str, uris: &[String]) -> Vec<(usize, SongDescription)> {
+        let songs = match self.browser.playlist_details_state(id) {
+            Some(state) => &state.songs,
+            None => return vec![],
+        };
+        songs
+            .collect()
+            .into_iter()
+            .filter(|song| uris.contains(&song.uri))
+            .filter_map(|song| Some((songs.find_index(&song.id)?, song)))
+            .collect()
     }
 
     pub fn update_state(&mut self, message: AppAction) -> Vec<AppEvent> {
@@ -140,15 +192,33 @@ impl AppState {
             // they're here just to have a consistent way of doing things (always an Action)
             AppAction::ShowNotification(c) => vec![AppEvent::NotificationShown(c)],
             AppAction::ViewNowPlaying => vec![AppEvent::NowPlayingShown],
+            AppAction::OpenTrack(id) => vec![AppEvent::TrackOpened(id)],
+            AppAction::SaveCurrentTrack => self
+                .playback
+                .current_song()
+                .map(AppEvent::CurrentTrackSaveRequested)
+                .into_iter()
+                .collect(),
             AppAction::Raise => vec![AppEvent::Raised],
             // Cross-state actions: multiple "substates" are affected by these actions, that's why they're handled here
             // Might need some clean-up
             AppAction::QueueSelection => {
-                self.playback.queue(self.selection.take_selection());
-                vec![
-                    SelectionEvent::SelectionModeChanged(false).into(),
-                    PlaybackEvent::PlaylistChanged.into(),
-                ]
+                let tracks = self.selection.take_selection();
+                let mut events = vec![SelectionEvent::SelectionModeChanged(false).into()];
+                events.append(&mut forward_action(
+                    PlaybackAction::Queue(tracks),
+                    &mut self.playback,
+                ));
+                events
+            }
+            AppAction::PlayNextSelection => {
+                let tracks = self.selection.take_selection();
+                let mut events = vec![SelectionEvent::SelectionModeChanged(false).into()];
+                events.append(&mut forward_action(
+                    PlaybackAction::PlayNext(tracks),
+                    &mut self.playback,
+                ));
+                events
             }
             AppAction::DequeueSelection => {
                 let tracks: Vec<String> = self
@@ -157,12 +227,14 @@ impl AppState {
                     .into_iter()
                     .map(|s| s.id)
                     .collect();
-                self.playback.dequeue(&tracks);
-
-                vec![
-                    SelectionEvent::SelectionModeChanged(false).into(),
-                    PlaybackEvent::PlaylistChanged.into(),
-                ]
+                let mut events = vec![SelectionEvent::SelectionModeChanged(false).into()];
+                events.append(&mut self.dequeue(&tracks));
+                events
+            }
+            AppAction::PlaybackAction(PlaybackAction::Dequeue(id)) => self.dequeue(&[id]),
+            AppAction::PlaybackAction(PlaybackAction::DequeueNextUp(id)) => {
+                let dequeued = self.playback.dequeue_next_up(&[id]);
+                self.record_dequeued(dequeued)
             }
             AppAction::MoveDownSelection => {
                 let mut selection = self.selection.peek_selection();
@@ -192,19 +264,42 @@ impl AppState {
                 events
             }
             AppAction::UnsaveSelection => {
-                let tracks: Vec<String> = self
-                    .selection
-                    .take_selection()
-                    .into_iter()
-                    .map(|s| s.id)
-                    .collect();
+                let tracks = self.selection.take_selection();
+                let ids: Vec<String> = tracks.iter().map(|s| s.id.clone()).collect();
                 let mut events: Vec<AppEvent> = forward_action(
-                    BrowserAction::RemoveSavedTracks(tracks),
+                    BrowserAction::RemoveSavedTracks(ids),
                     self.browser.home_state_mut().unwrap(),
                 );
                 events.push(SelectionEvent::SelectionModeChanged(false).into());
+                events.push(self.record(Inverse::SaveTracks(tracks)));
+                events
+            }
+            AppAction::BrowserAction(BrowserAction::RemoveTracksFromPlaylist(id, uris)) => {
+                let removed = self.playlist_tracks(&id, &uris);
+                let mut events = forward_action(
+                    BrowserAction::RemoveTracksFromPlaylist(id.clone(), uris),
+                    &mut self.browser,
+                );
+                if !removed.is_empty() {
+                    events.push(self.record(Inverse::AddToPlaylist(id, removed)));
+                }
+                events
+            }
+            AppAction::BrowserAction(BrowserAction::UnsaveAlbum(id)) => {
+                let mut events =
+                    forward_action(BrowserAction::UnsaveAlbum(id.clone()), &mut self.browser);
+                events.push(self.record(Inverse::SaveAlbum(id)));
                 events
             }
+            AppAction::Undo => {
+                let inverse = self.undo.pop();
+                self.undo(inverse)
+            }
+            AppAction::UndoEntry(id) => {
+                let inverse = self.undo.take(id);
+                self.undo(inverse)
+            }
+            AppAction::RecordUndo(inverse) => vec![self.record(inverse)],
             AppAction::EnableSelection(context) => {
                 if let Some(active) = self.selection.set_mode(Some(context)) {
                     vec![SelectionEvent::SelectionModeChanged(active).into()]
@@ -244,7 +339,18 @@ impl AppState {
                 events
             }
             // As for all other actions, we forward them to the substates :)
-            AppAction::PlaybackAction(a) => forward_action(a, &mut self.playback),
+            AppAction::PlaybackAction(a) => {
+                let events = forward_action(a, &mut self.playback);
+                let source_changed = events
+                    .iter()
+                    .any(|e| matches!(e, AppEvent::PlaybackEvent(PlaybackEvent::SourceChanged)));
+                if source_changed {
+                    // Tracks dequeued from another list can't be put back into this one
+                    self.undo
+                        .forget(|inverse| matches!(inverse, Inverse::Requeue(_)));
+                }
+                events
+            }
             AppAction::BrowserAction(a) => forward_action(a, &mut self.browser),
             AppAction::SelectionAction(a) => forward_action(a, &mut self.selection),
             AppAction::LoginAction(a) => forward_action(a, &mut self.logged_user),
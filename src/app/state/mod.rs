@@ -7,6 +7,7 @@ mod playback_state;
 mod screen_states;
 mod selection_state;
 mod settings_state;
+mod undo_stack;
 
 pub use app_model::AppModel;
 pub use app_state::*;
@@ -17,6 +18,7 @@ pub use playback_state::*;
 pub use screen_states::*;
 pub use selection_state::*;
 pub use settings_state::*;
+pub use undo_stack::*;
 
 pub trait UpdatableState {
     type Action: Clone;
@@ -1,5 +1,6 @@
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crate::app::models::*;
 use crate::app::state::{AppAction, AppEvent, UpdatableState};
@@ -13,12 +14,33 @@ pub struct PlaybackState {
     index: LazyRandomIndex,
     // The actual list like thing backing the currently playing tracks
     songs: SongListModel,
+    // Tracks queued by the user (or on a Connect device), which play before the rest of the source
+    next_up: SongListModel,
+    // The track from next_up that is playing, if any (list_position then points to the track before it)
+    playing_next_up: Option<SongDescription>,
     list_position: Option<usize>,
     seek_position: PositionMillis,
     source: Option<SongsSource>,
     repeat: RepeatMode,
     is_playing: bool,
     is_shuffled: bool,
+    // Whether shuffling keeps tracks by the same artist or from the same album apart
+    balanced_shuffle: bool,
+    volume: f64,
+    // The sleep timer and when it was set
+    sleep_timer: Option<(SleepTimer, Instant)>,
+}
+
+// Tracks from the same album or sharing an artist (as far as we know: they might not be loaded yet)
+fn are_similar(songs: &SongListModel, a: usize, b: usize) -> bool {
+    let (Some(a), Some(b)) = (songs.index(a), songs.index(b)) else {
+        return false;
+    };
+    let (a, b) = (a.description(), b.description());
+    a.album.id == b.album.id
+        || a.artists
+            .iter()
+            .any(|artist| b.artists.iter().any(|other| other.id == artist.id))
 }
 
 // Most mutatings methods shouldn't be pub
@@ -28,8 +50,20 @@ impl PlaybackState {
         &self.songs
     }
 
+    pub fn next_up(&self) -> &SongListModel {
+        &self.next_up
+    }
+
+    pub fn is_playing_next_up(&self) -> bool {
+        self.playing_next_up.is_some()
+    }
+
+    fn has_current_song(&self) -> bool {
+        self.list_position.is_some() || self.playing_next_up.is_some()
+    }
+
     pub fn is_playing(&self) -> bool {
-        self.is_playing && self.list_position.is_some()
+        self.is_playing && self.has_current_song()
     }
 
     pub fn is_shuffled(&self) -> bool {
@@ -40,9 +74,48 @@ impl PlaybackState {
         self.repeat
     }
 
-    // Whatever batch of songs we would need to grab if we were to play the next track
+    pub fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    pub fn sleep_timer(&self) -> Option<SleepTimer> {
+        self.sleep_timer.map(|(timer, _)| timer)
+    }
+
+    // How long until the sleep timer should kick in (if it can be known)
+    pub fn sleep_timer_remaining(&self) -> Option<Duration> {
+        let (timer, set_at) = self.sleep_timer?;
+        let remaining_ms = match timer {
+            SleepTimer::After(seconds) => {
+                let duration = Duration::from_secs(seconds.into());
+                return Some(duration.saturating_sub(set_at.elapsed()));
+            }
+            SleepTimer::EndOfTrack => self.remaining_in_track()?,
+            SleepTimer::EndOfAlbum => {
+                let current = self.current_song()?;
+                let rest_of_album: u64 = (self.list_position? + 1..self.songs.len())
+                    .map_while(|i| self.index(i))
+                    .take_while(|song| song.album.id == current.album.id)
+                    .map(|song| u64::from(song.duration))
+                    .sum();
+                self.remaining_in_track()? + rest_of_album
+            }
+        };
+        Some(Duration::from_millis(remaining_ms))
+    }
+
+    fn remaining_in_track(&self) -> Option<u64> {
+        let duration = u64::from(self.current_song()?.duration);
+        Some(duration.saturating_sub(self.seek_position.current()))
+    }
+
+    // Whatever batch of songs we would need to grab to play the current track (if we're waiting for it) or the next one
     pub fn next_query(&self) -> Option<BatchQuery> {
-        let next_index = self.next_index()?;
+        let next_index = if self.is_waiting_for_batch() {
+            self.list_position?
+        } else {
+            self.next_index()?
+        };
         let next_index = if self.is_shuffled {
             self.index.get(next_index)?
         } else {
@@ -57,6 +130,25 @@ impl PlaybackState {
         }
     }
 
+    // Queries for all the batches of the source that haven't been loaded yet, and where their songs go in the list
+    pub fn missing_queries(&self) -> Vec<(BatchQuery, usize)> {
+        let source = match self.source.as_ref() {
+            Some(source) => source,
+            None => return vec![],
+        };
+        self.songs
+            .missing_batches_with_starts()
+            .into_iter()
+            .map(|(batch, start)| {
+                let query = BatchQuery {
+                    source: source.clone(),
+                    batch,
+                };
+                (query, start)
+            })
+            .collect()
+    }
+
     fn index(&self, i: usize) -> Option<SongDescription> {
         let song = if self.is_shuffled {
             self.songs.index(self.index.get(i)?)
@@ -66,6 +158,34 @@ impl PlaybackState {
         Some(song?.into_description())
     }
 
+    // The songs (that are loaded) in the order they play, queued ones included, and where the current one is among them
+    pub fn play_order(&self) -> (Vec<SongDescription>, Option<usize>) {
+        let order: Vec<usize> = if self.is_shuffled {
+            self.index.order().to_vec()
+        } else {
+            (0..self.songs.len()).collect()
+        };
+        let song_at = |i: &usize| Some(self.songs.index(*i)?.into_description());
+        let split = self
+            .list_position
+            .map(|p| usize::min(p + 1, order.len()))
+            .unwrap_or(0);
+        let mut songs: Vec<SongDescription> = order[..split].iter().filter_map(song_at).collect();
+        let current = if let Some(song) = self.playing_next_up.as_ref() {
+            songs.push(song.clone());
+            Some(songs.len() - 1)
+        } else {
+            self.list_position
+                .and_then(|p| order.get(p))
+                .and_then(song_at)
+                .map(|_| songs.len() - 1)
+        };
+        // What was queued plays right after the current track
+        songs.extend(self.next_up.map_collect(|s| s));
+        songs.extend(order[split..].iter().filter_map(song_at));
+        (songs, current)
+    }
+
     pub fn current_source(&self) -> Option<&SongsSource> {
         self.source.as_ref()
     }
@@ -75,22 +195,38 @@ impl PlaybackState {
     }
 
     pub fn current_song_id(&self) -> Option<String> {
+        if let Some(song) = self.playing_next_up.as_ref() {
+            return Some(song.id.clone());
+        }
         Some(self.index(self.list_position?)?.id)
     }
 
+    // When shuffling a big playlist, we can skip to a track that hasn't been loaded yet
+    pub fn is_waiting_for_batch(&self) -> bool {
+        self.playing_next_up.is_none()
+            && self.list_position.is_some()
+            && self.current_song_id().is_none()
+    }
+
     pub fn current_song(&self) -> Option<SongDescription> {
+        if let Some(song) = self.playing_next_up.as_ref() {
+            return Some(song.clone());
+        }
         self.index(self.list_position?)
     }
 
     fn next_id(&self) -> Option<String> {
-        self.next_index()
-            .and_then(|i| Some(self.songs().index(i)?.description().id.clone()))
+        if let Some(song) = self.next_up.index(0) {
+            return Some(song.get_id());
+        }
+        Some(self.index(self.next_index()?)?.id)
     }
 
     fn clear(&mut self, source: Option<SongsSource>) -> SongListModelPending {
         self.source = source;
         self.index = Default::default();
         self.list_position = None;
+        self.playing_next_up = None;
         self.songs.clear()
     }
 
@@ -119,11 +255,83 @@ impl PlaybackState {
         self.index.grow(self.songs.len());
     }
 
-    pub fn dequeue(&mut self, ids: &[String]) {
+    // Queued tracks play before the rest of the source, the last ones queued to play next come first
+    // Without anything playing, there is no "rest of the source" and they are simply played in order
+    fn queue_next_up(&mut self, tracks: Vec<SongDescription>, play_next: bool) -> bool {
+        if !self.has_current_song() {
+            self.queue(tracks);
+            return false;
+        }
+        if play_next {
+            self.next_up.prepend(tracks).commit();
+        } else {
+            self.next_up.append(tracks).commit();
+        }
+        true
+    }
+
+    // The tracks with these ids, and where they are in a list (in order)
+    fn positions_in(list: &SongListModel, ids: &[String]) -> Vec<(usize, SongDescription)> {
+        let mut positions: Vec<(usize, SongDescription)> = ids
+            .iter()
+            .filter_map(|id| Some((list.find_index(id)?, list.get(id)?.into_description())))
+            .collect();
+        positions.sort_by_key(|(position, _)| *position);
+        positions
+    }
+
+    // The current track might have moved around
+    fn update_list_position(&mut self, current_id: Option<String>) {
+        let current_index = current_id.and_then(|id| self.songs.find_index(&id));
+        self.list_position = if self.is_shuffled {
+            current_index.and_then(|i| self.index.position_of(i))
+        } else {
+            current_index
+        };
+    }
+
+    pub fn dequeue(&mut self, ids: &[String]) -> Dequeued {
         let current_id = self.current_song_id();
+        let dequeued = Dequeued {
+            songs: Self::positions_in(&self.songs, ids),
+            next_up: Self::positions_in(&self.next_up, ids),
+        };
         self.songs.remove(ids).commit();
-        self.list_position = current_id.and_then(|id| self.songs.find_index(&id));
-        self.index.shrink(self.songs.len());
+        // From the last one, so that the positions of the others stay valid
+        for (index, _) in dequeued.songs.iter().rev() {
+            self.index.remove(*index);
+        }
+        self.update_list_position(current_id);
+        if !dequeued.next_up.is_empty() {
+            self.next_up.remove(ids).commit();
+        }
+        dequeued
+    }
+
+    // Only takes tracks out of "Next up": the same tracks might also be in the source, where they should stay
+    pub fn dequeue_next_up(&mut self, ids: &[String]) -> Dequeued {
+        let dequeued = Dequeued {
+            songs: vec![],
+            next_up: Self::positions_in(&self.next_up, ids),
+        };
+        if !dequeued.next_up.is_empty() {
+            self.next_up.remove(ids).commit();
+        }
+        dequeued
+    }
+
+    // Puts dequeued tracks back where they were
+    pub fn requeue(&mut self, dequeued: Dequeued) {
+        let current_id = self.current_song_id();
+        // From the first one, so that the positions of the next ones are right
+        for (index, song) in dequeued.songs {
+            self.songs.insert(index, vec![song]).commit();
+            self.index.insert(index);
+        }
+        self.update_list_position(current_id);
+        for (index, song) in dequeued.next_up {
+            self.next_up.insert(index, vec![song]).commit();
+        }
     }
 
     // Update the current playing track (identified by a position in the list) if we're swapping songs
@@ -158,6 +366,15 @@ impl PlaybackState {
             return false;
         }
 
+        // Whatever was queued before that track has been skipped
+        if self.next_up.find_index(id).is_some() {
+            while let Some(next_id) = self.play_next_up() {
+                if next_id == id {
+                    return true;
+                }
+            }
+        }
+
         let found_index = self.songs.find_index(id);
 
         if let Some(index) = found_index {
@@ -176,23 +393,105 @@ impl PlaybackState {
 
     fn stop(&mut self) {
         self.list_position = None;
+        self.playing_next_up = None;
         self.is_playing = false;
         self.seek_position.set(0, false);
     }
 
     fn play_index(&mut self, index: usize) -> Option<String> {
         self.is_playing = true;
+        self.playing_next_up = None;
         self.list_position.replace(index);
         self.seek_position.set(0, true);
-        self.index.next_until(index + 1);
+        self.shuffle_until(index + 1);
+        self.shuffle_rest_of_album();
         self.current_song_id()
     }
 
+    // To know when the album ends while shuffling, the order must be picked as far as the album goes
+    fn shuffle_rest_of_album(&mut self) {
+        if !self.is_shuffled || self.sleep_timer() != Some(SleepTimer::EndOfAlbum) {
+            return;
+        }
+        let (Some(position), Some(current)) = (self.list_position, self.current_song()) else {
+            return;
+        };
+        for i in position + 1..self.songs.len() {
+            self.shuffle_until(i);
+            match self.index(i) {
+                Some(song) if song.album.id == current.album.id => continue,
+                _ => break,
+            }
+        }
+    }
+
+    // Generate the shuffled order up to i
+    fn shuffle_until(&mut self, i: usize) {
+        if self.balanced_shuffle {
+            let songs = &self.songs;
+            self.index
+                .next_until_balanced(i, |a, b| are_similar(songs, a, b));
+        } else {
+            self.index.next_until(i);
+        }
+    }
+
+    // Plays the first track of next_up, if any
+    fn play_next_up(&mut self) -> Option<String> {
+        let song = self.next_up.index(0)?.into_description();
+        let id = song.id.clone();
+        self.next_up.remove(&[id.clone()]).commit();
+        self.playing_next_up = Some(song);
+        self.is_playing = true;
+        self.seek_position.set(0, true);
+        Some(id)
+    }
+
     fn play_next(&mut self) -> Option<String> {
-        self.next_index().and_then(|i| {
+        if self.repeat == RepeatMode::Song && self.playing_next_up.is_some() {
+            self.seek_position.set(0, true);
+            return self.current_song_id();
+        }
+        if let Some(id) = self.play_next_up() {
+            return Some(id);
+        }
+        let id = self.next_index().and_then(|i| {
             self.seek_position.set(0, true);
             self.play_index(i)
-        })
+        })?;
+        self.skip_unplayable(id)
+    }
+
+    fn is_playable(&self, id: &str) -> bool {
+        self.songs
+            .get(id)
+            .map(|song| song.get_playable())
+            .unwrap_or(true)
+    }
+
+    fn restriction_of(&self, id: &str) -> Option<Restriction> {
+        self.songs
+            .get(id)
+            .and_then(|song| song.description().restriction)
+    }
+
+    fn mark_unplayable(&mut self, id: &str) {
+        if let Some(song) = self.songs.get(id) {
+            song.set_playable(false);
+        }
+    }
+
+    // Starting from the current song, move forward until we find a song that can be played
+    // (giving up if we went through the whole list)
+    fn skip_unplayable(&mut self, id: String) -> Option<String> {
+        let mut id = id;
+        for _ in 0..self.songs.len() {
+            if self.is_playable(&id) {
+                return Some(id);
+            }
+            id = self.next_index().and_then(|i| self.play_index(i))?;
+        }
+        None
     }
 
     pub fn next_index(&self) -> Option<usize> {
@@ -206,6 +505,11 @@ impl PlaybackState {
     }
 
     fn play_prev(&mut self) -> Option<String> {
+        // Back to the track that played before the queued ones
+        if self.playing_next_up.is_some() && self.seek_position.current() <= 2000 {
+            self.seek_position.set(0, true);
+            return self.list_position.and_then(|i| self.play_index(i));
+        }
         self.prev_index().and_then(|i| {
             // Only jump to the previous track if we aren't more than 2 seconds (2,000 ms) into the current track.
             // Otherwise, seek to the start of the current track.
@@ -231,7 +535,7 @@ impl PlaybackState {
     }
 
     fn toggle_play(&mut self) -> Option<bool> {
-        if self.list_position.is_some() {
+        if self.has_current_song() {
             self.is_playing = !self.is_playing;
 
             match self.is_playing {
@@ -249,6 +553,132 @@ impl PlaybackState {
         self.is_shuffled = shuffled;
         let old = self.list_position.replace(0).unwrap_or(0);
         self.index.reset_picking_first(old);
+        self.shuffle_rest_of_album();
+    }
+
+    // The queue of a device lists what was queued, then what comes next in the source: only the former is kept
+    fn next_up_in(&self, remote_queue: Vec<SongDescription>) -> Vec<SongDescription> {
+        let next_in_source = self
+            .next_index()
+            .filter(|_| !self.is_shuffled)
+            .and_then(|i| self.index(i))
+            .map(|song| song.id);
+        remote_queue
+            .into_iter()
+            .take_while(|song| match next_in_source.as_ref() {
+                Some(next_id) => &song.id != next_id,
+                // Not knowing what comes next, we assume anything from the source is part of it
+                None => self.songs.get(&song.id).is_none(),
+            })
+            .collect()
+    }
+
+    fn sync_next_up(&mut self, remote_queue: Vec<SongDescription>) -> bool {
+        let next_up = self.next_up_in(remote_queue);
+        let unchanged = next_up.iter().map(|song| &song.id).eq(self
+            .next_up
+            .collect()
+            .iter()
+            .map(|song| &song.id));
+        if !unchanged {
+            self.next_up.clear().and(|s| s.append(next_up)).commit();
+        }
+        !unchanged
+    }
+
+    // What to save to resume playback after a restart (if anything is playing)
+    pub fn saved(&self) -> Option<SavedPlayback> {
+        if !self.has_current_song() {
+            return None;
+        }
+        let current_song = self.list_position.and_then(|p| {
+            let i = if self.is_shuffled {
+                self.index.get(p)?
+            } else {
+                p
+            };
+            Some((i, self.songs.index(i)?.get_id()))
+        });
+        Some(SavedPlayback {
+            source: self.source.clone(),
+            song_ids: self.songs.map_collect(|s| s.id),
+            batch_offsets: self
+                .songs
+                .loaded_batches()
+                .iter()
+                .map(|b| b.offset)
+                .collect(),
+            list_position: self.list_position,
+            current_song,
+            shuffle_order: self.index.generated().to_vec(),
+            is_shuffled: self.is_shuffled,
+            repeat: self.repeat,
+            seek_position: self.seek_position.current(),
+            next_up: self.next_up.map_collect(|s| s.id),
+            playing_next_up: self.playing_next_up.as_ref().map(|s| s.id.clone()),
+        })
+    }
+
+    // Picks up where some saved playback left off, paused, once its songs have been loaded again
+    // `queued` are the songs of the saved next_up (led by the one that was playing, if any) that could be loaded again
+    fn restore(&mut self, saved: SavedPlayback, queued: Vec<SongDescription>) -> bool {
+        let mut queued = queued.into_iter().peekable();
+        let playing_next_up = saved
+            .playing_next_up
+            .as_ref()
+            .and_then(|id| queued.next_if(|song| &song.id == id));
+        let current = match saved.current_song.as_ref() {
+            // Right where it was, unless the source changed in the meantime
+            Some((i, id)) => self
+                .songs
+                .index(*i)
+                .filter(|song| &song.get_id() == id)
+                .map(|_| *i)
+                .or_else(|| self.songs.find_index(id)),
+            // Saved before positions were, only works if all songs were loaded
+            None => {
+                let saved_index = if saved.is_shuffled {
+                    saved
+                        .list_position
+                        .and_then(|p| saved.shuffle_order.get(p).copied())
+                } else {
+                    saved.list_position
+                };
+                saved_index
+                    .and_then(|i| saved.song_ids.get(i))
+                    .and_then(|id| self.songs.find_index(id))
+            }
+        };
+        // Playing a queued track before any other is fine, otherwise the list track must still be there
+        if current.is_none() && (saved.list_position.is_some() || playing_next_up.is_none()) {
+            return false;
+        }
+
+        let unchanged = self.songs.map_collect(|s| s.id) == saved.song_ids;
+        self.repeat = saved.repeat;
+        self.is_shuffled = saved.is_shuffled;
+        self.list_position = match current {
+            None => None,
+            Some(current) if !saved.is_shuffled => Some(current),
+            Some(_) if unchanged => {
+                self.index.restore(&saved.shuffle_order, self.songs.len());
+                saved.list_position
+            }
+            Some(current) => {
+                // The source changed in the meantime, the saved order is meaningless
+                self.index.reset_picking_first(current);
+                self.shuffle_until(1);
+                Some(0)
+            }
+        };
+        self.playing_next_up = playing_next_up;
+        self.next_up
+            .clear()
+            .and(|s| s.append(queued.collect()))
+            .commit();
+        self.is_playing = false;
+        self.seek_position.set(saved.seek_position, false);
+        true
     }
 
     pub fn available_devices(&self) -> &Vec<ConnectDevice> {
@@ -267,16 +697,69 @@ impl Default for PlaybackState {
             current_device: Device::Local,
             index: LazyRandomIndex::default(),
             songs: SongListModel::new(50),
+            next_up: SongListModel::new(50),
+            playing_next_up: None,
             list_position: None,
             seek_position: PositionMillis::new(1.0),
             source: None,
             repeat: RepeatMode::None,
             is_playing: false,
             is_shuffled: false,
+            balanced_shuffle: false,
+            volume: 1.0,
+            sleep_timer: None,
+        }
+    }
+}
+
+// Tracks taken out of the queue, and where they were, to be able to put them back
+#[derive(Clone, Debug, Default)]
+pub struct Dequeued {
+    pub songs: Vec<(usize, SongDescription)>,
+    // Queued by the user
+    pub next_up: Vec<(usize, SongDescription)>,
+}
+
+impl Dequeued {
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty() && self.next_up.is_empty()
+    }
+
+    // What changed when these were taken out (or put back)
+    pub fn events(&self) -> Vec<PlaybackEvent> {
+        let mut events = vec![PlaybackEvent::PlaylistChanged];
+        if !self.next_up.is_empty() {
+            events.push(PlaybackEvent::NextUpChanged);
         }
+        events
     }
 }
 
+// Enough to resume playback where it was, provided the songs can be loaded again (from the source or by id)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedPlayback {
+    pub source: Option<SongsSource>,
+    // Only the songs that were loaded
+    pub song_ids: Vec<String>,
+    // Where the batches of the source that were loaded start, to load exactly those again
+    #[serde(default)]
+    pub batch_offsets: Vec<usize>,
+    pub list_position: Option<usize>,
+    // The track at list_position, and where it is among all the songs of the source
+    #[serde(default)]
+    pub current_song: Option<(usize, String)>,
+    // The mappings of the random index, see LazyRandomIndex
+    pub shuffle_order: Vec<usize>,
+    pub is_shuffled: bool,
+    pub repeat: RepeatMode,
+    pub seek_position: u64,
+    // Queued by the user, by id (older saves don't have them)
+    #[serde(default)]
+    pub next_up: Vec<String>,
+    #[serde(default)]
+    pub playing_next_up: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 pub enum PlaybackAction {
     TogglePlay,
@@ -287,6 +770,7 @@ pub enum PlaybackAction {
     SetShuffled(bool),
     ToggleRepeat,
     ToggleShuffle,
+    // Positions are in milliseconds
     Seek(u32),
     // I can't remember the diff betweek Seek and SyncSeek right now. Probably the source of the action
     SyncSeek(u32),
@@ -297,10 +781,24 @@ pub enum PlaybackAction {
     Next,
     Previous,
     Preload,
+    // Add to queue, or play next: both play before the rest of the source
     Queue(Vec<SongDescription>),
+    PlayNext(Vec<SongDescription>),
     Dequeue(String),
+    // Removes a track from the "Next up" section only
+    DequeueNextUp(String),
+    // Undoes a Dequeue (or DequeueNextUp)
+    Requeue(Dequeued),
+    // The queue of a Connect device (not including the current track)
+    SyncNextUp(Vec<SongDescription>),
     SwitchDevice(Device),
     SetAvailableDevices(Vec<ConnectDevice>),
+    // The player could not load that track
+    MarkUnavailable(String),
+    SetSleepTimer(Option<SleepTimer>),
+    SetBalancedShuffle(bool),
+    // Must come after the saved songs were loaded, along with the queued ones
+    Restore(Box<SavedPlayback>, Vec<SongDescription>),
 }
 
 impl From<PlaybackAction> for AppAction {
@@ -309,6 +807,14 @@ impl From<PlaybackAction> for AppAction {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SleepTimer {
+    // Pause after that many seconds
+    After(u32),
+    EndOfTrack,
+    EndOfAlbum,
+}
+
 #[derive(Clone, Debug)]
 pub enum Device {
     Local,
@@ -331,6 +837,14 @@ pub enum PlaybackEvent {
     PlaybackStopped,
     SwitchedDevice(Device),
     AvailableDevicesChanged,
+    // Along with the reason given by Spotify, if any
+    TrackUnavailable(String, Option<Restriction>),
+    SleepTimerChanged(Option<SleepTimer>),
+    NextUpChanged,
+    // The track to play is in a batch that has to be loaded first
+    WaitingForBatch,
+    // These tracks were queued and should be sent to the Connect device
+    AddedToNextUp(Vec<String>),
 }
 
 impl From<PlaybackEvent> for AppEvent {
@@ -397,6 +911,8 @@ impl UpdatableState for PlaybackState {
                         PlaybackEvent::TrackChanged(id),
                         PlaybackEvent::PlaybackResumed,
                     ]
+                } else if self.is_waiting_for_batch() {
+                    vec![PlaybackEvent::WaitingForBatch]
                 } else {
                     self.stop();
                     vec![PlaybackEvent::PlaybackStopped]
@@ -417,14 +933,38 @@ impl UpdatableState for PlaybackState {
                 }
             }
             PlaybackAction::Load(id) => {
-                if self.play(&id) {
+                if !self.play(&id) {
+                    vec![]
+                } else if self.is_playable(&id) {
                     vec![
                         PlaybackEvent::TrackChanged(id),
                         PlaybackEvent::PlaybackResumed,
                     ]
                 } else {
-                    vec![]
+                    let mut events = vec![PlaybackEvent::TrackUnavailable(
+                        id.clone(),
+                        self.restriction_of(&id),
+                    )];
+                    if let Some(id) = self.skip_unplayable(id) {
+                        events.push(PlaybackEvent::TrackChanged(id));
+                        events.push(PlaybackEvent::PlaybackResumed);
+                    } else {
+                        self.stop();
+                        events.push(PlaybackEvent::PlaybackStopped);
+                    }
+                    events
+                }
+            }
+            PlaybackAction::MarkUnavailable(id) => {
+                self.mark_unplayable(&id);
+                let mut events = vec![PlaybackEvent::TrackUnavailable(
+                    id.clone(),
+                    self.restriction_of(&id),
+                )];
+                if self.current_song_id().as_ref() == Some(&id) {
+                    events.append(&mut self.update_with(Cow::Owned(PlaybackAction::Next)));
                 }
+                events
             }
             PlaybackAction::Preload => {
                 if let Some(id) = self.next_id() {
@@ -436,11 +976,17 @@ impl UpdatableState for PlaybackState {
             PlaybackAction::LoadPagedSongs(source, batch)
                 if Some(&source) == self.source.as_ref() =>
             {
-                if self.add_batch(batch) {
-                    vec![PlaybackEvent::PlaylistChanged]
-                } else {
-                    vec![]
+                let was_waiting = self.is_waiting_for_batch();
+                if !self.add_batch(batch) {
+                    return vec![];
+                }
+                let mut events = vec![PlaybackEvent::PlaylistChanged];
+                // That's the batch we were waiting for to play the next track
+                if let Some(id) = self.current_song_id().filter(|_| was_waiting) {
+                    events.push(PlaybackEvent::TrackChanged(id));
+                    events.push(PlaybackEvent::PlaybackResumed);
                 }
+                events
             }
             PlaybackAction::LoadPagedSongs(source, batch)
                 if Some(&source) != self.source.as_ref() =>
@@ -451,32 +997,107 @@ impl UpdatableState for PlaybackState {
             }
             PlaybackAction::LoadSongs(tracks) => {
                 self.set_queue(tracks);
-                vec![PlaybackEvent::PlaylistChanged, PlaybackEvent::SourceChanged]
+                let mut events = vec![PlaybackEvent::PlaylistChanged, PlaybackEvent::SourceChanged];
+                // Without a source, the queue of a device is mirrored as a whole (queued tracks included)
+                let is_connect = matches!(self.current_device, Device::Connect(_));
+                if is_connect && self.next_up.len() > 0 {
+                    self.next_up.clear().commit();
+                    events.push(PlaybackEvent::NextUpChanged);
+                }
+                events
+            }
+            // A device plays its queue before the rest of the source, let's not mess with the latter
+            // (tracks can only be added at the end of that queue)
+            PlaybackAction::Queue(tracks) | PlaybackAction::PlayNext(tracks)
+                if matches!(self.current_device, Device::Connect(_)) =>
+            {
+                let ids = tracks.iter().map(|song| song.id.clone()).collect();
+                self.next_up.append(tracks).commit();
+                vec![
+                    PlaybackEvent::NextUpChanged,
+                    PlaybackEvent::AddedToNextUp(ids),
+                ]
             }
             PlaybackAction::Queue(tracks) => {
-                self.queue(tracks);
-                vec![PlaybackEvent::PlaylistChanged]
+                if self.queue_next_up(tracks, false) {
+                    vec![PlaybackEvent::NextUpChanged]
+                } else {
+                    vec![PlaybackEvent::PlaylistChanged]
+                }
             }
-            PlaybackAction::Dequeue(id) => {
-                self.dequeue(&[id]);
-                vec![PlaybackEvent::PlaylistChanged]
+            PlaybackAction::PlayNext(tracks) => {
+                if self.queue_next_up(tracks, true) {
+                    vec![PlaybackEvent::NextUpChanged]
+                } else {
+                    vec![PlaybackEvent::PlaylistChanged]
+                }
+            }
+            PlaybackAction::SyncNextUp(remote_queue) => {
+                if self.sync_next_up(remote_queue) {
+                    vec![PlaybackEvent::NextUpChanged]
+                } else {
+                    vec![]
+                }
+            }
+            PlaybackAction::Dequeue(id) => self.dequeue(&[id]).events(),
+            PlaybackAction::DequeueNextUp(id) => self.dequeue_next_up(&[id]).events(),
+            PlaybackAction::Requeue(dequeued) => {
+                let events = dequeued.events();
+                self.requeue(dequeued);
+                events
             }
             PlaybackAction::Seek(pos) => {
-                self.seek_position.set(pos as u64 * 1000, true);
+                self.seek_position.set(pos.into(), true);
                 vec![PlaybackEvent::TrackSeeked(pos)]
             }
             PlaybackAction::SyncSeek(pos) => {
-                self.seek_position.set(pos as u64 * 1000, true);
+                self.seek_position.set(pos.into(), true);
                 vec![PlaybackEvent::SeekSynced(pos)]
             }
-            PlaybackAction::SetVolume(volume) => vec![PlaybackEvent::VolumeSet(volume)],
+            PlaybackAction::SetVolume(volume) => {
+                self.volume = volume;
+                vec![PlaybackEvent::VolumeSet(volume)]
+            }
+            PlaybackAction::SetSleepTimer(timer) => {
+                self.sleep_timer = timer.map(|timer| (timer, Instant::now()));
+                self.shuffle_rest_of_album();
+                vec![PlaybackEvent::SleepTimerChanged(timer)]
+            }
+            // Only affects what's shuffled from now on
+            PlaybackAction::SetBalancedShuffle(balanced) => {
+                self.balanced_shuffle = balanced;
+                vec![]
+            }
+            PlaybackAction::Restore(saved, queued) => {
+                let seek_position = saved.seek_position as u32;
+                if self.restore(*saved, queued) {
+                    let mut events = vec![
+                        PlaybackEvent::ShuffleChanged(self.is_shuffled),
+                        PlaybackEvent::RepeatModeChanged(self.repeat),
+                        PlaybackEvent::SourceChanged,
+                        PlaybackEvent::NextUpChanged,
+                    ];
+                    if let Some(id) = self.current_song_id() {
+                        events.push(PlaybackEvent::TrackChanged(id));
+                    }
+                    events.push(PlaybackEvent::TrackSeeked(seek_position));
+                    events
+                } else {
+                    vec![]
+                }
+            }
             PlaybackAction::SetAvailableDevices(list) => {
                 self.available_devices = list;
                 vec![PlaybackEvent::AvailableDevicesChanged]
             }
             PlaybackAction::SwitchDevice(new_device) => {
                 self.current_device = new_device.clone();
-                vec![PlaybackEvent::SwitchedDevice(new_device)]
+                let mut events = vec![PlaybackEvent::SwitchedDevice(new_device)];
+                if self.next_up.len() > 0 {
+                    self.next_up.clear().commit();
+                    events.push(PlaybackEvent::NextUpChanged);
+                }
+                events
             }
             _ => vec![],
         }
@@ -544,10 +1165,14 @@ mod tests {
             album: AlbumRef {
                 id: "".to_string(),
                 name: "".to_string(),
+                artists: vec![],
             },
             duration: 1000,
             art: None,
             track_number: None,
+            disc_number: None,
+            is_playable: true,
+            restriction: None,
         }
     }
 
@@ -652,6 +1277,260 @@ mod tests {
         assert_eq!(state.current_song_id(), Some("1".to_string()));
     }
 
+    #[test]
+    fn test_skip_unavailable() {
+        let mut state = PlaybackState::default();
+        let mut unplayable = song("2");
+        unplayable.is_playable = false;
+        state.queue(vec![song("1"), unplayable, song("3"), song("4")]);
+
+        state.play("1");
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("3".to_string()));
+
+        let events =
+            state.update_with(Cow::Owned(PlaybackAction::MarkUnavailable("3".to_string())));
+        assert_eq!(state.current_song_id(), Some("4".to_string()));
+        assert!(matches!(
+            &events[..],
+            [
+                PlaybackEvent::TrackUnavailable(_, _),
+                PlaybackEvent::TrackChanged(id),
+                PlaybackEvent::PlaybackResumed
+            ] if id == "4"
+        ));
+
+        state.update_with(Cow::Owned(PlaybackAction::MarkUnavailable("4".to_string())));
+        assert!(!state.is_playing());
+        assert!(state.current_song_id().is_none());
+    }
+
+    #[test]
+    fn test_sleep_timer() {
+        let mut state = PlaybackState::default();
+        let mut other_album = song("4");
+        other_album.album.id = "other".to_string();
+        state.queue(vec![song("1"), song("2"), song("3"), other_album]);
+        state.play("2");
+
+        let remaining = |state: &PlaybackState| state.sleep_timer_remaining().unwrap().as_millis();
+
+        state.update_with(Cow::Owned(PlaybackAction::SetSleepTimer(Some(
+            SleepTimer::EndOfTrack,
+        ))));
+        assert!(remaining(&state) <= 1000 && remaining(&state) > 900);
+
+        state.update_with(Cow::Owned(PlaybackAction::SetSleepTimer(Some(
+            SleepTimer::EndOfAlbum,
+        ))));
+        assert!(remaining(&state) <= 2000 && remaining(&state) > 1900);
+
+        state.update_with(Cow::Owned(PlaybackAction::SetSleepTimer(Some(
+            SleepTimer::After(60),
+        ))));
+        assert!(remaining(&state) <= 60_000 && remaining(&state) > 59_900);
+
+        let events = state.update_with(Cow::Owned(PlaybackAction::SetSleepTimer(None)));
+        assert!(matches!(
+            &events[..],
+            [PlaybackEvent::SleepTimerChanged(None)]
+        ));
+        assert!(state.sleep_timer_remaining().is_none());
+    }
+
+    #[test]
+    fn test_sleep_timer_end_of_album_shuffled() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2"), song("3")]);
+        state.play("1");
+        state.set_shuffled(true);
+
+        state.update_with(Cow::Owned(PlaybackAction::SetSleepTimer(Some(
+            SleepTimer::EndOfAlbum,
+        ))));
+        // Whatever the order, all three tracks are from the same album
+        let remaining = state.sleep_timer_remaining().unwrap().as_millis();
+        assert!(remaining <= 3000 && remaining > 2900);
+    }
+
+    #[test]
+    fn test_seek_in_milliseconds() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1")]);
+        state.play("1");
+
+        state.update_with(Cow::Owned(PlaybackAction::Seek(500)));
+        let position = state.seek_position.current();
+        assert!((500..1000).contains(&position));
+    }
+
+    fn batch(songs: Vec<SongDescription>) -> SongBatch {
+        let total = songs.len();
+        SongBatch {
+            songs,
+            batch: Batch {
+                offset: 0,
+                batch_size: 50,
+                total,
+            },
+        }
+    }
+
+    fn connect_device() -> Device {
+        Device::Connect(ConnectDevice {
+            id: "phone".to_string(),
+            label: "Phone".to_string(),
+            kind: ConnectDeviceKind::Phone,
+            volume: Some(50),
+            is_active: true,
+            is_restricted: false,
+        })
+    }
+
+    fn next_up_ids(state: &PlaybackState) -> Vec<String> {
+        state.next_up().map_collect(|s| s.id)
+    }
+
+    #[test]
+    fn test_queue_on_connect_device() {
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::SwitchDevice(connect_device())));
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            SongsSource::Playlist("p".to_string()),
+            batch(vec![song("1"), song("2"), song("3")]),
+        )));
+        state.play("1");
+
+        let events = state.update_with(Cow::Owned(PlaybackAction::Queue(vec![song("a")])));
+        assert!(matches!(
+            &events[..],
+            [
+                PlaybackEvent::NextUpChanged,
+                PlaybackEvent::AddedToNextUp(ids)
+            ] if ids == &["a".to_string()]
+        ));
+        // The source is left alone
+        assert_eq!(state.songs().len(), 3);
+        assert!(state.current_source().is_some());
+
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("a".to_string()));
+        assert!(state.is_playing_next_up());
+        assert!(next_up_ids(&state).is_empty());
+
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("2".to_string()));
+        assert!(!state.is_playing_next_up());
+    }
+
+    #[test]
+    fn test_play_next() {
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            SongsSource::Playlist("p".to_string()),
+            batch(vec![song("1"), song("2"), song("3")]),
+        )));
+        state.play("1");
+
+        let events = state.update_with(Cow::Owned(PlaybackAction::Queue(vec![song("a")])));
+        assert!(matches!(&events[..], [PlaybackEvent::NextUpChanged]));
+        state.update_with(Cow::Owned(PlaybackAction::PlayNext(vec![song("b")])));
+        assert_eq!(next_up_ids(&state), vec!["b".to_string(), "a".to_string()]);
+        // The source is left alone
+        assert_eq!(state.songs().len(), 3);
+        assert!(state.current_source().is_some());
+
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("b".to_string()));
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("a".to_string()));
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("2".to_string()));
+        assert!(next_up_ids(&state).is_empty());
+    }
+
+    #[test]
+    fn test_play_next_when_stopped() {
+        let mut state = PlaybackState::default();
+        let events = state.update_with(Cow::Owned(PlaybackAction::PlayNext(vec![song("a")])));
+        assert!(matches!(&events[..], [PlaybackEvent::PlaylistChanged]));
+        assert_eq!(state.songs().len(), 1);
+        assert!(next_up_ids(&state).is_empty());
+    }
+
+    #[test]
+    fn test_dequeue_next_up() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2")]);
+        state.play("1");
+        state.update_with(Cow::Owned(PlaybackAction::Queue(vec![
+            song("a"),
+            song("b"),
+        ])));
+
+        let events = state.update_with(Cow::Owned(PlaybackAction::Dequeue("a".to_string())));
+        assert!(matches!(
+            &events[..],
+            [PlaybackEvent::PlaylistChanged, PlaybackEvent::NextUpChanged]
+        ));
+        assert_eq!(next_up_ids(&state), vec!["b".to_string()]);
+        assert!(state.dequeue(&["2".to_string()]).next_up.is_empty());
+    }
+
+    #[test]
+    fn test_dequeue_next_up_keeps_source() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2"), song("3")]);
+        state.play("1");
+        state.update_with(Cow::Owned(PlaybackAction::PlayNext(vec![song("3")])));
+        assert_eq!(next_up_ids(&state), vec!["3".to_string()]);
+
+        let events = state.update_with(Cow::Owned(PlaybackAction::DequeueNextUp("3".to_string())));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, PlaybackEvent::NextUpChanged)));
+        assert!(next_up_ids(&state).is_empty());
+        assert_eq!(state.song_ids(), vec!["1", "2", "3"]);
+        assert_eq!(state.next_id(), Some("2".to_string()));
+    }
+
+    #[test]
+    fn test_sync_next_up() {
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::SwitchDevice(connect_device())));
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            SongsSource::Playlist("p".to_string()),
+            batch(vec![song("1"), song("2"), song("3")]),
+        )));
+        state.play("1");
+
+        // Queued from another client, followed by the rest of the playlist
+        let events = state.update_with(Cow::Owned(PlaybackAction::SyncNextUp(vec![
+            song("a"),
+            song("b"),
+            song("2"),
+            song("3"),
+        ])));
+        assert!(matches!(&events[..], [PlaybackEvent::NextUpChanged]));
+        assert_eq!(next_up_ids(&state), vec!["a".to_string(), "b".to_string()]);
+
+        let events = state.update_with(Cow::Owned(PlaybackAction::SyncNextUp(vec![
+            song("a"),
+            song("b"),
+            song("2"),
+        ])));
+        assert!(events.is_empty());
+
+        // The device skipped ahead to b
+        state.update_with(Cow::Owned(PlaybackAction::Load("b".to_string())));
+        assert_eq!(state.current_song_id(), Some("b".to_string()));
+        assert!(next_up_ids(&state).is_empty());
+
+        state.update_with(Cow::Owned(PlaybackAction::Load("2".to_string())));
+        assert_eq!(state.current_song_id(), Some("2".to_string()));
+        assert_eq!(state.current_song_index(), Some(1));
+    }
+
     #[test]
     fn test_shuffle() {
         let mut state = PlaybackState::default();
@@ -684,6 +1563,257 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_play_order() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2"), song("3"), song("4")]);
+        state.play("3");
+
+        let (songs, current) = state.play_order();
+        let ids: Vec<&str> = songs.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2", "3", "4"]);
+        assert_eq!(current, Some(2));
+
+        state.set_shuffled(true);
+        state.play_next();
+        let next = state.current_song_id().unwrap();
+
+        let (songs, current) = state.play_order();
+        assert_eq!(songs.len(), 4);
+        assert_eq!(songs[0].id, "3");
+        assert_eq!(songs[1].id, next);
+        assert_eq!(current, Some(1));
+    }
+
+    #[test]
+    fn test_play_order_with_next_up() {
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            SongsSource::Playlist("p".to_string()),
+            batch(vec![song("1"), song("2"), song("3")]),
+        )));
+        state.play("1");
+        state.update_with(Cow::Owned(PlaybackAction::Queue(vec![
+            song("a"),
+            song("b"),
+        ])));
+
+        let ids = |state: &PlaybackState| -> (Vec<String>, Option<usize>) {
+            let (songs, current) = state.play_order();
+            (songs.into_iter().map(|s| s.id).collect(), current)
+        };
+        assert_eq!(
+            ids(&state),
+            (
+                vec!["1", "a", "b", "2", "3"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                Some(0)
+            )
+        );
+
+        state.play_next();
+        assert_eq!(
+            ids(&state),
+            (
+                vec!["1", "a", "b", "2", "3"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                Some(1)
+            )
+        );
+    }
+
+    #[test]
+    fn test_shuffle_unloaded_batch() {
+        let source = SongsSource::Playlist("p".to_string());
+        let songs = |range: std::ops::Range<usize>| -> Vec<SongDescription> {
+            range.map(|i| song(&i.to_string())).collect()
+        };
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            source.clone(),
+            SongBatch {
+                songs: songs(0..50),
+                batch: Batch {
+                    offset: 0,
+                    batch_size: 50,
+                    total: 100,
+                },
+            },
+        )));
+        state.set_shuffled(true);
+        state.play("0");
+
+        // Sooner or later, a track from the second batch comes up
+        let waiting = (0..50).any(|_| {
+            let events = state.update_with(Cow::Owned(PlaybackAction::Next));
+            matches!(&events[..], [PlaybackEvent::WaitingForBatch])
+        });
+        assert!(waiting);
+        assert!(state.current_song_id().is_none());
+
+        let query = state.next_query().unwrap();
+        assert_eq!(query.batch.offset, 50);
+        let events = state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            source,
+            SongBatch {
+                songs: songs(50..100),
+                batch: query.batch,
+            },
+        )));
+        let id = state.current_song_id().unwrap();
+        assert!(id.parse::<usize>().unwrap() >= 50);
+        assert!(matches!(
+            &events[..],
+            [
+                PlaybackEvent::PlaylistChanged,
+                PlaybackEvent::TrackChanged(track_id),
+                PlaybackEvent::PlaybackResumed
+            ] if track_id == &id
+        ));
+    }
+
+    #[test]
+    fn test_shuffle_onto_gap_left_by_dequeue() {
+        let source = SongsSource::Playlist("p".to_string());
+        let songs = |range: std::ops::Range<usize>| -> Vec<SongDescription> {
+            range.map(|i| song(&i.to_string())).collect()
+        };
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            source.clone(),
+            SongBatch {
+                songs: songs(0..50),
+                batch: Batch {
+                    offset: 0,
+                    batch_size: 50,
+                    total: 51,
+                },
+            },
+        )));
+        state.play("0");
+        state.dequeue(&["1".to_string()]);
+        assert_eq!(state.songs().len(), 50);
+        state.set_shuffled(true);
+
+        // The only track missing is the last one, which moved down to where song 49 was
+        let waiting = (0..50).any(|_| {
+            let events = state.update_with(Cow::Owned(PlaybackAction::Next));
+            matches!(&events[..], [PlaybackEvent::WaitingForBatch])
+        });
+        assert!(waiting);
+
+        let query = state.next_query().unwrap();
+        assert_eq!(query.batch.offset, 50);
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            source,
+            SongBatch {
+                songs: songs(50..51),
+                batch: query.batch,
+            },
+        )));
+        assert_eq!(state.current_song_id(), Some("50".to_string()));
+        assert_eq!(state.songs().len(), 50);
+        assert!(state.missing_queries().is_empty());
+    }
+
+    #[test]
+    fn test_dequeue_shuffled() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2"), song("3"), song("4")]);
+        state.set_shuffled(true);
+        state.play("3");
+        state.play_next();
+        let current = state.current_song_id().unwrap();
+
+        let other = ["1", "2", "4"].iter().find(|id| **id != current).unwrap();
+        state.dequeue(&[other.to_string(), "not_there".to_string()]);
+        assert_eq!(state.songs().len(), 3);
+        assert_eq!(state.current_song_id(), Some(current));
+    }
+
+    #[test]
+    fn test_requeue() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2"), song("3"), song("4")]);
+        state.play("3");
+        state.update_with(Cow::Owned(PlaybackAction::Queue(vec![song("a")])));
+
+        let dequeued = state.dequeue(&["1".to_string(), "4".to_string(), "a".to_string()]);
+        assert_eq!(state.song_ids(), vec!["2", "3"]);
+        assert!(next_up_ids(&state).is_empty());
+
+        let events = state.update_with(Cow::Owned(PlaybackAction::Requeue(dequeued)));
+        assert!(matches!(
+            &events[..],
+            [PlaybackEvent::PlaylistChanged, PlaybackEvent::NextUpChanged]
+        ));
+        assert_eq!(state.song_ids(), vec!["1", "2", "3", "4"]);
+        assert_eq!(next_up_ids(&state), vec!["a".to_string()]);
+        assert_eq!(state.current_song_id(), Some("3".to_string()));
+        assert_eq!(state.next_id(), Some("a".to_string()));
+    }
+
+    fn song_by(id: &str, artist: &str, album: &str) -> SongDescription {
+        let artist = ArtistRef {
+            id: artist.to_string(),
+            name: artist.to_string(),
+        };
+        SongDescription {
+            artists: vec![artist],
+            album: AlbumRef {
+                id: album.to_string(),
+                name: album.to_string(),
+                artists: vec![],
+            },
+            ..song(id)
+        }
+    }
+
+    #[test]
+    fn test_are_similar() {
+        let mut songs = SongListModel::new(50);
+        songs
+            .append(vec![
+                song_by("1", "a", "x"),
+                song_by("2", "a", "y"),
+                song_by("3", "b", "y"),
+                song_by("4", "c", "z"),
+            ])
+            .commit();
+        assert!(are_similar(&songs, 0, 1));
+        assert!(are_similar(&songs, 1, 2));
+        assert!(!are_similar(&songs, 0, 3));
+        // Not loaded
+        assert!(!are_similar(&songs, 0, 10));
+    }
+
+    #[test]
+    fn test_balanced_shuffle() {
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::SetBalancedShuffle(true)));
+        state.queue(
+            (0..20)
+                .map(|i| song_by(&i.to_string(), &(i % 4).to_string(), "x"))
+                .collect(),
+        );
+        state.set_shuffled(true);
+        state.play("0");
+
+        let mut played = vec![state.current_song_id().unwrap()];
+        while let Some(id) = state.play_next() {
+            played.push(id);
+        }
+        played.sort_by_key(|id| id.parse::<usize>().unwrap());
+        assert_eq!(
+            played,
+            (0..20).map(|i| i.to_string()).collect::<Vec<String>>()
+        );
+    }
+
     #[test]
     fn test_shuffle_queue() {
         let mut state = PlaybackState::default();
@@ -782,4 +1912,147 @@ mod tests {
         state.dequeue(&["3".to_string()]);
         assert_eq!(state.current_song_id(), None);
     }
+
+    fn restored(saved: SavedPlayback, songs: Vec<SongDescription>) -> PlaybackState {
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadSongs(songs)));
+        state.update_with(Cow::Owned(PlaybackAction::Restore(Box::new(saved), vec![])));
+        state
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let mut state = PlaybackState::default();
+        assert_eq!(state.saved(), None);
+
+        state.queue(vec![song("1"), song("2"), song("3")]);
+        state.play("2");
+        state.update_with(Cow::Owned(PlaybackAction::SetRepeatMode(
+            RepeatMode::Playlist,
+        )));
+        state.update_with(Cow::Owned(PlaybackAction::Seek(500)));
+        let saved = state.saved().unwrap();
+        assert_eq!(saved.song_ids, vec!["1", "2", "3"]);
+        assert_eq!(saved.list_position, Some(1));
+
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadSongs(vec![
+            song("1"),
+            song("2"),
+            song("3"),
+        ])));
+        let events =
+            state.update_with(Cow::Owned(PlaybackAction::Restore(Box::new(saved), vec![])));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, PlaybackEvent::TrackChanged(id) if id == "2")));
+        assert!(!state.is_playing());
+        assert_eq!(state.current_song_id(), Some("2".to_string()));
+        assert_eq!(state.repeat_mode(), RepeatMode::Playlist);
+        assert!(state.seek_position.current() >= 500);
+    }
+
+    #[test]
+    fn test_save_and_restore_next_up() {
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadPagedSongs(
+            SongsSource::Playlist("p".to_string()),
+            batch(vec![song("1"), song("2")]),
+        )));
+        state.play("1");
+        state.update_with(Cow::Owned(PlaybackAction::Queue(vec![
+            song("a"),
+            song("b"),
+        ])));
+        state.play_next();
+        let saved = state.saved().unwrap();
+        assert_eq!(saved.playing_next_up, Some("a".to_string()));
+        assert_eq!(saved.next_up, vec!["b"]);
+
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(PlaybackAction::LoadSongs(vec![
+            song("1"),
+            song("2"),
+        ])));
+        let events = state.update_with(Cow::Owned(PlaybackAction::Restore(
+            Box::new(saved),
+            vec![song("a"), song("b")],
+        )));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, PlaybackEvent::NextUpChanged)));
+        assert!(state.is_playing_next_up());
+        assert_eq!(state.current_song_id(), Some("a".to_string()));
+        assert_eq!(next_up_ids(&state), vec!["b".to_string()]);
+
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("b".to_string()));
+        state.play_next();
+        assert_eq!(state.current_song_id(), Some("2".to_string()));
+    }
+
+    #[test]
+    fn test_restore_shuffled() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2"), song("3"), song("4")]);
+        state.play("3");
+        state.set_shuffled(true);
+        state.play_next();
+        let saved = state.saved().unwrap();
+        let current = state.current_song_id();
+
+        let state = restored(saved, vec![song("1"), song("2"), song("3"), song("4")]);
+        assert!(state.is_shuffled());
+        assert_eq!(state.current_position(), Some(1));
+        assert_eq!(state.current_song_id(), current);
+        assert_eq!(state.index(0).map(|s| s.id), Some("3".to_string()));
+    }
+
+    #[test]
+    fn test_restore_partially_loaded() {
+        let source = SongsSource::Playlist("p".to_string());
+        let second_batch = || {
+            PlaybackAction::LoadPagedSongs(
+                source.clone(),
+                SongBatch {
+                    songs: (50..100).map(|i| song(&i.to_string())).collect(),
+                    batch: Batch {
+                        offset: 50,
+                        batch_size: 50,
+                        total: 150,
+                    },
+                },
+            )
+        };
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(second_batch()));
+        state.play("60");
+        state.set_shuffled(true);
+        let saved = state.saved().unwrap();
+        assert_eq!(saved.batch_offsets, vec![50]);
+        assert_eq!(saved.current_song, Some((60, "60".to_string())));
+
+        let mut state = PlaybackState::default();
+        state.update_with(Cow::Owned(second_batch()));
+        state.update_with(Cow::Owned(PlaybackAction::Restore(Box::new(saved), vec![])));
+        assert!(state.is_shuffled());
+        assert_eq!(state.current_song_id(), Some("60".to_string()));
+    }
+
+    #[test]
+    fn test_restore_with_other_songs() {
+        let mut state = PlaybackState::default();
+        state.queue(vec![song("1"), song("2"), song("3")]);
+        state.play("3");
+        state.set_shuffled(true);
+        let saved = state.saved().unwrap();
+
+        // Some songs were removed from the source in the meantime
+        let state = restored(saved.clone(), vec![song("2"), song("3")]);
+        assert!(state.is_shuffled());
+        assert_eq!(state.current_song_id(), Some("3".to_string()));
+
+        let state = restored(saved, vec![song("1"), song("2")]);
+        assert_eq!(state.current_song_id(), None);
+    }
 }
@@ -136,9 +136,22 @@ impl UpdatableState for PlaylistDetailsState {
                 vec![BrowserEvent::PlaylistTracksAppended(id.clone())]
             }
             BrowserAction::RemoveTracksFromPlaylist(id, uris) if id == &self.id => {
-                self.songs.remove(&uris[..]).commit();
+                let ids: Vec<String> = self
+                    .songs
+                    .collect()
+                    .into_iter()
+                    .filter(|s| uris.contains(&s.uri))
+                    .map(|s| s.id)
+                    .collect();
+                self.songs.remove(&ids[..]).commit();
                 vec![BrowserEvent::PlaylistTracksRemoved(self.id.clone())]
             }
+            BrowserAction::InsertPlaylistTracks(id, tracks) if id == &self.id => {
+                for (position, song) in tracks {
+                    self.songs.insert(*position, vec![song.clone()]).commit();
+                }
+                vec![BrowserEvent::PlaylistTracksAppended(id.clone())]
+            }
             _ => vec![],
         }
     }
@@ -1,11 +1,13 @@
 use crate::{
     app::state::{AppAction, AppEvent, UpdatableState},
-    settings::SpotSettings,
+    settings::{ResolvedProxy, SpotSettings},
 };
 
 #[derive(Clone, Debug)]
 pub enum SettingsAction {
     ChangeSettings,
+    // The proxy is resolved asynchronously, when logging in and when the settings change
+    SetProxy(ResolvedProxy),
 }
 
 impl From<SettingsAction> for AppAction {
@@ -29,6 +31,19 @@ impl From<SettingsEvent> for AppEvent {
 pub struct SettingsState {
     // Probably shouldn't be stored, the source of truth is GSettings anyway
     pub settings: SpotSettings,
+    pub proxy: ResolvedProxy,
+}
+
+impl SettingsState {
+    fn set_settings(&mut self, new_settings: SpotSettings) -> Vec<AppEvent> {
+        let player_settings_changed = new_settings.player_settings != self.settings.player_settings;
+        self.settings = new_settings;
+        if player_settings_changed {
+            vec![SettingsEvent::PlayerSettingsChanged.into()]
+        } else {
+            vec![]
+        }
+    }
 }
 
 impl UpdatableState for SettingsState {
@@ -38,16 +53,15 @@ impl UpdatableState for SettingsState {
     fn update_with(&mut self, action: std::borrow::Cow<Self::Action>) -> Vec<Self::Event> {
         match action.into_owned() {
             SettingsAction::ChangeSettings => {
-                let old_settings = &self.settings;
-                let new_settings = SpotSettings::new_from_gsettings().unwrap_or_default();
-                let player_settings_changed =
-                    new_settings.player_settings != old_settings.player_settings;
-                self.settings = new_settings;
-                if player_settings_changed {
-                    vec![SettingsEvent::PlayerSettingsChanged.into()]
-                } else {
-                    vec![]
-                }
+                let new_settings = SpotSettings::new_from_gsettings()
+                    .unwrap_or_default()
+                    .with_proxy(&self.proxy);
+                self.set_settings(new_settings)
+            }
+            SettingsAction::SetProxy(proxy) => {
+                let new_settings = self.settings.clone().with_proxy(&proxy);
+                self.proxy = proxy;
+                self.set_settings(new_settings)
             }
         }
     }
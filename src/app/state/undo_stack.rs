@@ -0,0 +1,97 @@
+use super::Dequeued;
+use crate::app::models::SongDescription;
+
+// How many actions can be undone
+const UNDO_LIMIT: usize = 10;
+
+// What it takes to undo a destructive action
+#[derive(Clone, Debug)]
+pub enum Inverse {
+    // Tracks removed from a playlist, and where they were (in order)
+    AddToPlaylist(String, Vec<(usize, SongDescription)>),
+    SaveTracks(Vec<SongDescription>),
+    SaveAlbum(String),
+    Requeue(Dequeued),
+}
+
+// The last few destructive actions, most recent last
+// Each one gets an id, so that a notification can undo the very action it is about
+#[derive(Default)]
+pub struct UndoStack {
+    inverses: Vec<(u32, Inverse)>,
+    next_id: u32,
+}
+
+impl UndoStack {
+    pub fn push(&mut self, inverse: Inverse) -> u32 {
+        if self.inverses.len() >= UNDO_LIMIT {
+            self.inverses.remove(0);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.inverses.push((id, inverse));
+        id
+    }
+
+    pub fn pop(&mut self) -> Option<Inverse> {
+        self.inverses.pop().map(|(_, inverse)| inverse)
+    }
+
+    pub fn take(&mut self, id: u32) -> Option<Inverse> {
+        let position = self.inverses.iter().position(|(i, _)| *i == id)?;
+        Some(self.inverses.remove(position).1)
+    }
+
+    // Drop the actions that can't be undone anymore
+    pub fn forget<F>(&mut self, f: F)
+    where
+        F: Fn(&Inverse) -> bool,
+    {
+        self.inverses.retain(|(_, inverse)| !f(inverse));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit() {
+        let mut stack = UndoStack::default();
+        for i in 0..UNDO_LIMIT + 2 {
+            stack.push(Inverse::SaveAlbum(i.to_string()));
+        }
+
+        let mut popped = vec![];
+        while let Some(Inverse::SaveAlbum(id)) = stack.pop() {
+            popped.push(id);
+        }
+        assert_eq!(popped.len(), UNDO_LIMIT);
+        assert_eq!(popped.first().unwrap(), &(UNDO_LIMIT + 1).to_string());
+        assert_eq!(popped.last().unwrap(), "2");
+    }
+
+    #[test]
+    fn test_take() {
+        let mut stack = UndoStack::default();
+        let first = stack.push(Inverse::SaveAlbum("1".to_string()));
+        stack.push(Inverse::SaveAlbum("2".to_string()));
+
+        assert!(matches!(stack.take(first), Some(Inverse::SaveAlbum(id)) if id == "1"));
+        assert!(stack.take(first).is_none());
+        assert!(matches!(stack.pop(), Some(Inverse::SaveAlbum(id)) if id == "2"));
+        assert!(stack.pop().is_none());
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut stack = UndoStack::default();
+        stack.push(Inverse::SaveAlbum("1".to_string()));
+        stack.push(Inverse::Requeue(Default::default()));
+
+        stack.forget(|inverse| matches!(inverse, Inverse::Requeue(_)));
+
+        assert!(matches!(stack.pop(), Some(Inverse::SaveAlbum(_))));
+        assert!(stack.pop().is_none());
+    }
+}
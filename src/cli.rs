@@ -0,0 +1,292 @@
+use std::ffi::CString;
+use std::rc::Rc;
+
+use futures::channel::mpsc::UnboundedSender;
+use gettextrs::*;
+use gio::prelude::*;
+use gio::ApplicationCommandLine;
+use glib::translate::ToGlibPtr;
+use serde::Serialize;
+
+use crate::app::state::{AppState, PlaybackAction};
+use crate::app::{play, AppAction, AppModel, SpotifyLink};
+
+// Options that control an already running instance, rather than open something
+const CONTROL_OPTIONS: [&str; 8] = [
+    "toggle", "next", "prev", "queue", "play", "volume", "status", "like",
+];
+
+pub fn add_options(app: &gtk::Application) {
+    let flag = |name: &str, description: &str| {
+        app.add_main_option(
+            name,
+            glib::Char::from(0u8),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::None,
+            description,
+            None,
+        );
+    };
+    flag("toggle", &gettext("Play or pause"));
+    flag("next", &gettext("Skip to the next track"));
+    flag("prev", &gettext("Go back to the previous track"));
+    flag("like", &gettext("Save the current track"));
+    flag("status", &gettext("Print what is currently playing"));
+    flag("json", &gettext("Print the status as JSON"));
+
+    app.add_main_option(
+        "queue",
+        glib::Char::from(0u8),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        &gettext("Add a track, album, playlist or artist to the queue"),
+        Some("URI"),
+    );
+    app.add_main_option(
+        "play",
+        glib::Char::from(0u8),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::String,
+        &gettext("Play a track, album, playlist or artist"),
+        Some("URI"),
+    );
+    app.add_main_option(
+        "volume",
+        glib::Char::from(0u8),
+        glib::OptionFlags::NONE,
+        glib::OptionArg::Int,
+        &gettext("Set the volume, from 0 to 100"),
+        Some("N"),
+    );
+}
+
+// ApplicationCommandLine::print isn't bound, and it's the only way to write to the remote's terminal
+fn print(cmdline: &ApplicationCommandLine, text: &str) {
+    let text = CString::new(text).unwrap_or_default();
+    unsafe {
+        gio::ffi::g_application_command_line_print(
+            cmdline.to_glib_none().0,
+            b"%s\n\0".as_ptr() as *const _,
+            text.as_ptr(),
+        );
+    }
+}
+
+fn printerr(cmdline: &ApplicationCommandLine, text: &str) {
+    let text = CString::new(text).unwrap_or_default();
+    unsafe {
+        gio::ffi::g_application_command_line_printerr(
+            cmdline.to_glib_none().0,
+            b"%s\n\0".as_ptr() as *const _,
+            text.as_ptr(),
+        );
+    }
+}
+
+fn fail(cmdline: &ApplicationCommandLine, message: &str) {
+    printerr(cmdline, message);
+    cmdline.set_exit_status(1);
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Status {
+    playing: bool,
+    title: Option<String>,
+    artists: Option<String>,
+    album: Option<String>,
+    uri: Option<String>,
+    // In percents
+    volume: u32,
+}
+
+impl Status {
+    fn of(state: &AppState) -> Self {
+        let song = state.playback.current_song();
+        Self {
+            playing: state.playback.is_playing(),
+            title: song.as_ref().map(|s| s.title.clone()),
+            artists: song.as_ref().map(|s| s.artists_name()),
+            album: song.as_ref().map(|s| s.album.name.clone()),
+            uri: song.as_ref().map(|s| format!("spotify:track:{}", s.id)),
+            volume: (state.playback.volume() * 100.0).round() as u32,
+        }
+    }
+
+    fn to_text(&self) -> String {
+        // this is just to fool xgettext, it doesn't like macros (or rust for that matter) :(
+        if cfg!(debug_assertions) {
+            // translators: This is printed by `spot --status`, it reads "Playing: <track> - <artists>".
+            gettext("Playing: {} - {}");
+            // translators: This is printed by `spot --status`, it reads "Paused: <track> - <artists>".
+            gettext("Paused: {} - {}");
+        }
+        match (&self.title, &self.artists) {
+            (Some(title), Some(artists)) if self.playing => {
+                gettext!("Playing: {} - {}", title, artists)
+            }
+            (Some(title), Some(artists)) => gettext!("Paused: {} - {}", title, artists),
+            // translators: This is printed by `spot --status` when nothing is playing.
+            _ => gettext("Stopped"),
+        }
+    }
+}
+
+fn queue(cmdline: &ApplicationCommandLine, model: &AppModel, sender: UnboundedSender<AppAction>) {
+    let uri = cmdline
+        .options_dict()
+        .lookup::<String>("queue")
+        .ok()
+        .flatten();
+    let link = match uri.as_deref().and_then(SpotifyLink::parse) {
+        Some(SpotifyLink::User(_)) | None => {
+            return fail(
+                cmdline,
+                &gettext("Only tracks, albums, playlists and artists can be queued"),
+            )
+        }
+        Some(link) => link,
+    };
+    let api = model.get_spotify();
+    // Holding on to the command line keeps the remote instance waiting until we're done
+    let cmdline = cmdline.clone();
+    glib::MainContext::default().spawn_local(async move {
+        match play::tracks_of(api, link).await {
+            Ok(songs) => {
+                sender
+                    .unbounded_send(PlaybackAction::Queue(songs).into())
+                    .unwrap();
+            }
+            Err(err) => fail(&cmdline, &err.to_string()),
+        }
+    });
+}
+
+fn play(cmdline: &ApplicationCommandLine, model: &AppModel, sender: UnboundedSender<AppAction>) {
+    let uri = cmdline
+        .options_dict()
+        .lookup::<String>("play")
+        .ok()
+        .flatten();
+    let Some(link) = uri.as_deref().and_then(SpotifyLink::parse) else {
+        return fail(cmdline, &gettext("Unsupported URI"));
+    };
+    let api = model.get_spotify();
+    let cmdline = cmdline.clone();
+    glib::MainContext::default().spawn_local(async move {
+        match play::play_link(api, link).await {
+            Ok(actions) => {
+                for action in actions {
+                    sender.unbounded_send(action).unwrap();
+                }
+            }
+            Err(err) => fail(&cmdline, &err.to_string()),
+        }
+    });
+}
+
+fn like(cmdline: &ApplicationCommandLine, model: &AppModel, sender: UnboundedSender<AppAction>) {
+    if model.get_state().playback.current_song().is_none() {
+        return fail(cmdline, &gettext("Nothing is playing"));
+    }
+    sender.unbounded_send(AppAction::SaveCurrentTrack).unwrap();
+}
+
+// Options are parsed by the local instance, then forwarded to the primary one, that runs this
+pub fn handle(
+    app: &gtk::Application,
+    cmdline: &ApplicationCommandLine,
+    model: &Rc<AppModel>,
+    sender: &UnboundedSender<AppAction>,
+) -> i32 {
+    let options = cmdline.options_dict();
+    if !CONTROL_OPTIONS.iter().any(|o| options.contains(o)) {
+        let files: Vec<gio::File> = cmdline
+            .arguments()
+            .iter()
+            .skip(1)
+            .map(|arg| cmdline.create_file_for_arg(arg))
+            .collect();
+        if files.is_empty() {
+            app.activate();
+        } else {
+            app.open(&files, "");
+        }
+        return 0;
+    }
+
+    if !cmdline.is_remote() {
+        printerr(cmdline, &gettext("Spot is not running"));
+        return 1;
+    }
+
+    if options.contains("toggle") {
+        app.activate_action("toggle_playback", None);
+    }
+    if options.contains("next") {
+        app.activate_action("player_next", None);
+    }
+    if options.contains("prev") {
+        app.activate_action("player_prev", None);
+    }
+    if let Ok(Some(volume)) = options.lookup::<i32>("volume") {
+        let volume = volume.clamp(0, 100) as f64 / 100.0;
+        sender
+            .unbounded_send(PlaybackAction::SetVolume(volume).into())
+            .unwrap();
+    }
+    if options.contains("queue") {
+        queue(cmdline, model, sender.clone());
+    }
+    if options.contains("play") {
+        play(cmdline, model, sender.clone());
+    }
+    if options.contains("like") {
+        like(cmdline, model, sender.clone());
+    }
+    if options.contains("status") {
+        let status = Status::of(&model.get_state());
+        if options.contains("json") {
+            print(cmdline, &serde_json::to_string(&status).unwrap_or_default());
+        } else {
+            print(cmdline, &status.to_text());
+        }
+    }
+    cmdline.exit_status()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(playing: bool, title: Option<&str>) -> Status {
+        Status {
+            playing,
+            title: title.map(|t| t.to_string()),
+            artists: title.map(|_| "Artist".to_string()),
+            album: None,
+            uri: None,
+            volume: 50,
+        }
+    }
+
+    #[test]
+    fn test_status_text() {
+        assert_eq!(
+            status(true, Some("Title")).to_text(),
+            "Playing: Title - Artist"
+        );
+        assert_eq!(
+            status(false, Some("Title")).to_text(),
+            "Paused: Title - Artist"
+        );
+        assert_eq!(status(true, None).to_text(), "Stopped");
+    }
+
+    #[test]
+    fn test_status_json() {
+        assert_eq!(
+            serde_json::to_string(&status(false, None)).unwrap(),
+            r#"{"playing":false,"title":null,"artists":null,"album":null,"uri":null,"volume":50}"#
+        );
+    }
+}
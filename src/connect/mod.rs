@@ -1,9 +1,9 @@
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::Instant;
 
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::StreamExt;
-use tokio::{task, time};
+use tokio::time;
 
 use crate::api::SpotifyApiClient;
 use crate::app::AppAction;
@@ -11,28 +11,34 @@ use crate::app::AppAction;
 mod player;
 pub use player::ConnectCommand;
 
+mod scheduler;
+use scheduler::PollScheduler;
+
 #[tokio::main]
 async fn connect_server(
     api: Arc<dyn SpotifyApiClient + Send + Sync>,
     action_sender: UnboundedSender<AppAction>,
-    receiver: UnboundedReceiver<ConnectCommand>,
+    mut receiver: UnboundedReceiver<ConnectCommand>,
 ) {
-    let player = Arc::new(player::ConnectPlayer::new(api, action_sender));
-
-    let player_clone = Arc::clone(&player);
-    task::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(5));
-        loop {
-            interval.tick().await;
-            if player_clone.has_device() {
-                player_clone.sync_state().await;
+    let player = player::ConnectPlayer::new(api, action_sender);
+    let mut scheduler = PollScheduler::new(Instant::now());
+
+    loop {
+        let next_poll = time::Instant::from_std(scheduler.next_poll());
+        tokio::select! {
+            command = receiver.next() => {
+                let Some(command) = command else {
+                    break;
+                };
+                player.handle_command(command).await.unwrap();
+                scheduler.command_sent(Instant::now());
+            }
+            _ = time::sleep_until(next_poll), if player.has_device() => {
+                let changed = player.sync_state().await;
+                scheduler.polled(Instant::now(), &player.last_state(), changed);
             }
         }
-    });
-
-    receiver
-        .for_each(|command| async { player.handle_command(command).await.unwrap() })
-        .await;
+    }
 }
 
 pub fn start_connect_server(
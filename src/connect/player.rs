@@ -1,18 +1,33 @@
 use std::collections::hash_map::DefaultHasher;
+use std::convert::TryFrom;
 use std::hash::{Hash, Hasher};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use futures::channel::mpsc::UnboundedSender;
 use gettextrs::gettext;
 
-use crate::api::{SpotifyApiClient, SpotifyApiError, SpotifyResult};
-use crate::app::models::{ConnectPlayerState, RepeatMode, SongDescription};
+use crate::api::{Conditional, SpotifyApiClient, SpotifyApiError, SpotifyResult};
+use crate::app::models::{Batch, ConnectPlayerState, RepeatMode, SongDescription};
 use crate::app::state::{Device, PlaybackAction};
-use crate::app::{AppAction, SongsSource};
+use crate::app::{AppAction, BatchLoader, BatchQuery, SongsSource};
+
+const CONTEXT_BATCH_SIZE: usize = 50;
+// How far we'll go looking for the current track in a remote context
+const MAX_CONTEXT_BATCHES: usize = 20;
+// How far the device's progress can drift from ours before we seek to catch up
+const MAX_PROGRESS_DRIFT_MS: u32 = 2000;
+// How long to back off when rate limited and the server doesn't say
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 pub enum ConnectCommand {
     SetDevice(String),
+    // Moves playback from the current Connect device to another one
+    TransferPlayback {
+        device_id: String,
+        keep_playing: bool,
+    },
     PlayerLoadInContext {
         source: SongsSource,
         offset: usize,
@@ -24,6 +39,11 @@ pub enum ConnectCommand {
     },
     PlayerResume,
     PlayerPause,
+    // Skips to the next track in the device's queue, unless that track is already playing
+    PlayerNext {
+        song: String,
+    },
+    AddToQueue(Vec<String>),
     PlayerStop,
     PlayerSeek(usize),
     PlayerRepeat(RepeatMode),
@@ -37,6 +57,34 @@ pub struct ConnectPlayer {
     device_id: RwLock<Option<String>>,
     last_queue: RwLock<u64>,
     last_state: RwLock<ConnectPlayerState>,
+    // When last_state was fetched, to tell how far it has played since
+    last_synced: RwLock<Option<Instant>>,
+    last_etag: RwLock<Option<String>>,
+    retry_after: RwLock<Option<Instant>>,
+}
+
+// The progress the device should have reached after some time, as long as nothing else changed
+fn progress_after(state: &ConnectPlayerState, elapsed: Duration) -> u32 {
+    if !state.is_playing {
+        return state.progress_ms;
+    }
+    let elapsed_ms = u32::try_from(elapsed.as_millis()).unwrap_or(u32::MAX);
+    let progress_ms = state.progress_ms.saturating_add(elapsed_ms);
+    if state.duration_ms > 0 {
+        progress_ms.min(state.duration_ms)
+    } else {
+        progress_ms
+    }
+}
+
+// Whether the device did anything besides playing on as expected
+fn has_changed(expected: &ConnectPlayerState, state: &ConnectPlayerState) -> bool {
+    let drift = expected.progress_ms.abs_diff(state.progress_ms);
+    let same_otherwise = ConnectPlayerState {
+        progress_ms: state.progress_ms,
+        ..expected.clone()
+    } == *state;
+    !same_otherwise || drift > MAX_PROGRESS_DRIFT_MS
 }
 
 impl ConnectPlayer {
@@ -50,6 +98,9 @@ impl ConnectPlayer {
             device_id: Default::default(),
             last_queue: Default::default(),
             last_state: Default::default(),
+            last_synced: Default::default(),
+            last_etag: Default::default(),
+            retry_after: Default::default(),
         }
     }
 
@@ -84,9 +135,76 @@ impl ConnectPlayer {
         })
     }
 
-    async fn apply_remote_state(&self, state: &ConnectPlayerState) {
-        if let Some(songs) = self.get_queue_if_changed().await {
-            self.send_actions([PlaybackAction::LoadSongs(songs).into()]);
+    // Load a remote context batch by batch, until we find the song currently playing
+    async fn load_context(&self, source: SongsSource, current_song_id: Option<&String>) {
+        let loader = BatchLoader::new(Arc::clone(&self.api));
+        let mut query = Some(BatchQuery {
+            source,
+            batch: Batch::first_of_size(CONTEXT_BATCH_SIZE),
+        });
+        for _ in 0..MAX_CONTEXT_BATCHES {
+            let Some(current_query) = query.take() else {
+                break;
+            };
+            let Ok(song_batch) = loader.get_batch(&current_query).await else {
+                break;
+            };
+            let found = current_song_id
+                .map(|id| song_batch.songs.iter().any(|song| &song.id == id))
+                .unwrap_or(true);
+            query = BatchQuery {
+                source: current_query.source.clone(),
+                batch: song_batch.batch,
+            }
+            .next();
+            self.send_actions([
+                PlaybackAction::LoadPagedSongs(current_query.source, song_batch).into(),
+            ]);
+            if found {
+                break;
+            }
+        }
+    }
+
+    // The device plays its queue before the rest of the context, the app needs to know about it
+    async fn sync_next_up(&self, current_song_id: Option<&String>) {
+        let Ok(songs) = self.api.get_player_queue().await else {
+            return;
+        };
+        let queue = songs
+            .into_iter()
+            .filter(|song| Some(&song.id) != current_song_id)
+            .collect();
+        self.send_actions([PlaybackAction::SyncNextUp(queue).into()]);
+    }
+
+    async fn apply_remote_state(
+        &self,
+        last_state: &ConnectPlayerState,
+        state: &ConnectPlayerState,
+    ) {
+        let should_sync_next_up = state.source.is_some()
+            && (last_state.source != state.source
+                || last_state.current_song_id != state.current_song_id);
+
+        match &state.source {
+            // Same context as before, the app will load more of it when needed
+            Some(source) if last_state.source.as_ref() == Some(source) => {}
+            Some(source) => {
+                if let Ok(mut last_queue) = self.last_queue.write() {
+                    *last_queue = 0;
+                }
+                self.load_context(source.clone(), state.current_song_id.as_ref())
+                    .await;
+            }
+            // Without a context we can only mirror the remote queue, refetched when the track changes
+            None if last_state.current_song_id == state.current_song_id
+                && last_state.source.is_none() => {}
+            None => {
+                if let Some(songs) = self.get_queue_if_changed().await {
+                    self.send_actions([PlaybackAction::LoadSongs(songs).into()]);
+                }
+            }
         }
 
         let play_pause = if state.is_playing {
@@ -101,6 +219,11 @@ impl ConnectPlayer {
             PlaybackAction::SetShuffled(state.shuffle).into(),
             PlaybackAction::SyncSeek(state.progress_ms).into(),
         ]);
+
+        // After the current track is known, so that the app can tell the queue and the context apart
+        if should_sync_next_up {
+            self.sync_next_up(state.current_song_id.as_ref()).await;
+        }
     }
 
     pub fn has_device(&self) -> bool {
@@ -110,16 +233,95 @@ impl ConnectPlayer {
             .unwrap_or(false)
     }
 
-    pub async fn sync_state(&self) {
+    // The last known state, with the progress it should have reached by now
+    pub fn last_state(&self) -> ConnectPlayerState {
+        let state = self
+            .last_state
+            .read()
+            .map(|state| state.clone())
+            .unwrap_or_default();
+        let elapsed = self
+            .last_synced
+            .read()
+            .ok()
+            .and_then(|synced| *synced)
+            .map(|synced| synced.elapsed())
+            .unwrap_or_default();
+        ConnectPlayerState {
+            progress_ms: progress_after(&state, elapsed),
+            ..state
+        }
+    }
+
+    fn is_rate_limited(&self) -> bool {
+        self.retry_after
+            .read()
+            .ok()
+            .and_then(|retry_after| *retry_after)
+            .map(|retry_after| Instant::now() < retry_after)
+            .unwrap_or(false)
+    }
+
+    fn set_last_state(&self, state: ConnectPlayerState) {
+        if let Ok(mut last_state) = self.last_state.write() {
+            *last_state = state;
+        }
+        if let Ok(mut last_synced) = self.last_synced.write() {
+            *last_synced = Some(Instant::now());
+        }
+    }
+
+    // Returns whether the state of the device changed since the last sync
+    pub async fn sync_state(&self) -> bool {
+        if self.is_rate_limited() {
+            debug!("rate limited, not polling connect device");
+            return false;
+        }
         debug!("polling connect device...");
-        let player_state = self.api.player_state().await;
-        let Ok(state) = player_state else {
-            self.device_lost();
-            return;
+        let etag = self.last_etag.read().ok().and_then(|etag| etag.clone());
+        let (state, etag) = match self.api.player_state(etag).await {
+            Ok(Conditional::Changed { value, etag }) => (value, etag),
+            // Our last state keeps playing on, see last_state()
+            Ok(Conditional::Unchanged) => return false,
+            Err(SpotifyApiError::TooManyRequests(retry_after)) => {
+                let delay = retry_after
+                    .map(Duration::from_secs)
+                    .unwrap_or(DEFAULT_RETRY_AFTER);
+                if let Ok(mut retry_after) = self.retry_after.write() {
+                    *retry_after = Some(Instant::now() + delay);
+                }
+                return false;
+            }
+            Err(_) => {
+                self.device_lost();
+                return false;
+            }
         };
-        self.apply_remote_state(&state).await;
+        if let Ok(mut last_etag) = self.last_etag.write() {
+            *last_etag = etag;
+        }
+        let last_state = self.last_state();
+        let changed = has_changed(&last_state, &state);
+        if changed {
+            self.apply_remote_state(&last_state, &state).await;
+        }
+        // Even when only the progress moved, so that we keep extrapolating from fresh data
+        self.set_last_state(state);
+        changed
+    }
+
+    fn forget_state(&self) {
+        if let Ok(mut last_etag) = self.last_etag.write() {
+            *last_etag = None;
+        }
         if let Ok(mut last_state) = self.last_state.write() {
-            *last_state = state;
+            *last_state = Default::default();
+        }
+        if let Ok(mut last_synced) = self.last_synced.write() {
+            *last_synced = None;
+        }
+        if let Ok(mut last_queue) = self.last_queue.write() {
+            *last_queue = 0;
         }
     }
 
@@ -211,6 +413,21 @@ impl ConnectPlayer {
             ConnectCommand::PlayerPause if state.is_playing => {
                 self.api.player_pause(device_id).await
             }
+            ConnectCommand::PlayerNext { song }
+                if state.current_song_id.as_ref() != Some(&song) =>
+            {
+                self.api.player_next(device_id).await
+            }
+            ConnectCommand::AddToQueue(songs) => {
+                for song in songs {
+                    let uri = format!("spotify:track:{}", song);
+                    self.api.player_add_to_queue(device_id.clone(), uri).await?;
+                }
+                if state.source.is_some() {
+                    self.sync_next_up(state.current_song_id.as_ref()).await;
+                }
+                Ok(())
+            }
             ConnectCommand::PlayerSeek(offset) => self.api.player_seek(device_id, offset).await,
             ConnectCommand::PlayerRepeat(mode) => self.api.player_repeat(device_id, mode).await,
             ConnectCommand::PlayerShuffle(shuffle) => {
@@ -227,9 +444,22 @@ impl ConnectPlayer {
         let device_lost = match command {
             ConnectCommand::SetDevice(new_device_id) => {
                 self.device_id.write().ok()?.replace(new_device_id);
+                self.forget_state();
                 self.sync_state().await;
                 false
             }
+            ConnectCommand::TransferPlayback {
+                device_id,
+                keep_playing,
+            } => {
+                self.device_id.write().ok()?.replace(device_id.clone());
+                self.forget_state();
+                let result = self.api.player_transfer(device_id, keep_playing).await;
+                if result.is_ok() {
+                    self.sync_state().await;
+                }
+                result.is_err()
+            }
             ConnectCommand::PlayerStop => {
                 let device_id = self.device_id.write().ok()?.take();
                 if let Some(old_id) = device_id {
@@ -255,3 +485,263 @@ impl ConnectPlayer {
         Some(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::api::fake::FakeSpotifyApiClient;
+    use crate::app::models::AlbumRef;
+    use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+    use futures::executor::block_on;
+
+    fn song(id: &str) -> SongDescription {
+        SongDescription {
+            id: id.to_string(),
+            uri: "".to_string(),
+            title: "Title".to_string(),
+            artists: vec![],
+            album: AlbumRef {
+                id: "".to_string(),
+                name: "".to_string(),
+                artists: vec![],
+            },
+            duration: 1000,
+            art: None,
+            track_number: None,
+            disc_number: None,
+            is_playable: true,
+            restriction: None,
+        }
+    }
+
+    fn make_player() -> (
+        Arc<FakeSpotifyApiClient>,
+        ConnectPlayer,
+        UnboundedReceiver<AppAction>,
+    ) {
+        let api = Arc::new(FakeSpotifyApiClient::default());
+        let (sender, receiver) = unbounded();
+        let player = ConnectPlayer::new(api.clone(), sender);
+        (api, player, receiver)
+    }
+
+    fn received(receiver: &mut UnboundedReceiver<AppAction>) -> Vec<AppAction> {
+        let mut actions = vec![];
+        while let Ok(Some(action)) = receiver.try_next() {
+            actions.push(action);
+        }
+        actions
+    }
+
+    fn playing(source: Option<SongsSource>, song_id: &str) -> ConnectPlayerState {
+        ConnectPlayerState {
+            is_playing: true,
+            source,
+            current_song_id: Some(song_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_unchanged_state_costs_nothing() {
+        let (api, player, mut receiver) = make_player();
+        *api.songs.lock().unwrap() = vec![song("1"), song("2")];
+        api.push_player_state(playing(None, "1"), Some("etag"));
+
+        assert!(block_on(player.sync_state()));
+        assert!(!received(&mut receiver).is_empty());
+
+        // The fake answers Unchanged once it runs out of states
+        assert!(!block_on(player.sync_state()));
+        assert!(received(&mut receiver).is_empty());
+
+        // Same state again, without an etag
+        api.push_player_state(playing(None, "1"), None);
+        assert!(!block_on(player.sync_state()));
+        assert!(received(&mut receiver).is_empty());
+
+        let queue_fetches = api
+            .calls()
+            .iter()
+            .filter(|call| *call == "get_player_queue")
+            .count();
+        assert_eq!(queue_fetches, 1);
+    }
+
+    #[test]
+    fn test_progress_alone_is_not_a_change() {
+        let (api, player, mut receiver) = make_player();
+        *api.songs.lock().unwrap() = vec![song("1")];
+        let state = |progress_ms| ConnectPlayerState {
+            progress_ms,
+            duration_ms: 180_000,
+            ..playing(None, "1")
+        };
+        api.push_player_state(state(1000), None);
+        assert!(block_on(player.sync_state()));
+        received(&mut receiver);
+
+        // Played on as expected
+        api.push_player_state(state(1500), None);
+        assert!(!block_on(player.sync_state()));
+        assert!(received(&mut receiver).is_empty());
+
+        // Seeked on the device
+        api.push_player_state(state(90_000), None);
+        assert!(block_on(player.sync_state()));
+        let seeked = received(&mut receiver).into_iter().any(|action| {
+            matches!(
+                action,
+                AppAction::PlaybackAction(PlaybackAction::SyncSeek(90_000))
+            )
+        });
+        assert!(seeked);
+    }
+
+    #[test]
+    fn test_progress_after() {
+        let state = ConnectPlayerState {
+            progress_ms: 1000,
+            duration_ms: 3000,
+            ..playing(None, "1")
+        };
+        assert_eq!(progress_after(&state, Duration::from_millis(500)), 1500);
+        assert_eq!(progress_after(&state, Duration::from_secs(10)), 3000);
+
+        let paused = ConnectPlayerState {
+            is_playing: false,
+            ..state
+        };
+        assert_eq!(progress_after(&paused, Duration::from_secs(10)), 1000);
+    }
+
+    #[test]
+    fn test_back_off_when_rate_limited() {
+        let (api, player, mut receiver) = make_player();
+        api.player_states
+            .lock()
+            .unwrap()
+            .push_back(Err(SpotifyApiError::TooManyRequests(Some(60))));
+        api.push_player_state(playing(None, "1"), None);
+
+        assert!(!block_on(player.sync_state()));
+        // Not asking again before the server told us to
+        assert!(!block_on(player.sync_state()));
+        assert!(received(&mut receiver).is_empty());
+
+        let polls = api
+            .calls()
+            .iter()
+            .filter(|call| *call == "player_state")
+            .count();
+        assert_eq!(polls, 1);
+    }
+
+    #[test]
+    fn test_load_context_until_current_song() {
+        let (api, player, mut receiver) = make_player();
+        *api.songs.lock().unwrap() = (0..120).map(|i| song(&i.to_string())).collect();
+        let source = SongsSource::Playlist("p".to_string());
+        api.push_player_state(playing(Some(source.clone()), "60"), None);
+
+        assert!(block_on(player.sync_state()));
+
+        let batches: Vec<usize> = received(&mut receiver)
+            .into_iter()
+            .filter_map(|action| match action {
+                AppAction::PlaybackAction(PlaybackAction::LoadPagedSongs(s, batch))
+                    if s == source =>
+                {
+                    Some(batch.batch.offset)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(batches, vec![0, CONTEXT_BATCH_SIZE]);
+        // Only to find out what was queued, not to mirror it
+        let queue_fetches = api
+            .calls()
+            .iter()
+            .filter(|call| *call == "get_player_queue")
+            .count();
+        assert_eq!(queue_fetches, 1);
+    }
+
+    #[test]
+    fn test_sync_next_up_with_context() {
+        let (api, player, mut receiver) = make_player();
+        *api.songs.lock().unwrap() = vec![song("1"), song("2")];
+        let source = SongsSource::Playlist("p".to_string());
+        api.push_player_state(playing(Some(source.clone()), "1"), None);
+
+        assert!(block_on(player.sync_state()));
+
+        let actions = received(&mut receiver);
+        let load = actions
+            .iter()
+            .position(|a| matches!(a, AppAction::PlaybackAction(PlaybackAction::Load(_))));
+        let sync_next_up = actions.iter().position(|a| {
+            matches!(
+                a,
+                AppAction::PlaybackAction(PlaybackAction::SyncNextUp(queue)) if queue.len() == 1
+            )
+        });
+        assert!(load.is_some() && sync_next_up.is_some());
+        assert!(load < sync_next_up);
+
+        // Same track, same context: no need to look at the queue again
+        api.push_player_state(
+            ConnectPlayerState {
+                progress_ms: 60_000,
+                ..playing(Some(source), "1")
+            },
+            None,
+        );
+        assert!(block_on(player.sync_state()));
+        let queue_fetches = api
+            .calls()
+            .iter()
+            .filter(|call| *call == "get_player_queue")
+            .count();
+        assert_eq!(queue_fetches, 1);
+    }
+
+    #[test]
+    fn test_transfer_playback() {
+        let (api, player, mut receiver) = make_player();
+        *api.songs.lock().unwrap() = vec![song("1"), song("2")];
+        api.push_player_state(playing(None, "2"), None);
+
+        block_on(player.handle_command(ConnectCommand::TransferPlayback {
+            device_id: "speaker".to_string(),
+            keep_playing: true,
+        }));
+
+        assert!(player.has_device());
+        assert_eq!(api.calls()[..2], ["player_transfer", "player_state"]);
+        // Nothing is restarted, the app just follows what the device plays
+        assert!(!api.calls().contains(&"player_play_no_context".to_string()));
+        assert!(received(&mut receiver).iter().any(|action| matches!(
+            action,
+            AppAction::PlaybackAction(PlaybackAction::Load(id)) if id == "2"
+        )));
+    }
+
+    #[test]
+    fn test_add_to_queue() {
+        let (api, player, _receiver) = make_player();
+        block_on(player.handle_command(ConnectCommand::SetDevice("phone".to_string())));
+        block_on(player.handle_command(ConnectCommand::AddToQueue(vec![
+            "a".to_string(),
+            "b".to_string(),
+        ])));
+
+        let added = api
+            .calls()
+            .iter()
+            .filter(|call| *call == "player_add_to_queue")
+            .count();
+        assert_eq!(added, 2);
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+use crate::app::models::ConnectPlayerState;
+
+// Give the device a moment to apply our commands before polling
+const AFTER_COMMAND_DELAY: Duration = Duration::from_millis(750);
+// While playing, poll at least this often to catch changes made on the device itself...
+const PLAYING_INTERVAL: Duration = Duration::from_secs(5);
+// ...and right after the current track should have ended
+const TRACK_END_MARGIN: Duration = Duration::from_millis(500);
+// While paused, poll less and less often as long as nothing changes
+const PAUSED_INTERVAL: Duration = Duration::from_secs(5);
+const PAUSED_MAX_INTERVAL: Duration = Duration::from_secs(60);
+
+// Decides when to poll the state of a Connect device next
+#[derive(Debug)]
+pub struct PollScheduler {
+    next_poll: Instant,
+    // How many polls in a row returned nothing new
+    unchanged_polls: u32,
+}
+
+impl PollScheduler {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            next_poll: now,
+            unchanged_polls: 0,
+        }
+    }
+
+    pub fn next_poll(&self) -> Instant {
+        self.next_poll
+    }
+
+    pub fn command_sent(&mut self, now: Instant) {
+        self.unchanged_polls = 0;
+        let soon = now + AFTER_COMMAND_DELAY;
+        if self.next_poll <= now || self.next_poll > soon {
+            self.next_poll = soon;
+        }
+    }
+
+    pub fn polled(&mut self, now: Instant, state: &ConnectPlayerState, changed: bool) {
+        self.unchanged_polls = if changed {
+            0
+        } else {
+            self.unchanged_polls.saturating_add(1)
+        };
+        let delay = if state.is_playing {
+            let remaining_ms = state.duration_ms.saturating_sub(state.progress_ms);
+            let until_track_end = Duration::from_millis(remaining_ms.into()) + TRACK_END_MARGIN;
+            until_track_end.min(PLAYING_INTERVAL)
+        } else {
+            let backoff = 1u32 << self.unchanged_polls.min(4);
+            (PAUSED_INTERVAL * backoff).min(PAUSED_MAX_INTERVAL)
+        };
+        self.next_poll = now + delay;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn state(is_playing: bool, progress_ms: u32) -> ConnectPlayerState {
+        ConnectPlayerState {
+            is_playing,
+            progress_ms,
+            duration_ms: 180_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_poll_soon_after_command() {
+        let now = Instant::now();
+        let mut scheduler = PollScheduler::new(now);
+        scheduler.polled(now, &state(false, 0), true);
+        assert_eq!(scheduler.next_poll(), now + PAUSED_INTERVAL);
+
+        scheduler.command_sent(now);
+        assert_eq!(scheduler.next_poll(), now + AFTER_COMMAND_DELAY);
+
+        // Another command shouldn't push the poll back
+        scheduler.command_sent(now + Duration::from_millis(500));
+        assert_eq!(scheduler.next_poll(), now + AFTER_COMMAND_DELAY);
+    }
+
+    #[test]
+    fn test_poll_near_track_end() {
+        let now = Instant::now();
+        let mut scheduler = PollScheduler::new(now);
+
+        scheduler.polled(now, &state(true, 0), true);
+        assert_eq!(scheduler.next_poll(), now + PLAYING_INTERVAL);
+
+        scheduler.polled(now, &state(true, 179_000), true);
+        assert_eq!(
+            scheduler.next_poll(),
+            now + Duration::from_millis(1000) + TRACK_END_MARGIN
+        );
+    }
+
+    #[test]
+    fn test_back_off_when_paused() {
+        let now = Instant::now();
+        let mut scheduler = PollScheduler::new(now);
+        let paused = state(false, 1000);
+
+        scheduler.polled(now, &paused, true);
+        let mut delay = scheduler.next_poll() - now;
+        assert_eq!(delay, PAUSED_INTERVAL);
+
+        for _ in 0..10 {
+            scheduler.polled(now, &paused, false);
+            let next_delay = scheduler.next_poll() - now;
+            assert!(next_delay >= delay);
+            delay = next_delay;
+        }
+        assert_eq!(delay, PAUSED_MAX_INTERVAL);
+
+        scheduler.polled(now, &paused, true);
+        assert_eq!(scheduler.next_poll() - now, PAUSED_INTERVAL);
+    }
+}
@@ -0,0 +1,154 @@
+#![allow(non_snake_case)]
+
+use std::sync::Arc;
+
+use futures::channel::mpsc::UnboundedSender;
+use gettextrs::gettext;
+use zbus::fdo::{Error, Result};
+use zbus::{dbus_interface, ObjectServer};
+
+use super::mpris::SpotMprisPlayer;
+use crate::api::SpotifyApiClient;
+use crate::app::play::play_link;
+use crate::app::state::{Device, PlaybackAction, SleepTimer};
+use crate::app::{AppAction, SpotifyLink};
+
+// The id used for this device when listing or switching devices
+const LOCAL_DEVICE_ID: &str = "local";
+const SEARCH_LIMIT: usize = 10;
+
+// Spot specific controls, not covered by MPRIS
+pub struct SpotControl {
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    sender: UnboundedSender<AppAction>,
+}
+
+impl SpotControl {
+    pub fn new(
+        api: Arc<dyn SpotifyApiClient + Send + Sync>,
+        sender: UnboundedSender<AppAction>,
+    ) -> Self {
+        Self { api, sender }
+    }
+
+    fn send(&self, action: AppAction) -> Result<()> {
+        self.sender
+            .unbounded_send(action)
+            .map_err(|_| Error::Failed("Could not send action".to_string()))
+    }
+}
+
+#[dbus_interface(interface = "dev.alextren.Spot.Control")]
+impl SpotControl {
+    // Pause playback in that many seconds (0 disables the timer)
+    fn set_sleep_timer(&self, seconds: u32) -> Result<()> {
+        let timer = Some(seconds).filter(|&s| s > 0).map(SleepTimer::After);
+        self.send(PlaybackAction::SetSleepTimer(timer).into())
+    }
+
+    // Pause playback at the end of the current "track" or "album"
+    fn set_sleep_timer_at_end_of(&self, what: &str) -> Result<()> {
+        let timer = match what {
+            "track" => SleepTimer::EndOfTrack,
+            "album" => SleepTimer::EndOfAlbum,
+            _ => return Err(Error::InvalidArgs(format!("Unknown value: {}", what))),
+        };
+        self.send(PlaybackAction::SetSleepTimer(Some(timer)).into())
+    }
+
+    // Save the track currently playing
+    async fn like_current(&self, #[zbus(object_server)] server: &ObjectServer) -> Result<()> {
+        let player = server
+            .interface::<_, SpotMprisPlayer>("/org/mpris/MediaPlayer2")
+            .await?;
+        if player.get().await.current_track().is_none() {
+            return Err(Error::Failed("Nothing is playing".to_string()));
+        }
+        self.send(AppAction::SaveCurrentTrack)
+    }
+
+    // Add a track (spotify:track:id or open.spotify.com link) to the queue
+    async fn queue(&self, uri: &str) -> Result<()> {
+        let id = match SpotifyLink::parse(uri) {
+            Some(SpotifyLink::Track(id)) => id,
+            _ => return Err(Error::InvalidArgs(format!("Unsupported URI: {uri}"))),
+        };
+        let song = self
+            .api
+            .get_track(&id)
+            .await
+            .map_err(|_| Error::Failed("Could not load the track".to_string()))?;
+        self.send(PlaybackAction::Queue(vec![song]).into())
+    }
+
+    // Play a track, album, playlist or the top tracks of an artist
+    async fn play_uri(&self, uri: &str) -> Result<()> {
+        let link = SpotifyLink::parse(uri)
+            .ok_or_else(|| Error::InvalidArgs(format!("Unsupported URI: {uri}")))?;
+        let actions = play_link(Arc::clone(&self.api), link)
+            .await
+            .map_err(|e| Error::Failed(e.to_string()))?;
+        for action in actions {
+            self.send(action)?;
+        }
+        Ok(())
+    }
+
+    // Albums and artists matching a query, as (uri, name) pairs
+    async fn search(&self, query: &str) -> Result<Vec<(String, String)>> {
+        let results = self
+            .api
+            .search(query, 0, SEARCH_LIMIT)
+            .await
+            .map_err(|_| Error::Failed("Could not search".to_string()))?;
+        let albums = results.albums.into_iter().map(|album| {
+            (
+                format!("spotify:album:{}", album.id),
+                format!("{} - {}", album.title, album.artists_name()),
+            )
+        });
+        let artists = results
+            .artists
+            .into_iter()
+            .map(|artist| (format!("spotify:artist:{}", artist.id), artist.name));
+        Ok(albums.chain(artists).collect())
+    }
+
+    // Available devices, as (id, name, is active) triples
+    async fn list_devices(&self) -> Result<Vec<(String, String, bool)>> {
+        let devices = self
+            .api
+            .list_available_devices()
+            .await
+            .map_err(|_| Error::Failed("Could not list devices".to_string()))?;
+        let local_is_active = !devices.iter().any(|d| d.is_active);
+        let list = std::iter::once((
+            LOCAL_DEVICE_ID.to_string(),
+            // translators: This is the name of the computer Spot runs on, when listing devices playback can be switched to.
+            gettext("This device"),
+            local_is_active,
+        ))
+        .chain(devices.into_iter().map(|d| (d.id, d.label, d.is_active)))
+        .collect();
+        Ok(list)
+    }
+
+    // Switch playback to another device (as listed by ListDevices)
+    async fn switch_device(&self, id: &str) -> Result<()> {
+        let device = if id == LOCAL_DEVICE_ID {
+            Device::Local
+        } else {
+            let devices = self
+                .api
+                .list_available_devices()
+                .await
+                .map_err(|_| Error::Failed("Could not list devices".to_string()))?;
+            devices
+                .into_iter()
+                .find(|d| d.id == id && !d.is_restricted)
+                .map(Device::Connect)
+                .ok_or_else(|| Error::InvalidArgs(format!("Unknown device: {id}")))?
+        };
+        self.send(PlaybackAction::SwitchDevice(device).into())
+    }
+}
@@ -1,14 +1,20 @@
 use futures::channel::mpsc::UnboundedSender;
+use std::collections::HashSet;
 use std::rc::Rc;
 
+use gettextrs::gettext;
+
 use crate::app::{
     components::EventListener,
-    models::{RepeatMode, SongDescription},
-    state::PlaybackEvent,
-    AppEvent, AppModel,
+    models::{PlaylistSummary, RepeatMode, SongDescription},
+    state::{LoginEvent, PlaybackEvent},
+    AppEvent, AppModel, BrowserEvent, SongsSource,
 };
 
-use super::types::{LoopStatus, PlaybackStatus, TrackMetadata};
+use super::types::{
+    playlist_object_id, track_object_id, track_object_ids, LoopStatus, PlaybackStatus,
+    PlaylistMetadata, TrackMetadata,
+};
 
 #[derive(Debug)]
 pub enum MprisStateUpdate {
@@ -18,7 +24,9 @@ pub enum MprisStateUpdate {
         current: Option<TrackMetadata>,
         has_next: bool,
     },
-    SetPositionMs(u128),
+    // Positions are in microseconds
+    Seek(u128),
+    SyncPosition(u128),
     SetLoopStatus {
         has_prev: bool,
         loop_status: LoopStatus,
@@ -26,38 +34,136 @@ pub enum MprisStateUpdate {
     },
     SetShuffled(bool),
     SetPlaying(PlaybackStatus),
+    // Object paths of the tracks in play order, along with the metadata of those that were not listed before
+    SetTrackList {
+        tracks: Vec<String>,
+        added: Vec<TrackMetadata>,
+        current: Option<String>,
+    },
+    SetPlaylists(Vec<PlaylistMetadata>),
+    SetActivePlaylist(Option<String>),
+    // Details of the current track, fetched after it changed
+    SetTrackDetails(TrackMetadata),
+}
+
+fn track_meta(object_id: String, song: SongDescription) -> TrackMetadata {
+    let SongDescription {
+        id,
+        title,
+        artists,
+        album,
+        duration,
+        art,
+        track_number,
+        disc_number,
+        ..
+    } = song;
+    TrackMetadata {
+        id: object_id,
+        length: 1000 * duration as u64,
+        title,
+        album: album.name,
+        album_artist: album.artists.into_iter().map(|a| a.name).collect(),
+        artist: artists.into_iter().map(|a| a.name).collect(),
+        url: Some(format!("https://open.spotify.com/track/{id}")),
+        track_number,
+        disc_number,
+        art,
+        is_saved: None,
+    }
 }
 
 pub struct AppPlaybackStateListener {
     app_model: Rc<AppModel>,
     sender: UnboundedSender<MprisStateUpdate>,
+    // What was last sent as the track list
+    track_ids: Vec<String>,
 }
 
 impl AppPlaybackStateListener {
     pub fn new(app_model: Rc<AppModel>, sender: UnboundedSender<MprisStateUpdate>) -> Self {
-        Self { app_model, sender }
+        Self {
+            app_model,
+            sender,
+            track_ids: vec![],
+        }
     }
 
     fn make_track_meta(&self) -> Option<TrackMetadata> {
-        let SongDescription {
-            id,
-            title,
-            artists,
-            album,
-            duration,
-            art,
-            ..
-        } = self.app_model.get_state().playback.current_song()?;
-        Some(TrackMetadata {
-            id: format!("/dev/alextren/Spot/Track/{id}"),
-            length: 1000 * duration as u64,
-            title,
-            album: album.name,
-            artist: artists.into_iter().map(|a| a.name).collect(),
-            art,
+        let state = self.app_model.get_state();
+        let song = state.playback.current_song()?;
+        // The same track might be listed more than once: find which one is playing
+        let (songs, current) = state.playback.play_order();
+        let object_id = current
+            .filter(|&i| songs[i].id == song.id)
+            .and_then(|i| {
+                track_object_ids(songs.iter().map(|s| s.id.as_str()))
+                    .get(i)
+                    .cloned()
+            })
+            .unwrap_or_else(|| track_object_id(&song.id, 0));
+        Some(track_meta(object_id, song))
+    }
+
+    fn make_current_track(&self) -> MprisStateUpdate {
+        let current = self.make_track_meta();
+        let (has_prev, has_next) = self.has_prev_next();
+        MprisStateUpdate::SetCurrentTrack {
+            has_prev,
+            has_next,
+            current,
+        }
+    }
+
+    // Only if the list changed, and only with the metadata of tracks that were not in it already
+    fn make_track_list(&mut self) -> Option<MprisStateUpdate> {
+        let (songs, current) = self.app_model.get_state().playback.play_order();
+        let track_ids = track_object_ids(songs.iter().map(|s| s.id.as_str()));
+        if track_ids == self.track_ids {
+            return None;
+        }
+        let known: HashSet<&String> = self.track_ids.iter().collect();
+        let added = track_ids
+            .iter()
+            .zip(songs)
+            .filter(|(id, _)| !known.contains(id))
+            .map(|(id, song)| track_meta(id.clone(), song))
+            .collect();
+        let current = current.map(|i| track_ids[i].clone());
+        self.track_ids = track_ids.clone();
+        Some(MprisStateUpdate::SetTrackList {
+            tracks: track_ids,
+            added,
+            current,
         })
     }
 
+    fn make_playlists(&self) -> MprisStateUpdate {
+        let state = self.app_model.get_state();
+        let saved_tracks = PlaylistMetadata {
+            id: playlist_object_id(&SongsSource::SavedTracks).unwrap_or_default(),
+            name: gettext("Saved tracks"),
+        };
+        let playlists =
+            state
+                .logged_user
+                .playlists
+                .iter()
+                .filter_map(|PlaylistSummary { id, title }| {
+                    Some(PlaylistMetadata {
+                        id: playlist_object_id(&SongsSource::Playlist(id.clone()))?,
+                        name: title.clone(),
+                    })
+                });
+        MprisStateUpdate::SetPlaylists(std::iter::once(saved_tracks).chain(playlists).collect())
+    }
+
+    fn make_active_playlist(&self) -> MprisStateUpdate {
+        let state = self.app_model.get_state();
+        let active = state.playback.current_source().and_then(playlist_object_id);
+        MprisStateUpdate::SetActivePlaylist(active)
+    }
+
     fn has_prev_next(&self) -> (bool, bool) {
         let state = self.app_model.get_state();
         (
@@ -86,15 +192,7 @@ impl AppPlaybackStateListener {
             PlaybackEvent::PlaybackStopped => {
                 Some(MprisStateUpdate::SetPlaying(PlaybackStatus::Stopped))
             }
-            PlaybackEvent::TrackChanged(_) => {
-                let current = self.make_track_meta();
-                let (has_prev, has_next) = self.has_prev_next();
-                Some(MprisStateUpdate::SetCurrentTrack {
-                    has_prev,
-                    has_next,
-                    current,
-                })
-            }
+            PlaybackEvent::TrackChanged(_) => Some(self.make_current_track()),
             PlaybackEvent::RepeatModeChanged(_) => {
                 let loop_status = self.loop_status();
                 let (has_prev, has_next) = self.has_prev_next();
@@ -107,24 +205,59 @@ impl AppPlaybackStateListener {
             PlaybackEvent::ShuffleChanged(shuffled) => {
                 Some(MprisStateUpdate::SetShuffled(*shuffled))
             }
-            PlaybackEvent::TrackSeeked(pos) | PlaybackEvent::SeekSynced(pos) => {
+            PlaybackEvent::TrackSeeked(pos) => {
                 let pos = 1000 * (*pos as u128);
-                Some(MprisStateUpdate::SetPositionMs(pos))
+                Some(MprisStateUpdate::Seek(pos))
+            }
+            PlaybackEvent::SeekSynced(pos) => {
+                let pos = 1000 * (*pos as u128);
+                Some(MprisStateUpdate::SyncPosition(pos))
             }
             PlaybackEvent::VolumeSet(vol) => Some(MprisStateUpdate::SetVolume(*vol)),
             _ => None,
         }
     }
+
+    fn track_list_update_for(&mut self, event: &PlaybackEvent) -> Option<MprisStateUpdate> {
+        match event {
+            // Shuffling reorders the list too, and so does moving on to a track that wasn't picked yet
+            PlaybackEvent::PlaylistChanged
+            | PlaybackEvent::NextUpChanged
+            | PlaybackEvent::SourceChanged
+            | PlaybackEvent::ShuffleChanged(_)
+            | PlaybackEvent::TrackChanged(_) => self.make_track_list(),
+            _ => None,
+        }
+    }
+
+    fn active_playlist_update_for(&self, event: &PlaybackEvent) -> Option<MprisStateUpdate> {
+        matches!(event, PlaybackEvent::SourceChanged).then(|| self.make_active_playlist())
+    }
+
+    fn updates_for(&mut self, event: &AppEvent) -> Vec<MprisStateUpdate> {
+        match event {
+            AppEvent::PlaybackEvent(event) => self
+                .update_for(event)
+                .into_iter()
+                .chain(self.track_list_update_for(event))
+                .chain(self.active_playlist_update_for(event))
+                .collect(),
+            AppEvent::LoginEvent(LoginEvent::UserPlaylistsLoaded) => vec![self.make_playlists()],
+            // The current track might have been (un)saved
+            AppEvent::BrowserEvent(BrowserEvent::SavedTracksUpdated) => {
+                vec![self.make_current_track()]
+            }
+            _ => vec![],
+        }
+    }
 }
 
 impl EventListener for AppPlaybackStateListener {
     fn on_event(&mut self, event: &AppEvent) {
-        if let AppEvent::PlaybackEvent(event) = event {
-            if let Some(update) = self.update_for(event) {
-                self.sender
-                    .unbounded_send(update)
-                    .expect("Could not send event to DBUS server");
-            }
+        for update in self.updates_for(event) {
+            self.sender
+                .unbounded_send(update)
+                .expect("Could not send event to DBUS server");
         }
     }
 }
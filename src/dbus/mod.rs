@@ -1,25 +1,175 @@
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::thread;
+use url::Url;
 use zbus::Connection;
 
-use crate::app::{AppAction, AppModel};
+use crate::api::SpotifyApiClient;
+use crate::app::loader::ImageLoader;
+use crate::app::{AppAction, AppModel, BatchLoader};
 
 mod mpris;
 pub use mpris::*;
 
+mod tracklist;
+use tracklist::SpotMprisTrackList;
+
+mod playlists;
+use playlists::SpotMprisPlaylists;
+
+mod control;
+use control::SpotControl;
+
+mod search_provider;
+use search_provider::SpotSearchProvider;
+
 mod types;
+use types::{song_id_of, TrackMetadata};
 
 mod listener;
 use listener::*;
 
+// Whether the track is saved, and a local copy of its art (remote art can't be loaded everywhere)
+async fn with_details(
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    mut track: TrackMetadata,
+) -> TrackMetadata {
+    if let Some(id) = song_id_of(&track.id) {
+        track.is_saved = api.is_track_saved(id).await.ok();
+    }
+    if let Some(art) = track.art.as_ref() {
+        let local_art = ImageLoader::new()
+            .cache_remote(art, "jpg")
+            .await
+            .and_then(|path| Url::from_file_path(path).ok());
+        if let Some(local_art) = local_art {
+            track.art = Some(local_art.to_string());
+        }
+    }
+    track
+}
+
+async fn update_player(connection: &Connection, update: MprisStateUpdate) -> zbus::Result<()> {
+    let player_ref = match connection
+        .object_server()
+        .interface::<_, SpotMprisPlayer>("/org/mpris/MediaPlayer2")
+        .await
+    {
+        Ok(player_ref) => player_ref,
+        Err(_) => return Ok(()),
+    };
+    let mut player = player_ref.get_mut().await;
+    let ctxt = player_ref.signal_context();
+    match update {
+        MprisStateUpdate::SetVolume(volume) => {
+            player.state_mut().set_volume(volume);
+            player.volume_changed(ctxt).await
+        }
+        MprisStateUpdate::SetCurrentTrack {
+            has_prev,
+            has_next,
+            current,
+        } => {
+            player.state_mut().set_has_prev(has_prev);
+            player.state_mut().set_has_next(has_next);
+            player.state_mut().set_current_track(current);
+            player.notify_current_track_changed(ctxt).await
+        }
+        // Unless the track changed again in the meantime
+        MprisStateUpdate::SetTrackDetails(track) => {
+            if player.state_mut().update_current_track(track) {
+                player.notify_current_track_changed(ctxt).await
+            } else {
+                Ok(())
+            }
+        }
+        MprisStateUpdate::Seek(position) => {
+            player.state_mut().set_position(position);
+            SpotMprisPlayer::seeked(ctxt, position as i64).await
+        }
+        // Only tell about syncs that actually move the position
+        MprisStateUpdate::SyncPosition(position) => {
+            if player.state_mut().sync_position(position) {
+                SpotMprisPlayer::seeked(ctxt, position as i64).await
+            } else {
+                Ok(())
+            }
+        }
+        MprisStateUpdate::SetLoopStatus {
+            has_prev,
+            has_next,
+            loop_status,
+        } => {
+            player.state_mut().set_has_prev(has_prev);
+            player.state_mut().set_has_next(has_next);
+            player.state_mut().set_loop_status(loop_status);
+            player.notify_loop_status(ctxt).await
+        }
+        MprisStateUpdate::SetShuffled(shuffled) => {
+            player.state_mut().set_shuffled(shuffled);
+            player.shuffle_changed(ctxt).await
+        }
+        MprisStateUpdate::SetPlaying(status) => {
+            player.state_mut().set_playing(status);
+            player.playback_status_changed(ctxt).await
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn update_track_list(connection: &Connection, update: MprisStateUpdate) -> zbus::Result<()> {
+    let track_list_ref = match connection
+        .object_server()
+        .interface::<_, SpotMprisTrackList>("/org/mpris/MediaPlayer2")
+        .await
+    {
+        Ok(track_list_ref) => track_list_ref,
+        Err(_) => return Ok(()),
+    };
+    let mut track_list = track_list_ref.get_mut().await;
+    let ctxt = track_list_ref.signal_context();
+    match update {
+        MprisStateUpdate::SetTrackList {
+            tracks,
+            added,
+            current,
+        } => track_list.set_tracks(ctxt, tracks, added, current).await,
+        _ => Ok(()),
+    }
+}
+
+async fn update_playlists(connection: &Connection, update: MprisStateUpdate) -> zbus::Result<()> {
+    let playlists_ref = match connection
+        .object_server()
+        .interface::<_, SpotMprisPlaylists>("/org/mpris/MediaPlayer2")
+        .await
+    {
+        Ok(playlists_ref) => playlists_ref,
+        Err(_) => return Ok(()),
+    };
+    let mut playlists = playlists_ref.get_mut().await;
+    let ctxt = playlists_ref.signal_context();
+    match update {
+        MprisStateUpdate::SetPlaylists(list) => playlists.set_playlists(ctxt, list).await,
+        MprisStateUpdate::SetActivePlaylist(active) => playlists.set_active(ctxt, active).await,
+        _ => Ok(()),
+    }
+}
+
 #[tokio::main]
 async fn dbus_server(
     mpris: SpotMpris,
     player: SpotMprisPlayer,
+    track_list: SpotMprisTrackList,
+    playlists: SpotMprisPlaylists,
+    control: SpotControl,
+    search_provider: SpotSearchProvider,
     receiver: UnboundedReceiver<MprisStateUpdate>,
 ) -> zbus::Result<()> {
+    let api = player.api();
     let connection = Connection::session().await?;
     connection
         .object_server()
@@ -29,61 +179,61 @@ async fn dbus_server(
         .object_server()
         .at("/org/mpris/MediaPlayer2", player)
         .await?;
+    connection
+        .object_server()
+        .at("/org/mpris/MediaPlayer2", track_list)
+        .await?;
+    connection
+        .object_server()
+        .at("/org/mpris/MediaPlayer2", playlists)
+        .await?;
+    connection
+        .object_server()
+        .at("/dev/alextren/Spot", control)
+        .await?;
+    connection
+        .object_server()
+        .at("/dev/alextren/Spot/SearchProvider", search_provider)
+        .await?;
     connection
         .request_name("org.mpris.MediaPlayer2.Spot")
         .await?;
+    // See data/dev.alextren.Spot.search-provider.ini
+    // Not worth giving up on MPRIS if that name is unavailable (taken by another instance, say)
+    if let Err(e) = connection
+        .request_name("dev.alextren.Spot.SearchProvider")
+        .await
+    {
+        warn!("Could not register the search provider: {}", e);
+    }
 
-    receiver
-        .for_each(|update| async {
-            if let Ok(player_ref) = connection
-                .object_server()
-                .interface::<_, SpotMprisPlayer>("/org/mpris/MediaPlayer2")
-                .await
-            {
-                let mut player = player_ref.get_mut().await;
-                let ctxt = player_ref.signal_context();
-                let res: zbus::Result<()> = match update {
-                    MprisStateUpdate::SetVolume(volume) => {
-                        player.state_mut().set_volume(volume);
-                        player.volume_changed(ctxt).await
-                    }
-                    MprisStateUpdate::SetCurrentTrack {
-                        has_prev,
-                        has_next,
-                        current,
-                    } => {
-                        player.state_mut().set_has_prev(has_prev);
-                        player.state_mut().set_has_next(has_next);
-                        player.state_mut().set_current_track(current);
-                        player.notify_current_track_changed(ctxt).await
-                    }
-                    MprisStateUpdate::SetPositionMs(position) => {
-                        player.state_mut().set_position(position);
-                        Ok(())
-                    }
-                    MprisStateUpdate::SetLoopStatus {
-                        has_prev,
-                        has_next,
-                        loop_status,
-                    } => {
-                        player.state_mut().set_has_prev(has_prev);
-                        player.state_mut().set_has_next(has_next);
-                        player.state_mut().set_loop_status(loop_status);
-                        player.notify_loop_status(ctxt).await
-                    }
-                    MprisStateUpdate::SetShuffled(shuffled) => {
-                        player.state_mut().set_shuffled(shuffled);
-                        player.shuffle_changed(ctxt).await
-                    }
-                    MprisStateUpdate::SetPlaying(status) => {
-                        player.state_mut().set_playing(status);
-                        player.playback_status_changed(ctxt).await
-                    }
-                };
-                res.expect("Signal emission failed");
+    // Some details take a while to fetch: they're sent in a later update, so that others don't wait
+    let mut updates = receiver.fuse();
+    let mut details = FuturesUnordered::new();
+    loop {
+        let update = futures::select! {
+            update = updates.next() => match update {
+                Some(update) => update,
+                None => break,
+            },
+            track = details.select_next_some() => MprisStateUpdate::SetTrackDetails(track),
+        };
+        if let MprisStateUpdate::SetCurrentTrack {
+            current: Some(track),
+            ..
+        } = &update
+        {
+            details.push(with_details(Arc::clone(&api), track.clone()));
+        }
+        let res = match update {
+            MprisStateUpdate::SetTrackList { .. } => update_track_list(&connection, update).await,
+            MprisStateUpdate::SetPlaylists(_) | MprisStateUpdate::SetActivePlaylist(_) => {
+                update_playlists(&connection, update).await
             }
-        })
-        .await;
+            _ => update_player(&connection, update).await,
+        };
+        res.expect("Signal emission failed");
+    }
 
     Ok(())
 }
@@ -93,11 +243,86 @@ pub fn start_dbus_server(
     sender: UnboundedSender<AppAction>,
 ) -> AppPlaybackStateListener {
     let mpris = SpotMpris::new(sender.clone());
-    let player = SpotMprisPlayer::new(sender);
+    let player = SpotMprisPlayer::new(app_model.get_spotify(), sender.clone());
+    let track_list = SpotMprisTrackList::new(app_model.get_spotify(), sender.clone());
+    let playlists =
+        SpotMprisPlaylists::new(BatchLoader::new(app_model.get_spotify()), sender.clone());
+    let control = SpotControl::new(app_model.get_spotify(), sender.clone());
+    let search_provider = SpotSearchProvider::new(app_model.get_spotify(), sender);
 
     let (sender, receiver) = unbounded();
 
-    thread::spawn(move || dbus_server(mpris, player, receiver));
+    thread::spawn(move || {
+        dbus_server(
+            mpris,
+            player,
+            track_list,
+            playlists,
+            control,
+            search_provider,
+            receiver,
+        )
+    });
 
     AppPlaybackStateListener::new(app_model, sender)
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::channel::mpsc::unbounded;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+    use zbus::{ConnectionBuilder, Guid, MessageStream};
+
+    use super::*;
+    use crate::api::fake::FakeSpotifyApiClient;
+
+    // A private, peer-to-peer bus between the player and a single client
+    async fn connect(player: SpotMprisPlayer) -> zbus::Result<(Connection, Connection)> {
+        let (server, client) = UnixStream::pair()?;
+        let guid = Guid::generate();
+        let server = ConnectionBuilder::unix_stream(server)
+            .server(&guid)
+            .p2p()
+            .serve_at("/org/mpris/MediaPlayer2", player)?
+            .build();
+        let client = ConnectionBuilder::unix_stream(client).p2p().build();
+        futures::try_join!(server, client)
+    }
+
+    async fn next_seeked(messages: &mut MessageStream) -> i64 {
+        while let Some(message) = messages.next().await {
+            let message = message.unwrap();
+            if message.member().map_or(false, |m| m.as_str() == "Seeked") {
+                return message.body::<i64>().unwrap();
+            }
+        }
+        panic!("No Seeked signal");
+    }
+
+    #[test]
+    fn test_seeked_signal() {
+        block_on(async {
+            let api = Arc::new(FakeSpotifyApiClient::default());
+            let (sender, _receiver) = unbounded();
+            let (server, client) = connect(SpotMprisPlayer::new(api, sender)).await.unwrap();
+            let mut messages = MessageStream::from(&client);
+
+            update_player(&server, MprisStateUpdate::Seek(10_000_000))
+                .await
+                .unwrap();
+            assert_eq!(next_seeked(&mut messages).await, 10_000_000);
+
+            // Nothing is playing, so the position stays put: the first sync is close enough
+            update_player(&server, MprisStateUpdate::SyncPosition(10_200_000))
+                .await
+                .unwrap();
+            update_player(&server, MprisStateUpdate::SyncPosition(60_000_000))
+                .await
+                .unwrap();
+            assert_eq!(next_seeked(&mut messages).await, 60_000_000);
+        });
+    }
+}
@@ -3,6 +3,7 @@
 
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::sync::Arc;
 
 use futures::channel::mpsc::UnboundedSender;
 use zbus::fdo::{Error, Result};
@@ -10,9 +11,11 @@ use zbus::{dbus_interface, Interface, SignalContext};
 use zvariant::{ObjectPath, Value};
 
 use super::types::*;
+use crate::api::SpotifyApiClient;
 use crate::app::models::RepeatMode;
+use crate::app::play::play_link;
 use crate::app::state::PlaybackAction;
-use crate::app::AppAction;
+use crate::app::{AppAction, SpotifyLink};
 
 #[derive(Clone)]
 pub struct SpotMpris {
@@ -49,7 +52,7 @@ impl SpotMpris {
 
     #[dbus_interface(property)]
     fn has_track_list(&self) -> bool {
-        false
+        true
     }
 
     #[dbus_interface(property)]
@@ -64,7 +67,8 @@ impl SpotMpris {
 
     #[dbus_interface(property)]
     fn supported_uri_schemes(&self) -> Vec<String> {
-        vec![]
+        // https would suggest any web page can be opened, when only open.spotify.com links can
+        vec!["spotify".to_string()]
     }
 
     #[dbus_interface(property)]
@@ -75,13 +79,18 @@ impl SpotMpris {
 
 pub struct SpotMprisPlayer {
     state: MprisState,
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
     sender: UnboundedSender<AppAction>,
 }
 
 impl SpotMprisPlayer {
-    pub fn new(sender: UnboundedSender<AppAction>) -> Self {
+    pub fn new(
+        api: Arc<dyn SpotifyApiClient + Send + Sync>,
+        sender: UnboundedSender<AppAction>,
+    ) -> Self {
         Self {
             state: MprisState::new(),
+            api,
             sender,
         }
     }
@@ -90,6 +99,14 @@ impl SpotMprisPlayer {
         &mut self.state
     }
 
+    pub fn current_track(&self) -> Option<&TrackMetadata> {
+        self.state.current_track()
+    }
+
+    pub fn api(&self) -> Arc<dyn SpotifyApiClient + Send + Sync> {
+        Arc::clone(&self.api)
+    }
+
     pub async fn notify_current_track_changed(&self, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
         let metadata = Value::from(self.metadata());
         let can_go_next = Value::from(self.can_go_next());
@@ -135,8 +152,18 @@ impl SpotMprisPlayer {
             .map_err(|_| Error::Failed("Could not send action".to_string()))
     }
 
-    pub fn open_uri(&self, Uri: &str) -> Result<()> {
-        Err(Error::NotSupported("Not implemented".to_string()))
+    pub async fn open_uri(&self, Uri: &str) -> Result<()> {
+        let link = SpotifyLink::parse(Uri)
+            .ok_or_else(|| Error::InvalidArgs(format!("Unsupported URI: {Uri}")))?;
+        let actions = play_link(Arc::clone(&self.api), link)
+            .await
+            .map_err(|e| Error::Failed(e.to_string()))?;
+        for action in actions {
+            self.sender
+                .unbounded_send(action)
+                .map_err(|_| Error::Failed("Could not send action".to_string()))?;
+        }
+        Ok(())
     }
 
     pub fn pause(&self) -> Result<()> {
@@ -267,12 +294,8 @@ impl SpotMprisPlayer {
             .current_track()
             .cloned()
             .unwrap_or_else(|| TrackMetadata {
-                id: String::new(),
-                length: 0,
                 title: "Not playing".to_string(),
-                artist: vec![],
-                album: String::new(),
-                art: None,
+                ..Default::default()
             })
     }
 
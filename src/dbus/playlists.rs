@@ -0,0 +1,207 @@
+#![allow(non_snake_case)]
+
+use std::collections::HashMap;
+
+use futures::channel::mpsc::UnboundedSender;
+use zbus::fdo::{Error, Result};
+use zbus::{dbus_interface, Interface, SignalContext};
+use zvariant::{ObjectPath, Value};
+
+use super::types::*;
+use crate::app::play::play_source;
+use crate::app::{AppAction, BatchLoader};
+
+const USER_DEFINED: &str = "UserDefined";
+const ALPHABETICAL: &str = "Alphabetical";
+
+fn ordered(
+    mut playlists: Vec<PlaylistMetadata>,
+    order: &str,
+    reverse: bool,
+) -> Vec<PlaylistMetadata> {
+    if order == ALPHABETICAL {
+        playlists.sort_by_key(|p| p.name.to_lowercase());
+    }
+    if reverse {
+        playlists.reverse();
+    }
+    playlists
+}
+
+// The user's playlists, plus their saved tracks
+pub struct SpotMprisPlaylists {
+    playlists: Vec<PlaylistMetadata>,
+    // The id of the source currently playing, if it can be exposed as a playlist
+    active: Option<String>,
+    loader: BatchLoader,
+    sender: UnboundedSender<AppAction>,
+}
+
+impl SpotMprisPlaylists {
+    pub fn new(loader: BatchLoader, sender: UnboundedSender<AppAction>) -> Self {
+        Self {
+            playlists: vec![],
+            active: None,
+            loader,
+            sender,
+        }
+    }
+
+    fn send(&self, action: AppAction) -> Result<()> {
+        self.sender
+            .unbounded_send(action)
+            .map_err(|_| Error::Failed("Could not send action".to_string()))
+    }
+
+    pub async fn set_playlists(
+        &mut self,
+        ctxt: &SignalContext<'_>,
+        playlists: Vec<PlaylistMetadata>,
+    ) -> zbus::Result<()> {
+        let renamed: Vec<PlaylistMetadata> = playlists
+            .iter()
+            .filter(|p| {
+                self.playlists
+                    .iter()
+                    .any(|old| old.id == p.id && old.name != p.name)
+            })
+            .cloned()
+            .collect();
+        self.playlists = playlists;
+
+        for playlist in renamed {
+            Self::playlist_changed(ctxt, playlist).await?;
+        }
+
+        // The active playlist might only be known now
+        let playlist_count = Value::from(self.playlist_count());
+        let active_playlist = Value::from(self.active_playlist());
+        zbus::fdo::Properties::properties_changed(
+            ctxt,
+            Self::name(),
+            &HashMap::from([
+                ("PlaylistCount", &playlist_count),
+                ("ActivePlaylist", &active_playlist),
+            ]),
+            &[],
+        )
+        .await
+    }
+
+    pub async fn set_active(
+        &mut self,
+        ctxt: &SignalContext<'_>,
+        active: Option<String>,
+    ) -> zbus::Result<()> {
+        if self.active == active {
+            return Ok(());
+        }
+        self.active = active;
+        self.active_playlist_changed(ctxt).await
+    }
+}
+
+#[dbus_interface(interface = "org.mpris.MediaPlayer2.Playlists")]
+impl SpotMprisPlaylists {
+    async fn activate_playlist(&self, PlaylistId: ObjectPath<'_>) -> Result<()> {
+        let source = source_of(PlaylistId.as_str())
+            .ok_or_else(|| Error::InvalidArgs(format!("Unknown playlist: {PlaylistId}")))?;
+        let actions = play_source(&self.loader, source)
+            .await
+            .map_err(|e| Error::Failed(e.to_string()))?;
+        for action in actions {
+            self.send(action)?;
+        }
+        Ok(())
+    }
+
+    fn get_playlists(
+        &self,
+        Index: u32,
+        MaxCount: u32,
+        Order: &str,
+        ReverseOrder: bool,
+    ) -> Vec<PlaylistMetadata> {
+        ordered(self.playlists.clone(), Order, ReverseOrder)
+            .into_iter()
+            .skip(Index as usize)
+            .take(MaxCount as usize)
+            .collect()
+    }
+
+    #[dbus_interface(signal)]
+    async fn playlist_changed(
+        ctxt: &SignalContext<'_>,
+        Playlist: PlaylistMetadata,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playlist_count(&self) -> u32 {
+        self.playlists.len() as u32
+    }
+
+    #[dbus_interface(property)]
+    fn orderings(&self) -> Vec<String> {
+        vec![USER_DEFINED.to_string(), ALPHABETICAL.to_string()]
+    }
+
+    #[dbus_interface(property)]
+    fn active_playlist(&self) -> MaybePlaylist {
+        let active = self
+            .active
+            .as_ref()
+            .and_then(|id| self.playlists.iter().find(|p| &p.id == id));
+        MaybePlaylist(active.cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::SongsSource;
+
+    fn playlist(name: &str) -> PlaylistMetadata {
+        PlaylistMetadata {
+            id: playlist_object_id(&SongsSource::Playlist(name.to_string())).unwrap(),
+            name: name.to_string(),
+        }
+    }
+
+    fn names(playlists: Vec<PlaylistMetadata>) -> Vec<String> {
+        playlists.into_iter().map(|p| p.name).collect()
+    }
+
+    #[test]
+    fn test_ordered() {
+        let playlists = vec![playlist("b"), playlist("C"), playlist("a")];
+        assert_eq!(
+            names(ordered(playlists.clone(), USER_DEFINED, false)),
+            vec!["b", "C", "a"]
+        );
+        assert_eq!(
+            names(ordered(playlists.clone(), ALPHABETICAL, false)),
+            vec!["a", "b", "C"]
+        );
+        assert_eq!(
+            names(ordered(playlists, ALPHABETICAL, true)),
+            vec!["C", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn test_source_of_playlist_id() {
+        let sources = [
+            SongsSource::Playlist("37i9dQZF1DXcBWIGoYBM5M".to_string()),
+            SongsSource::SavedTracks,
+        ];
+        for source in sources {
+            let id = playlist_object_id(&source).unwrap();
+            assert_eq!(source_of(&id), Some(source));
+        }
+        assert_eq!(
+            playlist_object_id(&SongsSource::Album("a".to_string())),
+            None
+        );
+        assert_eq!(source_of("/dev/alextren/Spot/Playlist/"), None);
+    }
+}
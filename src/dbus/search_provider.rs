@@ -0,0 +1,180 @@
+#![allow(non_snake_case)]
+#![allow(unused_variables)]
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::channel::mpsc::UnboundedSender;
+use futures::future::join_all;
+use zbus::dbus_interface;
+use zbus::fdo::{Error, Result};
+use zvariant::{OwnedValue, Value};
+
+use crate::api::SpotifyApiClient;
+use crate::app::loader::ImageLoader;
+use crate::app::models::SearchResults;
+use crate::app::play::play_link;
+use crate::app::state::BrowserAction;
+use crate::app::{AppAction, SpotifyLink};
+
+// The shell queries on every keystroke
+const DEBOUNCE_MS: u64 = 300;
+const SEARCH_LIMIT: usize = 5;
+
+#[derive(Clone, Debug)]
+struct ResultMeta {
+    name: String,
+    description: Option<String>,
+    art: Option<String>,
+}
+
+// Results are identified by their Spotify URI
+fn metas_for(results: SearchResults) -> Vec<(String, ResultMeta)> {
+    let albums = results.albums.into_iter().map(|album| {
+        let meta = ResultMeta {
+            description: Some(album.artists_name()),
+            name: album.title,
+            art: album.art,
+        };
+        (format!("spotify:album:{}", album.id), meta)
+    });
+    let artists = results.artists.into_iter().map(|artist| {
+        let meta = ResultMeta {
+            name: artist.name,
+            description: None,
+            art: artist.photo,
+        };
+        (format!("spotify:artist:{}", artist.id), meta)
+    });
+    albums.chain(artists).collect()
+}
+
+// Lets GNOME Shell search for albums and artists from the overview
+pub struct SpotSearchProvider {
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    sender: UnboundedSender<AppAction>,
+    // Results (and their metas) of previous queries, since the current search started
+    results: Mutex<HashMap<String, Vec<String>>>,
+    metas: Mutex<HashMap<String, ResultMeta>>,
+    // Incremented on every query, to know when one was superseded by another
+    generation: AtomicUsize,
+}
+
+impl SpotSearchProvider {
+    pub fn new(
+        api: Arc<dyn SpotifyApiClient + Send + Sync>,
+        sender: UnboundedSender<AppAction>,
+    ) -> Self {
+        Self {
+            api,
+            sender,
+            results: Default::default(),
+            metas: Default::default(),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    fn send(&self, action: AppAction) -> Result<()> {
+        self.sender
+            .unbounded_send(action)
+            .map_err(|_| Error::Failed("Could not send action".to_string()))
+    }
+
+    async fn search(&self, terms: Vec<String>) -> Vec<String> {
+        let query = terms.join(" ");
+        let cached = self.results.lock().unwrap().get(&query).cloned();
+        if let Some(ids) = cached {
+            return ids;
+        }
+
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        async_std::task::sleep(Duration::from_millis(DEBOUNCE_MS)).await;
+        // The user kept typing, a newer query will be answered instead
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return vec![];
+        }
+
+        let Ok(results) = self.api.search(&query, 0, SEARCH_LIMIT).await else {
+            return vec![];
+        };
+        let metas = metas_for(results);
+        let ids: Vec<String> = metas.iter().map(|(id, _)| id.clone()).collect();
+        self.metas.lock().unwrap().extend(metas);
+        self.results.lock().unwrap().insert(query, ids.clone());
+        ids
+    }
+
+    async fn meta_for(&self, id: String) -> Option<HashMap<String, OwnedValue>> {
+        let meta = self.metas.lock().unwrap().get(&id).cloned()?;
+        let mut result = HashMap::new();
+        if let Some(art) = meta.art {
+            if let Some(path) = ImageLoader::new().cache_remote(&art, "jpg").await {
+                let path = path.to_string_lossy().to_string();
+                result.insert("gicon".to_string(), Value::from(path).into());
+            }
+        }
+        if let Some(description) = meta.description {
+            result.insert("description".to_string(), Value::from(description).into());
+        }
+        result.insert("name".to_string(), Value::from(meta.name).into());
+        result.insert("id".to_string(), Value::from(id).into());
+        Some(result)
+    }
+}
+
+#[dbus_interface(interface = "org.gnome.Shell.SearchProvider2")]
+impl SpotSearchProvider {
+    // A new search: whatever was cached for the previous one can go
+    async fn get_initial_result_set(&self, terms: Vec<String>) -> Vec<String> {
+        self.results.lock().unwrap().clear();
+        self.metas.lock().unwrap().clear();
+        self.search(terms).await
+    }
+
+    async fn get_subsearch_result_set(
+        &self,
+        previous_results: Vec<String>,
+        terms: Vec<String>,
+    ) -> Vec<String> {
+        self.search(terms).await
+    }
+
+    async fn get_result_metas(&self, identifiers: Vec<String>) -> Vec<HashMap<String, OwnedValue>> {
+        join_all(identifiers.into_iter().map(|id| self.meta_for(id)))
+            .await
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    async fn activate_result(
+        &self,
+        identifier: &str,
+        terms: Vec<String>,
+        timestamp: u32,
+    ) -> Result<()> {
+        let link = SpotifyLink::parse(identifier)
+            .ok_or_else(|| Error::InvalidArgs(format!("Unknown result: {identifier}")))?;
+        let actions = match link {
+            SpotifyLink::Album(id) => vec![AppAction::ViewAlbum(id)],
+            SpotifyLink::Artist(id) => vec![AppAction::ViewArtist(id)],
+            SpotifyLink::Playlist(id) => vec![AppAction::ViewPlaylist(id)],
+            SpotifyLink::User(id) => vec![AppAction::ViewUser(id)],
+            link @ SpotifyLink::Track(_) => play_link(Arc::clone(&self.api), link)
+                .await
+                .map_err(|e| Error::Failed(e.to_string()))?,
+        };
+        self.send(AppAction::Raise)?;
+        for action in actions {
+            self.send(action)?;
+        }
+        Ok(())
+    }
+
+    fn launch_search(&self, terms: Vec<String>, timestamp: u32) -> Result<()> {
+        self.send(AppAction::Raise)?;
+        self.send(BrowserAction::Search(terms.join(" ")).into())
+    }
+}
@@ -0,0 +1,249 @@
+#![allow(non_snake_case)]
+#![allow(unused_variables)]
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use futures::channel::mpsc::UnboundedSender;
+use zbus::fdo::{Error, Result};
+use zbus::{dbus_interface, Interface, SignalContext};
+use zvariant::{ObjectPath, OwnedObjectPath};
+
+use super::types::*;
+use crate::api::SpotifyApiClient;
+use crate::app::state::PlaybackAction;
+use crate::app::{AppAction, SpotifyLink};
+
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+fn object_path(id: &str) -> ObjectPath<'_> {
+    ObjectPath::try_from(id).unwrap_or_else(|_| ObjectPath::from_static_str_unchecked(NO_TRACK))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TrackListChange {
+    Added(usize),
+    Removed(String),
+}
+
+// How to go from one list of track ids to the other, if it is only a matter of appending or removing tracks
+fn diff(old: &[String], new: &[String]) -> Option<Vec<TrackListChange>> {
+    if new.len() >= old.len() {
+        new.starts_with(old)
+            .then(|| (old.len()..new.len()).map(TrackListChange::Added).collect())
+    } else {
+        let mut new_ids = new.iter().peekable();
+        let removed = old
+            .iter()
+            .filter(|id| {
+                let kept = new_ids.peek() == Some(id);
+                if kept {
+                    new_ids.next();
+                }
+                !kept
+            })
+            .map(|id| TrackListChange::Removed(id.clone()))
+            .collect();
+        new_ids.peek().is_none().then_some(removed)
+    }
+}
+
+// The tracks of the current source, in play order
+pub struct SpotMprisTrackList {
+    tracks: Vec<String>,
+    metadata: HashMap<String, TrackMetadata>,
+    api: Arc<dyn SpotifyApiClient + Send + Sync>,
+    sender: UnboundedSender<AppAction>,
+}
+
+impl SpotMprisTrackList {
+    pub fn new(
+        api: Arc<dyn SpotifyApiClient + Send + Sync>,
+        sender: UnboundedSender<AppAction>,
+    ) -> Self {
+        Self {
+            tracks: vec![],
+            metadata: HashMap::new(),
+            api,
+            sender,
+        }
+    }
+
+    fn send(&self, action: AppAction) -> Result<()> {
+        self.sender
+            .unbounded_send(action)
+            .map_err(|_| Error::Failed("Could not send action".to_string()))
+    }
+
+    fn song_id(&self, TrackId: &ObjectPath<'_>) -> Result<String> {
+        self.tracks
+            .iter()
+            .find(|id| id.as_str() == TrackId.as_str())
+            .and_then(|id| song_id_of(id))
+            .map(|id| id.to_string())
+            .ok_or_else(|| Error::InvalidArgs(format!("Unknown track: {TrackId}")))
+    }
+
+    pub async fn set_tracks(
+        &mut self,
+        ctxt: &SignalContext<'_>,
+        tracks: Vec<String>,
+        added: Vec<TrackMetadata>,
+        current: Option<String>,
+    ) -> zbus::Result<()> {
+        let old_ids = std::mem::replace(&mut self.tracks, tracks);
+        let new_ids = self.tracks.clone();
+        let listed: HashSet<&String> = new_ids.iter().collect();
+        self.metadata.retain(|id, _| listed.contains(id));
+        self.metadata
+            .extend(added.into_iter().map(|track| (track.id.clone(), track)));
+
+        match diff(&old_ids, &new_ids) {
+            Some(changes) if changes.is_empty() => return Ok(()),
+            Some(changes) => {
+                for change in changes {
+                    match change {
+                        TrackListChange::Added(i) => {
+                            let after = i
+                                .checked_sub(1)
+                                .map(|i| new_ids[i].as_str())
+                                .unwrap_or(NO_TRACK);
+                            let metadata =
+                                self.metadata.get(&new_ids[i]).cloned().unwrap_or_default();
+                            Self::track_added(ctxt, metadata, object_path(after)).await?;
+                        }
+                        TrackListChange::Removed(id) => {
+                            Self::track_removed(ctxt, object_path(&id)).await?;
+                        }
+                    }
+                }
+            }
+            None => {
+                let current = current
+                    .filter(|id| new_ids.contains(id))
+                    .unwrap_or_else(|| NO_TRACK.to_string());
+                let tracks = new_ids.iter().map(|id| object_path(id)).collect();
+                Self::track_list_replaced(ctxt, tracks, object_path(&current)).await?;
+            }
+        }
+
+        zbus::fdo::Properties::properties_changed(ctxt, Self::name(), &HashMap::new(), &["Tracks"])
+            .await
+    }
+}
+
+#[dbus_interface(interface = "org.mpris.MediaPlayer2.TrackList")]
+impl SpotMprisTrackList {
+    fn get_tracks_metadata(&self, TrackIds: Vec<ObjectPath<'_>>) -> Vec<TrackMetadata> {
+        TrackIds
+            .iter()
+            .filter_map(|id| self.metadata.get(id.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    // Only tracks are supported, and they always end up at the end of the list
+    async fn add_track(
+        &self,
+        Uri: &str,
+        AfterTrack: ObjectPath<'_>,
+        SetAsCurrent: bool,
+    ) -> Result<()> {
+        let id = match SpotifyLink::parse(Uri) {
+            Some(SpotifyLink::Track(id)) => id,
+            _ => return Err(Error::InvalidArgs(format!("Unsupported URI: {Uri}"))),
+        };
+        let song = self
+            .api
+            .get_track(&id)
+            .await
+            .map_err(|_| Error::Failed(format!("Could not load track: {Uri}")))?;
+        let id = song.id.clone();
+        self.send(PlaybackAction::Queue(vec![song]).into())?;
+        if SetAsCurrent {
+            self.send(PlaybackAction::Load(id).into())?;
+        }
+        Ok(())
+    }
+
+    fn go_to(&self, TrackId: ObjectPath<'_>) -> Result<()> {
+        let id = self.song_id(&TrackId)?;
+        self.send(PlaybackAction::Load(id).into())
+    }
+
+    fn remove_track(&self, TrackId: ObjectPath<'_>) -> Result<()> {
+        let id = self.song_id(&TrackId)?;
+        self.send(PlaybackAction::Dequeue(id).into())
+    }
+
+    #[dbus_interface(signal)]
+    async fn track_list_replaced(
+        ctxt: &SignalContext<'_>,
+        Tracks: Vec<ObjectPath<'_>>,
+        CurrentTrack: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn track_added(
+        ctxt: &SignalContext<'_>,
+        Metadata: TrackMetadata,
+        AfterTrack: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn track_removed(ctxt: &SignalContext<'_>, TrackId: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn tracks(&self) -> Vec<OwnedObjectPath> {
+        self.tracks
+            .iter()
+            .map(|id| object_path(id).into())
+            .collect()
+    }
+
+    #[dbus_interface(property)]
+    fn can_edit_tracks(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_diff_unchanged() {
+        assert_eq!(diff(&ids(&["a", "b"]), &ids(&["a", "b"])), Some(vec![]));
+    }
+
+    #[test]
+    fn test_diff_appended() {
+        assert_eq!(
+            diff(&ids(&["a"]), &ids(&["a", "b", "c"])),
+            Some(vec![TrackListChange::Added(1), TrackListChange::Added(2)])
+        );
+    }
+
+    #[test]
+    fn test_diff_removed() {
+        assert_eq!(
+            diff(&ids(&["a", "b", "c", "d"]), &ids(&["a", "c"])),
+            Some(vec![
+                TrackListChange::Removed("b".to_string()),
+                TrackListChange::Removed("d".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_diff_replaced() {
+        assert_eq!(diff(&ids(&["a", "b"]), &ids(&["b", "a"])), None);
+        assert_eq!(diff(&ids(&["a", "b"]), &ids(&["c"])), None);
+        assert_eq!(diff(&ids(&["a", "b"]), &ids(&["c", "a", "b"])), None);
+    }
+}
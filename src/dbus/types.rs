@@ -1,7 +1,11 @@
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
 use std::convert::{Into, TryFrom};
 use std::time::Instant;
 use zvariant::Type;
-use zvariant::{Dict, Signature, Str, Value};
+use zvariant::{Dict, ObjectPath, Signature, Str, StructureBuilder, Value};
+
+use crate::app::SongsSource;
 
 fn boxed_value<'a, V: Into<Value<'a>>>(v: V) -> Value<'a> {
     Value::new(v.into())
@@ -65,6 +69,9 @@ impl From<PlaybackStatus> for Value<'_> {
     }
 }
 
+// Positions synced from a Connect device lag a bit behind (polling, network...)
+const SYNC_TOLERANCE_MICROS: u128 = 1_000_000;
+
 struct PositionMicros {
     last_known_position: u128,
     last_resume_instant: Option<Instant>,
@@ -104,14 +111,52 @@ impl PositionMicros {
     }
 }
 
-#[derive(Debug, Clone)]
+// Tracks are identified by object paths, derived from their Spotify id
+// A track listed more than once gets a suffix for its later occurrences, so that paths stay unique
+const TRACK_ID_PREFIX: &str = "/dev/alextren/Spot/Track/";
+
+pub fn track_object_id(song_id: &str, occurrence: usize) -> String {
+    if occurrence == 0 {
+        format!("{TRACK_ID_PREFIX}{song_id}")
+    } else {
+        format!("{TRACK_ID_PREFIX}{song_id}_{occurrence}")
+    }
+}
+
+// Unique object paths for a list of tracks
+pub fn track_object_ids<'a>(song_ids: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    song_ids
+        .map(|id| {
+            let occurrence = occurrences.entry(id).or_default();
+            let object_id = track_object_id(id, *occurrence);
+            *occurrence += 1;
+            object_id
+        })
+        .collect()
+}
+
+pub fn song_id_of(track_id: &str) -> Option<&str> {
+    track_id
+        .strip_prefix(TRACK_ID_PREFIX)
+        .and_then(|id| id.split('_').next())
+        .filter(|id| !id.is_empty())
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct TrackMetadata {
     pub id: String,
     pub length: u64,
     pub artist: Vec<String>,
     pub album: String,
+    pub album_artist: Vec<String>,
     pub title: String,
+    pub url: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
     pub art: Option<String>,
+    // Only known once fetched
+    pub is_saved: Option<bool>,
 }
 
 impl Type for TrackMetadata {
@@ -120,25 +165,145 @@ impl Type for TrackMetadata {
     }
 }
 
-impl From<TrackMetadata> for Value<'_> {
-    fn from(meta: TrackMetadata) -> Self {
+impl TrackMetadata {
+    fn to_dict(&self) -> Dict<'static, 'static> {
         let mut d = Dict::new(Str::signature(), Value::signature());
-        d.append("mpris:trackid".into(), boxed_value(meta.id))
+        d.append("mpris:trackid".into(), boxed_value(self.id.clone()))
             .unwrap();
-        d.append("mpris:length".into(), boxed_value(meta.length))
+        d.append("mpris:length".into(), boxed_value(self.length))
             .unwrap();
-        d.append("xesam:title".into(), boxed_value(meta.title))
+        d.append("xesam:title".into(), boxed_value(self.title.clone()))
             .unwrap();
-        d.append("xesam:artist".into(), boxed_value(meta.artist.clone()))
+        d.append("xesam:artist".into(), boxed_value(self.artist.clone()))
             .unwrap();
-        d.append("xesam:albumArtist".into(), boxed_value(meta.artist))
+        let album_artist = if self.album_artist.is_empty() {
+            self.artist.clone()
+        } else {
+            self.album_artist.clone()
+        };
+        d.append("xesam:albumArtist".into(), boxed_value(album_artist))
             .unwrap();
-        d.append("xesam:album".into(), boxed_value(meta.album))
+        d.append("xesam:album".into(), boxed_value(self.album.clone()))
             .unwrap();
-        if let Some(art) = meta.art {
+        if let Some(url) = self.url.clone() {
+            d.append("xesam:url".into(), boxed_value(url)).unwrap();
+        }
+        if let Some(track_number) = self.track_number {
+            d.append("xesam:trackNumber".into(), boxed_value(track_number as i32))
+                .unwrap();
+        }
+        if let Some(disc_number) = self.disc_number {
+            d.append("xesam:discNumber".into(), boxed_value(disc_number as i32))
+                .unwrap();
+        }
+        if let Some(art) = self.art.clone() {
             d.append("mpris:artUrl".into(), boxed_value(art)).unwrap();
         }
-        Value::Dict(d)
+        // Saved tracks are "loved"
+        if let Some(is_saved) = self.is_saved {
+            let rating = if is_saved { 1.0 } else { 0.0 };
+            d.append("xesam:userRating".into(), boxed_value(rating))
+                .unwrap();
+        }
+        d
+    }
+}
+
+impl From<TrackMetadata> for Value<'_> {
+    fn from(meta: TrackMetadata) -> Self {
+        Value::Dict(meta.to_dict())
+    }
+}
+
+// So that metadata can also be returned by methods and sent along signals (TrackList)
+impl Serialize for TrackMetadata {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_dict().serialize(serializer)
+    }
+}
+
+const PLAYLIST_ID_PREFIX: &str = "/dev/alextren/Spot/Playlist/";
+const SAVED_TRACKS_ID: &str = "/dev/alextren/Spot/SavedTracks";
+
+// Only playlists and saved tracks are exposed as MPRIS playlists
+pub fn playlist_object_id(source: &SongsSource) -> Option<String> {
+    match source {
+        SongsSource::Playlist(id) => Some(format!("{PLAYLIST_ID_PREFIX}{id}")),
+        SongsSource::SavedTracks => Some(SAVED_TRACKS_ID.to_string()),
+        _ => None,
+    }
+}
+
+pub fn source_of(playlist_id: &str) -> Option<SongsSource> {
+    if playlist_id == SAVED_TRACKS_ID {
+        return Some(SongsSource::SavedTracks);
+    }
+    playlist_id
+        .strip_prefix(PLAYLIST_ID_PREFIX)
+        .filter(|id| !id.is_empty())
+        .map(|id| SongsSource::Playlist(id.to_string()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistMetadata {
+    pub id: String,
+    pub name: String,
+}
+
+impl PlaylistMetadata {
+    // Playlists have no icon
+    const NO_ICON: &'static str = "";
+
+    fn object_path(&self) -> ObjectPath<'static> {
+        ObjectPath::try_from(self.id.clone())
+            .unwrap_or_else(|_| ObjectPath::from_static_str_unchecked("/"))
+    }
+}
+
+impl Type for PlaylistMetadata {
+    fn signature() -> Signature<'static> {
+        Signature::from_str_unchecked("(oss)")
+    }
+}
+
+impl From<PlaylistMetadata> for Value<'_> {
+    fn from(playlist: PlaylistMetadata) -> Self {
+        let s = StructureBuilder::new()
+            .add_field(playlist.object_path())
+            .add_field(playlist.name)
+            .add_field(PlaylistMetadata::NO_ICON)
+            .build();
+        Value::Structure(s)
+    }
+}
+
+impl Serialize for PlaylistMetadata {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.object_path(), &self.name, PlaylistMetadata::NO_ICON).serialize(serializer)
+    }
+}
+
+// The Maybe_Playlist type of the spec
+pub struct MaybePlaylist(pub Option<PlaylistMetadata>);
+
+impl Type for MaybePlaylist {
+    fn signature() -> Signature<'static> {
+        Signature::from_str_unchecked("(b(oss))")
+    }
+}
+
+impl From<MaybePlaylist> for Value<'_> {
+    fn from(MaybePlaylist(playlist): MaybePlaylist) -> Self {
+        let valid = playlist.is_some();
+        let playlist = playlist.unwrap_or_else(|| PlaylistMetadata {
+            id: "/".to_string(),
+            name: String::new(),
+        });
+        let s = StructureBuilder::new()
+            .add_field(valid)
+            .append_field(Value::from(playlist))
+            .build();
+        Value::Structure(s)
     }
 }
 
@@ -213,6 +378,17 @@ impl MprisState {
         self.position.set(0, playing);
     }
 
+    // Replaces the metadata of the current track, if it is still that track
+    pub fn update_current_track(&mut self, track: TrackMetadata) -> bool {
+        match self.metadata.as_mut() {
+            Some(current) if current.id == track.id => {
+                *current = track;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn position(&self) -> u128 {
         self.position.current()
     }
@@ -222,6 +398,13 @@ impl MprisState {
         self.position.set(position, playing);
     }
 
+    // Like set_position, but also tells whether we were far off (that is, if it was a seek)
+    pub fn sync_position(&mut self, position: u128) -> bool {
+        let drift = self.position.current().abs_diff(position);
+        self.set_position(position);
+        drift > SYNC_TOLERANCE_MICROS
+    }
+
     pub fn set_loop_status(&mut self, loop_status: LoopStatus) {
         self.loop_status = loop_status;
     }
@@ -245,3 +428,22 @@ impl MprisState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_object_ids() {
+        let ids = track_object_ids(vec!["a", "b", "a"].into_iter());
+        assert_eq!(
+            ids,
+            vec![
+                "/dev/alextren/Spot/Track/a",
+                "/dev/alextren/Spot/Track/b",
+                "/dev/alextren/Spot/Track/a_1"
+            ]
+        );
+        assert_eq!(song_id_of(&ids[2]), Some("a"));
+    }
+}
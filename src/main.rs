@@ -12,16 +12,18 @@ use gio::prelude::*;
 use gio::ApplicationFlags;
 use gio::SimpleAction;
 use gtk::prelude::*;
+use std::rc::Rc;
 
 mod api;
 mod app;
+mod cli;
 mod config;
 mod connect;
 mod dbus;
 mod player;
 mod settings;
 
-use crate::app::components::expose_custom_widgets;
+use crate::app::components::{expose_custom_widgets, save_playback};
 use crate::app::dispatch::{spawn_task_handler, DispatchLoop};
 use crate::app::{state::PlaybackAction, App, AppAction, BrowserAction};
 
@@ -33,7 +35,10 @@ fn main() {
     // so here goes!
     expose_custom_widgets();
 
-    let gtk_app = gtk::Application::new(Some(config::APPID), ApplicationFlags::HANDLES_OPEN);
+    let gtk_app = gtk::Application::new(
+        Some(config::APPID),
+        ApplicationFlags::HANDLES_OPEN | ApplicationFlags::HANDLES_COMMAND_LINE,
+    );
     let builder = gtk::Builder::from_resource("/dev/alextren/Spot/window.ui");
     let window: libadwaita::ApplicationWindow = builder.object("window").unwrap();
 
@@ -48,8 +53,9 @@ fn main() {
     let dispatch_loop = DispatchLoop::new();
     let sender = dispatch_loop.make_dispatcher();
 
-    // Couple of actions used with shortcuts
+    // Couple of actions used with shortcuts (and from the command line)
     register_actions(&gtk_app, sender.clone());
+    cli::add_options(&gtk_app);
     setup_credits(builder.object::<libadwaita::AboutWindow>("about").unwrap());
 
     // Main app logic is hooked up here
@@ -59,8 +65,18 @@ fn main() {
         sender.clone(),
         spawn_task_handler(&context),
     );
+    let model = app.model();
     context.spawn_local(app.attach(dispatch_loop));
 
+    // Whatever is playing is saved one last time on quit
+    let model_clone = Rc::clone(&model);
+    gtk_app.connect_shutdown(move |_| save_playback(&model_clone));
+
+    let sender_clone = sender.clone();
+    gtk_app.connect_command_line(move |gtk_app, cmdline| {
+        cli::handle(gtk_app, cmdline, &model, &sender_clone)
+    });
+
     let sender_clone = sender.clone();
     gtk_app.connect_activate(move |gtk_app| {
         debug!("activate");
@@ -180,6 +196,20 @@ fn register_actions(app: &gtk::Application, sender: UnboundedSender<AppAction>)
         sender.clone(),
     ));
 
+    app.add_action(&make_action("undo", AppAction::Undo, sender.clone()));
+
+    app.add_action(&{
+        let sender = sender.clone();
+        let action = SimpleAction::new("undo_entry", Some(glib::VariantTy::UINT32));
+        action.set_enabled(true);
+        action.connect_activate(move |_, entry_id| {
+            if let Some(id) = entry_id.and_then(|v| v.get::<u32>()) {
+                sender.unbounded_send(AppAction::UndoEntry(id)).unwrap();
+            }
+        });
+        action
+    });
+
     app.add_action(&{
         let action = SimpleAction::new("open_playlist", Some(glib::VariantTy::STRING));
         action.set_enabled(true);
@@ -1,4 +1,5 @@
 use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use gettextrs::*;
 use librespot::core::spotify_id::SpotifyId;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -25,7 +26,7 @@ pub enum Command {
     PlayerSetVolume(f64),
     PlayerPreload(SpotifyId),
     RefreshToken,
-    ReloadSettings,
+    ReloadSettings(SpotifyPlayerSettings),
 }
 
 struct AppPlayerDelegate {
@@ -102,6 +103,31 @@ impl SpotifyPlayerDelegate for AppPlayerDelegate {
             .unbounded_send(PlaybackAction::Preload.into())
             .unwrap();
     }
+
+    fn track_unavailable(&self, id: String) {
+        self.sender
+            .borrow_mut()
+            .unbounded_send(PlaybackAction::MarkUnavailable(id).into())
+            .unwrap();
+    }
+
+    fn session_lost(&self) {
+        self.sender
+            .borrow_mut()
+            // translators: This notification is shown when the connection to Spotify's servers drops during playback; Spot will try to reconnect on its own.
+            .unbounded_send(AppAction::ShowNotification(gettext(
+                "Connection lost, reconnecting…",
+            )))
+            .unwrap();
+    }
+
+    fn session_restored(&self) {
+        self.sender
+            .borrow_mut()
+            // translators: This notification is shown once Spot managed to reconnect to Spotify's servers after losing the connection.
+            .unbounded_send(AppAction::ShowNotification(gettext("Reconnected")))
+            .unwrap();
+    }
 }
 
 #[tokio::main]
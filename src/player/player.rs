@@ -6,6 +6,7 @@ use librespot::core::cache::Cache;
 use librespot::core::config::SessionConfig;
 use librespot::core::keymaster;
 use librespot::core::session::{Session, SessionError};
+use librespot::core::spotify_id::SpotifyId;
 
 use librespot::playback::mixer::softmixer::SoftMixer;
 use librespot::playback::mixer::{Mixer, MixerConfig};
@@ -20,11 +21,11 @@ use std::env;
 use std::error::Error;
 use std::fmt;
 use std::rc::Rc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
+use url::Url;
 
 use super::Command;
 use crate::app::credentials;
-use crate::settings::SpotSettings;
 
 #[derive(Debug)]
 pub enum SpotifyError {
@@ -57,6 +58,9 @@ pub trait SpotifyPlayerDelegate {
     fn report_error(&self, error: SpotifyError);
     fn notify_playback_state(&self, position: u32);
     fn preload_next_track(&self);
+    fn track_unavailable(&self, id: String);
+    fn session_lost(&self);
+    fn session_restored(&self);
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -64,6 +68,10 @@ pub enum AudioBackend {
     GStreamer(String),
     PulseAudio,
     Alsa(String),
+    // Raw PCM (S16LE, 44.1kHz, stereo) written to a file or FIFO, or to stdout if None
+    Pipe(Option<String>),
+    // Raw PCM written to the standard input of a shell command
+    Subprocess(String),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -72,6 +80,24 @@ pub struct SpotifyPlayerSettings {
     pub backend: AudioBackend,
     pub gapless: bool,
     pub ap_port: Option<u16>,
+    pub proxy: Option<String>,
+    pub proxy_credentials: Option<(String, String)>,
+}
+
+impl SpotifyPlayerSettings {
+    fn proxy_url(&self) -> Option<Url> {
+        let proxy = self.proxy.as_ref()?;
+        let mut url = Url::parse(proxy)
+            .map_err(|e| warn!("Invalid proxy url: {}", e))
+            .ok()?;
+        if let Some((username, password)) = self.proxy_credentials.as_ref() {
+            url.set_username(username)
+                .and_then(|_| url.set_password(Some(password)))
+                .map_err(|_| warn!("Could not set credentials on proxy url {}", url))
+                .ok()?;
+        }
+        Some(url)
+    }
 }
 
 impl Default for SpotifyPlayerSettings {
@@ -81,15 +107,97 @@ impl Default for SpotifyPlayerSettings {
             gapless: true,
             backend: AudioBackend::PulseAudio,
             ap_port: None,
+            proxy: None,
+            proxy_credentials: None,
+        }
+    }
+}
+
+// What the player was last asked to play, so that playback can be picked up again
+// should we have to recreate the player (after a reconnection, for instance)
+#[derive(Default)]
+struct PlaybackSnapshot {
+    track: Option<SpotifyId>,
+    // Last known position in the track (in milliseconds)
+    position_ms: u32,
+    // Set while playing
+    resumed_at: Option<Instant>,
+}
+
+impl PlaybackSnapshot {
+    fn is_playing(&self) -> bool {
+        self.resumed_at.is_some()
+    }
+
+    fn position_ms(&self) -> u32 {
+        let elapsed = self
+            .resumed_at
+            .map(|i| i.elapsed().as_millis() as u32)
+            .unwrap_or(0);
+        self.position_ms.saturating_add(elapsed)
+    }
+
+    fn set(&mut self, position_ms: u32, playing: bool) {
+        self.position_ms = position_ms;
+        self.resumed_at = if playing { Some(Instant::now()) } else { None };
+    }
+
+    fn update_for(&mut self, command: &Command) {
+        match command {
+            Command::PlayerLoad { track, resume } => {
+                self.track = Some(*track);
+                self.set(0, *resume);
+            }
+            Command::PlayerResume if !self.is_playing() => self.set(self.position_ms, true),
+            Command::PlayerPause => self.set(self.position_ms(), false),
+            Command::PlayerSeek(position) => self.set(*position, self.is_playing()),
+            Command::PlayerStop | Command::Logout => *self = Default::default(),
+            _ => {}
+        }
+    }
+}
+
+// Keeps track of our attempts at restoring a session that went away
+struct Reconnection {
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+impl Reconnection {
+    fn new() -> Self {
+        Self {
+            attempts: 0,
+            next_attempt: Instant::now(),
         }
     }
+
+    fn is_due(&self) -> bool {
+        Instant::now() >= self.next_attempt
+    }
+
+    // Exponential backoff: 2s, 4s, 8s... up to 2 minutes between attempts
+    fn backoff(&mut self) {
+        let delay = RECONNECT_INITIAL_DELAY
+            .saturating_mul(1 << self.attempts.min(6))
+            .min(RECONNECT_MAX_DELAY);
+        self.attempts += 1;
+        self.next_attempt = Instant::now() + delay;
+    }
 }
 
+const SESSION_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(2);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(120);
+
 pub struct SpotifyPlayer {
     settings: SpotifyPlayerSettings,
     player: Option<Player>,
     mixer: Option<Box<dyn Mixer>>,
     session: Option<Session>,
+    // Reusable credentials for the current session, used to reconnect
+    credentials: Option<Credentials>,
+    snapshot: Rc<RefCell<PlaybackSnapshot>>,
+    reconnection: Option<Reconnection>,
     delegate: Rc<dyn SpotifyPlayerDelegate>,
 }
 
@@ -100,11 +208,90 @@ impl SpotifyPlayer {
             mixer: None,
             player: None,
             session: None,
+            credentials: None,
+            snapshot: Default::default(),
+            reconnection: None,
             delegate,
         }
     }
 
+    fn session_lost(&self) -> bool {
+        self.session
+            .as_ref()
+            .map(|session| session.is_invalid())
+            .unwrap_or(false)
+    }
+
+    // Called periodically and before handling commands: if librespot dropped our session,
+    // we try to get a new one (with some backoff) and resume playback where we left it
+    async fn check_session(&mut self) {
+        if !self.session_lost() {
+            return;
+        }
+
+        match &self.reconnection {
+            Some(reconnection) if !reconnection.is_due() => return,
+            Some(reconnection) => debug!("Reconnection attempt #{}", reconnection.attempts + 1),
+            None => {
+                warn!("Session lost, reconnecting...");
+                self.delegate.session_lost();
+                let _ = self.player.take();
+                self.reconnection = Some(Reconnection::new());
+            }
+        }
+
+        let credentials = match self.credentials.clone() {
+            Some(credentials) => credentials,
+            None => return,
+        };
+
+        match create_session(&credentials, &self.settings).await {
+            Ok((new_session, credentials)) => {
+                info!("Session restored");
+                self.reconnection = None;
+                self.credentials.replace(credentials);
+                self.set_session(new_session);
+                self.restore_playback();
+                self.delegate.session_restored();
+            }
+            Err(SpotifyError::LoginFailed) => {
+                // Our credentials are no good anymore, no point in retrying
+                self.reconnection = None;
+                self.session = None;
+                self.credentials = None;
+                self.delegate.report_error(SpotifyError::LoginFailed);
+            }
+            Err(err) => {
+                debug!("Reconnection failed: {}", err);
+                if let Some(reconnection) = self.reconnection.as_mut() {
+                    reconnection.backoff();
+                }
+            }
+        }
+    }
+
+    fn set_session(&mut self, session: Session) {
+        let (new_player, channel) = self.create_player(session.clone());
+        tokio::task::spawn_local(player_setup_delegate(
+            channel,
+            Rc::clone(&self.delegate),
+            Rc::clone(&self.snapshot),
+        ));
+        self.player.replace(new_player);
+        self.session.replace(session);
+    }
+
+    fn restore_playback(&mut self) {
+        let snapshot = self.snapshot.borrow();
+        if let (Some(track), Some(player)) = (snapshot.track, self.player.as_mut()) {
+            let position = snapshot.position_ms();
+            info!("Resuming playback at {}ms", position);
+            player.load(track, snapshot.is_playing(), position);
+        }
+    }
+
     async fn handle(&mut self, action: Command) -> Result<(), SpotifyError> {
+        self.snapshot.borrow_mut().update_for(&action);
         match action {
             Command::PlayerSetVolume(volume) => {
                 if let Some(mixer) = self.mixer.as_mut() {
@@ -161,6 +348,8 @@ impl SpotifyPlayer {
                 Ok(())
             }
             Command::Logout => {
+                self.reconnection = None;
+                self.credentials = None;
                 self.session
                     .take()
                     .ok_or(SpotifyError::PlayerNotReady)?
@@ -170,9 +359,11 @@ impl SpotifyPlayer {
             }
             Command::PasswordLogin { username, password } => {
                 let credentials = Credentials::with_password(username, password.clone());
-                let new_session = create_session(&credentials, self.settings.ap_port).await?;
+                let (new_session, credentials) =
+                    create_session(&credentials, &self.settings).await?;
                 let (token, token_expiry_time) =
                     get_access_token_and_expiry_time(&new_session).await?;
+                let reusable_credentials = credentials;
                 let credentials = credentials::Credentials {
                     username: new_session.username(),
                     password,
@@ -182,10 +373,9 @@ impl SpotifyPlayer {
                 };
                 self.delegate.password_login_successful(credentials);
 
-                let (new_player, channel) = self.create_player(new_session.clone());
-                tokio::task::spawn_local(player_setup_delegate(channel, Rc::clone(&self.delegate)));
-                self.player.replace(new_player);
-                self.session.replace(new_session);
+                self.reconnection = None;
+                self.credentials.replace(reusable_credentials);
+                self.set_session(new_session);
 
                 Ok(())
             }
@@ -195,25 +385,24 @@ impl SpotifyPlayer {
                     auth_type: AuthenticationType::AUTHENTICATION_SPOTIFY_TOKEN,
                     auth_data: token.clone().into_bytes(),
                 };
-                let new_session = create_session(&credentials, self.settings.ap_port).await?;
+                let (new_session, credentials) =
+                    create_session(&credentials, &self.settings).await?;
                 self.delegate
                     .token_login_successful(new_session.username(), token);
 
-                let (new_player, channel) = self.create_player(new_session.clone());
-                tokio::task::spawn_local(player_setup_delegate(channel, Rc::clone(&self.delegate)));
-                self.player.replace(new_player);
-                self.session.replace(new_session);
+                self.reconnection = None;
+                self.credentials.replace(credentials);
+                self.set_session(new_session);
 
                 Ok(())
             }
-            Command::ReloadSettings => {
-                let settings = SpotSettings::new_from_gsettings().unwrap_or_default();
-                self.settings = settings.player_settings;
+            Command::ReloadSettings(settings) => {
+                self.settings = settings;
 
-                let session = self.session.take().ok_or(SpotifyError::PlayerNotReady)?;
-                let (new_player, channel) = self.create_player(session);
-                tokio::task::spawn_local(player_setup_delegate(channel, Rc::clone(&self.delegate)));
-                self.player.replace(new_player);
+                // Before logging in, the first session simply uses the new settings
+                if let Some(session) = self.session.take() {
+                    self.set_session(session);
+                }
 
                 Ok(())
             }
@@ -260,20 +449,45 @@ impl SpotifyPlayer {
                 let backend = audio_backend::find(Some("alsa".to_string())).unwrap();
                 backend(Some(device), AudioFormat::default())
             }
+            AudioBackend::Pipe(path) => {
+                info!("using pipe ({})", path.as_deref().unwrap_or("stdout"));
+                let backend = audio_backend::find(Some("pipe".to_string())).unwrap();
+                backend(path, AudioFormat::default())
+            }
+            AudioBackend::Subprocess(command) => {
+                info!("using subprocess ({})", &command);
+                let backend = audio_backend::find(Some("subprocess".to_string())).unwrap();
+                backend(Some(command), AudioFormat::default())
+            }
         })
     }
 
-    pub async fn start(self, receiver: UnboundedReceiver<Command>) -> Result<(), ()> {
-        let _self = RefCell::new(self);
-        receiver
-            .for_each(|action| async {
-                let mut _self = _self.borrow_mut();
-                match _self.handle(action).await {
-                    Ok(_) => {}
-                    Err(err) => _self.delegate.report_error(err),
+    pub async fn start(mut self, mut receiver: UnboundedReceiver<Command>) -> Result<(), ()> {
+        let mut session_check = tokio::time::interval(SESSION_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                action = receiver.next() => {
+                    let action = match action {
+                        Some(action) => action,
+                        None => break,
+                    };
+                    let is_login = matches!(
+                        action,
+                        Command::PasswordLogin { .. } | Command::TokenLogin { .. } | Command::Logout
+                    );
+                    if !is_login {
+                        self.check_session().await;
+                    }
+                    match self.handle(action).await {
+                        // The command was still recorded and will apply once we're reconnected
+                        Err(SpotifyError::PlayerNotReady) if self.reconnection.is_some() => {}
+                        Err(err) => self.delegate.report_error(err),
+                        Ok(_) => {}
+                    }
                 }
-            })
-            .await;
+                _ = session_check.tick() => self.check_session().await,
+            }
+        }
         Ok(())
     }
 }
@@ -309,9 +523,11 @@ async fn get_access_token_and_expiry_time(
 async fn create_session_with_port(
     credentials: &Credentials,
     ap_port: Option<u16>,
-) -> Result<Session, SpotifyError> {
+    proxy: Option<Url>,
+) -> Result<(Session, Credentials), SpotifyError> {
     let session_config = SessionConfig {
         ap_port,
+        proxy,
         ..Default::default()
     };
     let root = glib::user_cache_dir().join("spot").join("librespot");
@@ -324,7 +540,7 @@ async fn create_session_with_port(
     .map_err(|e| dbg!(e))
     .ok();
     match Session::connect(session_config, credentials.clone(), cache, true).await {
-        Ok(r) => Ok(r.0),
+        Ok(r) => Ok(r),
         Err(SessionError::IoError(_)) => Err(SpotifyError::TechnicalError),
         Err(SessionError::AuthenticationError(err)) => {
             warn!("Login failure: {}", err);
@@ -333,17 +549,21 @@ async fn create_session_with_port(
     }
 }
 
+// Returns the new session along with reusable credentials
 async fn create_session(
     credentials: &Credentials,
-    ap_port: Option<u16>,
-) -> Result<Session, SpotifyError> {
+    settings: &SpotifyPlayerSettings,
+) -> Result<(Session, Credentials), SpotifyError> {
+    let ap_port = settings.ap_port;
+    let proxy = settings.proxy_url();
     match ap_port {
-        Some(_) => create_session_with_port(credentials, ap_port).await,
+        Some(_) => create_session_with_port(credentials, ap_port, proxy).await,
         None => {
             let mut ports_to_try = KNOWN_AP_PORTS.iter();
             loop {
                 if let Some(next_port) = ports_to_try.next() {
-                    let res = create_session_with_port(credentials, *next_port).await;
+                    let res =
+                        create_session_with_port(credentials, *next_port, proxy.clone()).await;
                     match res {
                         Err(SpotifyError::TechnicalError) => continue,
                         _ => break res,
@@ -359,6 +579,7 @@ async fn create_session(
 async fn player_setup_delegate(
     mut channel: PlayerEventChannel,
     delegate: Rc<dyn SpotifyPlayerDelegate>,
+    snapshot: Rc<RefCell<PlaybackSnapshot>>,
 ) {
     while let Some(event) = channel.recv().await {
         match event {
@@ -366,8 +587,16 @@ async fn player_setup_delegate(
                 delegate.end_of_track_reached();
             }
             PlayerEvent::Playing { position_ms, .. } => {
+                snapshot.borrow_mut().set(position_ms, true);
                 delegate.notify_playback_state(position_ms);
             }
+            PlayerEvent::Paused { position_ms, .. } => {
+                snapshot.borrow_mut().set(position_ms, false);
+            }
+            PlayerEvent::Unavailable { track_id, .. } => {
+                warn!("Track unavailable: {:?}", track_id);
+                delegate.track_unavailable(track_id.to_base62());
+            }
             PlayerEvent::TimeToPreloadNextTrack { .. } => {
                 debug!("Requestiong next track to be preloaded...");
                 delegate.preload_next_track();
@@ -1,9 +1,41 @@
+use crate::app::credentials::ProxyPassword;
 use crate::player::{AudioBackend, SpotifyPlayerSettings};
-use gio::prelude::SettingsExt;
+use gio::prelude::{ProxyResolverExt, SettingsExt};
 use libadwaita::ColorScheme;
 use librespot::playback::config::Bitrate;
 
 const SETTINGS: &str = "dev.alextren.Spot";
+const CONNECT_KEEP_PLAYING: &str = "connect-keep-playing";
+const BALANCED_SHUFFLE: &str = "balanced-shuffle";
+
+// Whether playback should carry on when transferred to another Connect device
+pub fn connect_keep_playing() -> bool {
+    gio::Settings::new(SETTINGS).boolean(CONNECT_KEEP_PLAYING)
+}
+
+// A stateful action to toggle the above
+pub fn connect_keep_playing_action() -> gio::Action {
+    gio::Settings::new(SETTINGS).create_action(CONNECT_KEEP_PLAYING)
+}
+
+// A stateful action to toggle balanced shuffle
+pub fn balanced_shuffle_action() -> gio::Action {
+    gio::Settings::new(SETTINGS).create_action(BALANCED_SHUFFLE)
+}
+
+// Calls f with whether shuffle should be balanced, then again whenever that changes
+// The returned settings must be kept around for that to work
+pub fn watch_balanced_shuffle<F>(f: F) -> gio::Settings
+where
+    F: Fn(bool) + 'static,
+{
+    let settings = gio::Settings::new(SETTINGS);
+    f(settings.boolean(BALANCED_SHUFFLE));
+    settings.connect_changed(Some(BALANCED_SHUFFLE), move |settings, key| {
+        f(settings.boolean(key))
+    });
+    settings
+}
 
 #[derive(Clone, Debug, Default)]
 pub struct WindowGeometry {
@@ -35,6 +67,99 @@ impl WindowGeometry {
     }
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProxySettings {
+    // Empty if we should rely on the system settings
+    pub url: String,
+    pub username: String,
+    // Empty until retrieved from the keyring
+    pub password: String,
+}
+
+// The proxies our clients should go through, once the system and the keyring were asked
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResolvedProxy {
+    // For the Web API and images: isahc handles HTTP and SOCKS proxies alike
+    pub http: Option<String>,
+    // For librespot, which can only go through HTTP proxies (with CONNECT)
+    pub librespot: Option<String>,
+    pub credentials: Option<(String, String)>,
+}
+
+impl ProxySettings {
+    // Doesn't include the password, see retrieve()
+    pub fn new_from_gsettings() -> Self {
+        let settings = gio::Settings::new(SETTINGS);
+        Self {
+            url: settings.string("proxy-url").trim().to_string(),
+            username: settings.string("proxy-username").as_str().to_string(),
+            password: String::new(),
+        }
+    }
+
+    // The settings along with the password, which lives in the keyring
+    pub async fn retrieve() -> Self {
+        let mut settings = Self::new_from_gsettings();
+        // Only bother the keyring if there is a username
+        if !settings.username.is_empty() {
+            settings.password = ProxyPassword::retrieve()
+                .await
+                .map_err(|e| warn!("Could not retrieve proxy password: {}", e))
+                .unwrap_or_default();
+        }
+        settings
+    }
+
+    // The proxy to use to reach a given uri, if any: either the one set in our settings,
+    // or whatever the system is configured with (as told by GIO)
+    pub async fn proxy_for(&self, uri: &str) -> Option<String> {
+        if !self.url.is_empty() {
+            return Some(self.url.clone());
+        }
+        gio::ProxyResolver::default()
+            .lookup_future(uri)
+            .await
+            .map_err(|e| warn!("Could not resolve system proxy: {}", e))
+            .ok()?
+            .into_iter()
+            .map(|proxy| proxy.to_string())
+            .find(|proxy| proxy != "direct://")
+    }
+
+    pub fn credentials(&self) -> Option<(&str, &str)> {
+        if self.username.is_empty() {
+            None
+        } else {
+            Some((&self.username, &self.password))
+        }
+    }
+
+    pub async fn resolve(&self) -> ResolvedProxy {
+        let http = self.proxy_for("https://api.spotify.com").await;
+        let librespot = self
+            .proxy_for("https://apresolve.spotify.com")
+            .await
+            .filter(|proxy| {
+                let supported = proxy.starts_with("http://");
+                if !supported {
+                    warn!(
+                        "Unsupported proxy {} for playback, only http:// proxies can be used",
+                        proxy
+                    );
+                }
+                supported
+            });
+        let credentials = self
+            .credentials()
+            .map(|(username, password)| (username.to_string(), password.to_string()));
+        ResolvedProxy {
+            http,
+            librespot,
+            credentials,
+        }
+    }
+}
+
 // Player (librespot) settings
 impl SpotifyPlayerSettings {
     pub fn new_from_gsettings() -> Option<Self> {
@@ -53,6 +178,17 @@ impl SpotifyPlayerSettings {
             2 => Some(AudioBackend::GStreamer(
                 "audioconvert dithering=none ! audioresample ! pipewiresink".to_string(), // This should be configurable eventually
             )),
+            3 => {
+                let path = settings.string("pipe-path").as_str().to_string();
+                Some(AudioBackend::Pipe(Some(path).filter(|p| !p.is_empty())))
+            }
+            4 => match settings.string("subprocess-command").as_str() {
+                "" => {
+                    warn!("No command set for the subprocess audio backend, using PulseAudio");
+                    Some(AudioBackend::PulseAudio)
+                }
+                command => Some(AudioBackend::Subprocess(command.to_string())),
+            },
             _ => None,
         }?;
         let gapless = settings.boolean("gapless-playback");
@@ -70,11 +206,14 @@ impl SpotifyPlayerSettings {
             x => Some(x as u16),
         };
 
+        // The proxy can only be resolved asynchronously, see SpotSettings::with_proxy
         Some(Self {
             bitrate,
             backend,
             gapless,
             ap_port,
+            proxy: None,
+            proxy_credentials: None,
         })
     }
 }
@@ -102,6 +241,12 @@ impl SpotSettings {
             window: WindowGeometry::new_from_gsettings(),
         })
     }
+
+    pub fn with_proxy(mut self, proxy: &ResolvedProxy) -> Self {
+        self.player_settings.proxy = proxy.librespot.clone();
+        self.player_settings.proxy_credentials = proxy.credentials.clone();
+        self
+    }
 }
 
 impl Default for SpotSettings {